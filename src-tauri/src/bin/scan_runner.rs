@@ -0,0 +1,207 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use mylib::{
+    handlers::query_runner::{run_scan, ScanSpec},
+    proxies::{
+        credentials,
+        query_runner::{ProgressMessage, ResultPerSchema, RunResults},
+        scan_progress::ProgressSink,
+        sql_common::SQLResult,
+    },
+};
+
+const USAGE: &str = "usage: scan_runner <spec.yaml|spec.json> [--report <report.json>]
+the passphrase of a protected credential store is read from SCAN_RUNNER_PASSPHRASE";
+
+/// The env var holding the passphrase of the credential store, when it's protected by one.
+const PASSPHRASE_ENV: &str = "SCAN_RUNNER_PASSPHRASE";
+
+/// Exit code of a scan which had errors or diffs.
+const EXIT_FAILED: i32 = 1;
+/// Exit code of a scan which couldn't run, e.g. for a bad spec.
+const EXIT_INVALID: i32 = 2;
+
+/// Prints the progress of the scan to stderr, so the summary on stdout stays clean.
+struct StderrSink;
+
+impl ProgressSink for StderrSink {
+    fn progress(&self, progress: &ProgressMessage) {
+        eprintln!(
+            "[{}] query {}: {}/{} finished",
+            progress.schema(),
+            progress.index() + 1,
+            progress.finished(),
+            progress.total()
+        );
+    }
+
+    fn schema_result(&self, schema: &str, _result: &ResultPerSchema) {
+        eprintln!("[{}] done", schema);
+    }
+}
+
+struct Args {
+    spec: PathBuf,
+    report: PathBuf,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = env::args().skip(1);
+    let mut spec = None;
+    let mut report = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--report" => {
+                report = Some(PathBuf::from(
+                    args.next()
+                        .ok_or_else(|| anyhow!("--report requires a path."))?,
+                ))
+            }
+            _ if spec.is_none() => spec = Some(PathBuf::from(arg)),
+            _ => return Err(anyhow!("unexpected argument {}.", arg)),
+        }
+    }
+
+    let spec: PathBuf = spec.ok_or_else(|| anyhow!("the scan spec is required."))?;
+    let report = report.unwrap_or_else(|| spec.with_extension("report.json"));
+    Ok(Args { spec, report })
+}
+
+fn read_spec(path: &Path) -> Result<ScanSpec> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| anyhow!("failed to read the spec {:?}: {}", path, e))?;
+    ScanSpec::parse(&text).map_err(|e| anyhow!("invalid spec {:?}: {}", path, e))
+}
+
+/// Finishes an interrupted key rotation like the app does on start, then unlocks the credential
+/// store with the passphrase of the env var, if it's set.
+fn unlock_credentials() -> Result<()> {
+    credentials::recover_key_rotation()?;
+    let passphrase = match env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) => passphrase,
+        Err(_) => return Ok(()),
+    };
+    if !credentials::unlock(&passphrase)? {
+        return Err(anyhow!("the passphrase of {} is wrong.", PASSPHRASE_ENV));
+    }
+
+    Ok(())
+}
+
+/// The diff status of the results of a schema or of a comparison group, `matched` when the
+/// results didn't differ.
+fn describe_diff(schema_result: &ResultPerSchema, matched: &str) -> String {
+    let diff = match schema_result.diff_summary() {
+        Some(summary) => format!(
+            "{}/{} sets mismatched",
+            summary.mismatching(),
+            summary.compared()
+        ),
+        None if schema_result.diff_results().is_some() || schema_result.row_diffs().is_some() => {
+            "mismatched".to_string()
+        }
+        None => matched.to_string(),
+    };
+    if schema_result.cancelled() {
+        format!("{} (cancelled)", diff)
+    } else {
+        diff
+    }
+}
+
+/// A row per schema: the queries, the failed queries, the rows and the diff status, then a line
+/// per comparison group.
+fn print_summary(results: &RunResults) {
+    let mut schemas: Vec<_> = results.schema_results().iter().collect();
+    schemas.sort_by(|a, b| a.0.cmp(b.0));
+    let width = schemas
+        .iter()
+        .map(|(schema, _)| schema.len())
+        .chain(std::iter::once("SCHEMA".len()))
+        .max()
+        .unwrap_or_default();
+
+    println!(
+        "{:<width$}  {:>7}  {:>6}  {:>8}  DIFF",
+        "SCHEMA",
+        "QUERIES",
+        "ERRORS",
+        "ROWS",
+        width = width
+    );
+    for (schema, schema_result) in schemas {
+        let query_results: Vec<_> = schema_result.query_results().iter().flatten().collect();
+        let errors = query_results
+            .iter()
+            .filter(|qr| matches!(qr.results(), Some(SQLResult::Error(_))))
+            .count();
+        let rows: usize = query_results
+            .iter()
+            .filter_map(|qr| qr.results().and_then(SQLResult::result_set))
+            .map(|rs| rs.row_count())
+            .sum();
+        let diff = describe_diff(schema_result, "-");
+
+        println!(
+            "{:<width$}  {:>7}  {:>6}  {:>8}  {}",
+            schema,
+            query_results.len(),
+            errors,
+            rows,
+            diff,
+            width = width
+        );
+    }
+
+    for comparison in results.comparison_results() {
+        println!(
+            "compare {}: {}",
+            comparison.group().label(),
+            describe_diff(comparison.result(), "matched")
+        );
+    }
+}
+
+fn run(args: &Args) -> Result<bool> {
+    let inputs = read_spec(&args.spec)?.into_inputs()?;
+    unlock_credentials()?;
+    let run = run_scan(Arc::new(StderrSink), None, inputs, None);
+
+    print_summary(run.results());
+    fs::write(&args.report, serde_json::to_string_pretty(&run)?)
+        .map_err(|e| anyhow!("failed to write the report {:?}: {}", args.report, e))?;
+    println!("run {} reported to {:?}", run.run_id(), args.report);
+
+    let results = run.results();
+    Ok(!results.has_error() && !results.has_diff() && !results.cancelled())
+}
+
+/// Runs a scan spec without the app, e.g. for the nightly parity checks of the databases. Exits
+/// with a non-zero code when any query failed or any results differed.
+fn main() {
+    if let Err(e) = mylib::core::log::setup_logger() {
+        eprintln!("logger setup failed: {}", e);
+    }
+
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(EXIT_INVALID);
+        }
+    };
+    match run(&args) {
+        Ok(true) => {}
+        Ok(false) => process::exit(EXIT_FAILED),
+        Err(e) => {
+            eprintln!("scan failed: {}", e);
+            process::exit(EXIT_INVALID);
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How the cell values of different databases are made comparable, by default the values
+/// which only differ by how Oracle and Postgres represent them are equivalent.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EquivalenceRules {
+    /// Numeric text equals the same number, e.g. an Oracle NUMBER with decimals read as text.
+    /// Only applies against a number, two texts are still compared as text.
+    pub numeric_strings: bool,
+    /// Booleans equal 1 and 0, as Oracle has no boolean column type.
+    pub bool_as_number: bool,
+    /// An empty string equals NULL, as Oracle stores empty strings as NULL.
+    pub empty_string_as_null: bool,
+    /// The trailing spaces of the text are ignored, as Oracle pads CHAR columns.
+    pub ignore_trailing_spaces: bool,
+    /// The digits of the fractional seconds compared, all of them when absent.
+    pub timestamp_precision: Option<u32>,
+    /// The max absolute difference of two numbers which are still equal.
+    pub float_tolerance: Option<f64>,
+    /// The max difference of two timestamps in milliseconds which are still equal.
+    pub timestamp_tolerance_ms: Option<i64>,
+}
+
+impl Default for EquivalenceRules {
+    fn default() -> Self {
+        Self {
+            numeric_strings: true,
+            bool_as_number: true,
+            empty_string_as_null: true,
+            ignore_trailing_spaces: false,
+            timestamp_precision: None,
+            float_tolerance: None,
+            timestamp_tolerance_ms: None,
+        }
+    }
+}
+
+/// A cell value in a form which is the same for all the databases.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CanonicalValue {
+    Null,
+    Bool(bool),
+    /// The significant digits and the position of the decimal point, e.g. `15e1` for 1.50.
+    Decimal(String),
+    /// In UTC, the timestamps without a time zone are taken as UTC.
+    Timestamp(NaiveDateTime),
+    Text(String),
+    /// Arrays and objects, compared as they are.
+    Other(Value),
+}
+
+impl CanonicalValue {
+    pub fn new(value: &Value, rules: &EquivalenceRules) -> Self {
+        match value {
+            Value::Null => CanonicalValue::Null,
+            Value::Bool(b) if rules.bool_as_number => {
+                CanonicalValue::Decimal(if *b { "1e1" } else { "0" }.to_string())
+            }
+            Value::Bool(b) => CanonicalValue::Bool(*b),
+            Value::Number(n) => normalize_decimal(&n.to_string()).map_or_else(
+                || CanonicalValue::Other(value.clone()),
+                CanonicalValue::Decimal,
+            ),
+            Value::String(s) => Self::from_text(s, rules),
+            other => CanonicalValue::Other(other.clone()),
+        }
+    }
+
+    /// The value compared to the other value, the numeric text is taken as a number when the
+    /// other value is a number.
+    fn compared_to(value: &Value, other: &Value, rules: &EquivalenceRules) -> Self {
+        let is_number = match other {
+            Value::Number(_) => true,
+            Value::Bool(_) => rules.bool_as_number,
+            _ => false,
+        };
+        match value {
+            Value::String(s) if rules.numeric_strings && is_number => {
+                let text = if rules.ignore_trailing_spaces {
+                    s.trim_end_matches(' ')
+                } else {
+                    s
+                };
+                normalize_decimal(text)
+                    .map_or_else(|| Self::from_text(s, rules), CanonicalValue::Decimal)
+            }
+            _ => Self::new(value, rules),
+        }
+    }
+
+    fn from_text(text: &str, rules: &EquivalenceRules) -> Self {
+        let text = if rules.ignore_trailing_spaces {
+            text.trim_end_matches(' ')
+        } else {
+            text
+        };
+        if text.is_empty() && rules.empty_string_as_null {
+            return CanonicalValue::Null;
+        }
+        match parse_timestamp(text) {
+            Some(ts) => CanonicalValue::Timestamp(truncate_nanos(ts, rules.timestamp_precision)),
+            None => CanonicalValue::Text(text.to_string()),
+        }
+    }
+
+    /// The text of the value used to match the rows by key.
+    pub fn key_text(&self) -> String {
+        match self {
+            CanonicalValue::Null => "\u{0}".to_string(),
+            CanonicalValue::Bool(b) => b.to_string(),
+            CanonicalValue::Decimal(d) => plain_decimal(d),
+            CanonicalValue::Timestamp(ts) => ts.to_string(),
+            CanonicalValue::Text(s) => s.clone(),
+            CanonicalValue::Other(v) => v.to_string(),
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            CanonicalValue::Decimal(d) => {
+                let (sign, d) = match d.strip_prefix('-') {
+                    Some(unsigned) => ("-", unsigned),
+                    None => ("", d.as_str()),
+                };
+                match d.split_once('e') {
+                    Some((digits, point)) => format!("{}0.{}e{}", sign, digits, point).parse().ok(),
+                    None => Some(0.0),
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl EquivalenceRules {
+    pub fn equivalent(&self, left: &Value, right: &Value) -> bool {
+        if left == right {
+            return true;
+        }
+
+        let (left, right) = (
+            CanonicalValue::compared_to(left, right, self),
+            CanonicalValue::compared_to(right, left, self),
+        );
+        if left == right {
+            return true;
+        }
+
+        match (
+            &left,
+            &right,
+            self.float_tolerance,
+            self.timestamp_tolerance_ms,
+        ) {
+            (CanonicalValue::Decimal(_), CanonicalValue::Decimal(_), Some(tolerance), _) => {
+                match (left.as_f64(), right.as_f64()) {
+                    (Some(l), Some(r)) => (l - r).abs() <= tolerance,
+                    _ => false,
+                }
+            }
+            (CanonicalValue::Timestamp(l), CanonicalValue::Timestamp(r), _, Some(tolerance)) => {
+                (*l - *r).num_milliseconds().abs() <= tolerance
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Normalizes the text of a decimal number into its significant digits followed by `e` and the
+/// position of the decimal point, zero is `0`. None if the text isn't a decimal number.
+fn normalize_decimal(text: &str) -> Option<String> {
+    let text = text.trim();
+    let (negative, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+        Some(i) => (&unsigned[..i], unsigned[i + 1..].parse::<i64>().ok()?),
+        None => (unsigned, 0),
+    };
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if int_part.is_empty() && frac_part.is_empty() {
+        return None;
+    }
+    if !int_part
+        .chars()
+        .chain(frac_part.chars())
+        .all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let digits = format!("{}{}", int_part, frac_part);
+    let significant = digits.trim_start_matches('0');
+    let point = int_part.len() as i64 + exponent - (digits.len() - significant.len()) as i64;
+    let significant = significant.trim_end_matches('0');
+    if significant.is_empty() {
+        return Some("0".to_string());
+    }
+
+    Some(format!(
+        "{}{}e{}",
+        if negative { "-" } else { "" },
+        significant,
+        point
+    ))
+}
+
+/// Renders a normalized decimal as plain text, e.g. `1.5` for `15e1`, so a number key matches
+/// the same number read as text.
+fn plain_decimal(decimal: &str) -> String {
+    let (sign, unsigned) = match decimal.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", decimal),
+    };
+    let (digits, point) = match unsigned.split_once('e') {
+        Some((digits, point)) => (digits, point.parse::<i64>().unwrap_or_default()),
+        None => return "0".to_string(),
+    };
+    let len = digits.len() as i64;
+    let text = if point <= 0 {
+        format!("0.{}{}", "0".repeat(-point as usize), digits)
+    } else if point >= len {
+        format!("{}{}", digits, "0".repeat((point - len) as usize))
+    } else {
+        let (int_part, frac_part) = digits.split_at(point as usize);
+        format!("{}.{}", int_part, frac_part)
+    };
+
+    format!("{}{}", sign, text)
+}
+
+/// Parses the timestamps and dates as formatted by the result mappers of both databases, a
+/// date is the timestamp at midnight.
+pub fn parse_timestamp(text: &str) -> Option<NaiveDateTime> {
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(text) {
+        return Some(date_time.naive_utc());
+    }
+    if let Ok(date_time) = DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f %:z") {
+        return Some(date_time.naive_utc());
+    }
+
+    let naive = text.strip_suffix(" UTC").unwrap_or(text);
+    if let Ok(date_time) = NaiveDateTime::parse_from_str(naive, "%Y-%m-%d %H:%M:%S%.f") {
+        return Some(date_time);
+    }
+    NaiveDate::parse_from_str(naive, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+}
+
+fn truncate_nanos(ts: NaiveDateTime, precision: Option<u32>) -> NaiveDateTime {
+    let unit = match precision {
+        Some(digits) if digits < 9 => 10u32.pow(9 - digits),
+        _ => return ts,
+    };
+    ts.with_nanosecond(ts.nanosecond() / unit * unit)
+        .unwrap_or(ts)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_normalize_decimal() {
+        assert_eq!(normalize_decimal("1.50"), Some("15e1".to_string()));
+        assert_eq!(normalize_decimal(".15e1"), Some("15e1".to_string()));
+        assert_eq!(normalize_decimal("-0012"), Some("-12e2".to_string()));
+        assert_eq!(normalize_decimal("0.000"), Some("0".to_string()));
+        assert_eq!(normalize_decimal("1e20"), Some("1e21".to_string()));
+        assert_eq!(normalize_decimal("GREENCO"), None);
+        assert_eq!(normalize_decimal("."), None);
+    }
+
+    #[test]
+    fn test_key_text() {
+        let rules = EquivalenceRules::default();
+        let key = |value: Value| CanonicalValue::new(&value, &rules).key_text();
+        assert_eq!(key(json!(1.5)), "1.5");
+        assert_eq!(key(json!(-0.05)), "-0.05");
+        assert_eq!(key(json!(1200)), "1200");
+        assert_eq!(key(json!(0)), "0");
+        assert_eq!(key(json!(7)), key(json!("7")));
+        assert_ne!(key(json!("007")), key(json!("7")));
+    }
+
+    #[test]
+    fn test_equivalent_across_databases() {
+        let rules = EquivalenceRules::default();
+        assert!(rules.equivalent(&json!(1), &json!("1.00")));
+        assert!(rules.equivalent(&json!(true), &json!(1)));
+        assert!(rules.equivalent(&json!(""), &Value::Null));
+        assert!(rules.equivalent(&json!("2022-01-30"), &json!("2022-01-30 00:00:00")));
+        assert!(!rules.equivalent(&json!(1.5), &json!(1.51)));
+        assert!(!rules.equivalent(&json!("a"), &json!("b")));
+        assert!(!rules.equivalent(&json!("007"), &json!("7")));
+        assert!(!rules.equivalent(&json!("1.0"), &json!("1")));
+        assert!(rules.equivalent(&json!(7), &json!("007")));
+
+        let strict = EquivalenceRules {
+            numeric_strings: false,
+            bool_as_number: false,
+            empty_string_as_null: false,
+            ..Default::default()
+        };
+        assert!(!strict.equivalent(&json!(1), &json!("1")));
+        assert!(!strict.equivalent(&json!(true), &json!(1)));
+        assert!(!strict.equivalent(&json!(""), &Value::Null));
+    }
+
+    #[test]
+    fn test_timestamp_precision_and_tolerance() {
+        let rules = EquivalenceRules {
+            timestamp_precision: Some(3),
+            ..Default::default()
+        };
+        assert!(rules.equivalent(
+            &json!("2022-01-30 12:00:00.123456"),
+            &json!("2022-01-30 12:00:00.123 UTC")
+        ));
+        assert!(!rules.equivalent(
+            &json!("2022-01-30 12:00:00.123"),
+            &json!("2022-01-30 12:00:00.124")
+        ));
+
+        let rules = EquivalenceRules {
+            float_tolerance: Some(0.01),
+            timestamp_tolerance_ms: Some(500),
+            ..Default::default()
+        };
+        assert!(rules.equivalent(&json!(1.5), &json!("1.505")));
+        assert!(rules.equivalent(&json!(-1.5), &json!("-1.505")));
+        assert!(rules.equivalent(
+            &json!("2022-01-30 12:00:00 +08:00"),
+            &json!("2022-01-30 04:00:00.400 UTC")
+        ));
+    }
+}
@@ -1,10 +1,14 @@
+pub mod canonical_value;
 pub mod db_schema_processor;
 pub mod formatter;
 pub mod java_props;
 pub mod log;
 pub mod oracle_param_mapper;
+pub mod param_source;
 pub mod parameter_iterator;
 pub mod postgres_param_mapper;
+pub mod postgres_types;
 pub mod query_scanner;
 pub mod result_diff;
+pub mod result_export;
 pub mod xml_parser;
@@ -0,0 +1,335 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+
+use crate::proxies::sql_common::{SQLResultSet, MAX_FETCH_ROWS};
+
+/// The max number of values generated by a single source.
+const MAX_SOURCE_VALUES: usize = 100000;
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The values of a parameter, given as a literal array or declared as a source.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ParameterSeed {
+    Values(Vec<Value>),
+    Source(ParameterSource),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "source", rename_all = "camelCase")]
+pub enum ParameterSource {
+    /// The first column of a query run against the scanned schema.
+    Query { statement: String },
+    /// A column of a CSV file with a header row, or of a JSON array of values, arrays or
+    /// objects. The first column is used when absent.
+    File {
+        path: String,
+        column: Option<String>,
+    },
+    /// The numbers from start to end inclusive, the step defaults to 1.
+    NumberRange {
+        start: f64,
+        end: f64,
+        step: Option<f64>,
+    },
+    /// The `YYYY-MM-DD` dates from start to end inclusive, the step defaults to a day.
+    #[serde(rename_all = "camelCase")]
+    DateRange {
+        start: String,
+        end: String,
+        step_days: Option<i64>,
+    },
+}
+
+impl ParameterSeed {
+    pub fn values(&self) -> Option<&[Value]> {
+        match self {
+            ParameterSeed::Values(values) => Some(values),
+            ParameterSeed::Source(_) => None,
+        }
+    }
+
+    /// The statement of a seed read from the database, it's run by the caller.
+    pub fn query(&self) -> Option<&str> {
+        match self {
+            ParameterSeed::Source(ParameterSource::Query { statement }) => Some(statement),
+            _ => None,
+        }
+    }
+
+    /// Resolves the values of the seeds which don't need a database.
+    pub fn resolve(&self) -> Result<Vec<Value>> {
+        match self {
+            ParameterSeed::Values(values) => Ok(values.clone()),
+            ParameterSeed::Source(ParameterSource::Query { .. }) => Err(anyhow!(
+                "The query parameter source has to be run against a schema."
+            )),
+            ParameterSeed::Source(ParameterSource::File { path, column }) => {
+                read_file_column(Path::new(path), column.as_deref())
+            }
+            ParameterSeed::Source(ParameterSource::NumberRange { start, end, step }) => {
+                number_range(*start, *end, step.unwrap_or(1.0))
+            }
+            ParameterSeed::Source(ParameterSource::DateRange {
+                start,
+                end,
+                step_days,
+            }) => date_range(start, end, step_days.unwrap_or(1)),
+        }
+    }
+}
+
+/// The first column of the rows of a seed query, which fails rather than scanning only part of
+/// the values when the rows were cut off by the fetch cap.
+pub fn first_column_values(result_set: SQLResultSet) -> Result<Vec<Value>> {
+    if result_set.truncated() {
+        return Err(anyhow!(
+            "The parameter source query returned more than {} rows.",
+            MAX_FETCH_ROWS
+        ));
+    }
+    let rows = result_set
+        .get_rows()
+        .as_ref()
+        .ok_or_else(|| anyhow!("The parameter source query returned no rows."))?;
+
+    Ok(rows
+        .iter()
+        .map(|row| row.get(0).cloned().unwrap_or(Value::Null))
+        .collect())
+}
+
+fn read_file_column(path: &Path, column: Option<&str>) -> Result<Vec<Value>> {
+    let is_json = path
+        .extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("json"));
+    let values = if is_json {
+        let content: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        json_column(&content, column)?
+    } else {
+        csv_column(path, column)?
+    };
+
+    if values.len() > MAX_SOURCE_VALUES {
+        return Err(anyhow!(
+            "The parameter source file {:?} has more than {} values.",
+            path,
+            MAX_SOURCE_VALUES
+        ));
+    }
+
+    Ok(values)
+}
+
+fn csv_column(path: &Path, column: Option<&str>) -> Result<Vec<Value>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let index = match column {
+        Some(name) => reader
+            .headers()?
+            .iter()
+            .position(|header| header.trim() == name)
+            .ok_or_else(|| anyhow!("The column {} is not found in {:?}.", name, path))?,
+        None => 0,
+    };
+
+    let mut values = Vec::new();
+    for record in reader.records() {
+        values.push(parse_cell(record?.get(index).unwrap_or_default()));
+    }
+
+    Ok(values)
+}
+
+/// Reads the cells as numbers where possible, an empty cell is a null.
+fn parse_cell(cell: &str) -> Value {
+    let cell = cell.trim();
+    if cell.is_empty() {
+        return Value::Null;
+    }
+    if let Ok(int) = cell.parse::<i64>() {
+        return Value::from(int);
+    }
+    match cell.parse::<f64>().ok().and_then(Number::from_f64) {
+        Some(num) => Value::Number(num),
+        None => Value::String(cell.to_string()),
+    }
+}
+
+fn json_column(content: &Value, column: Option<&str>) -> Result<Vec<Value>> {
+    let items = content
+        .as_array()
+        .ok_or_else(|| anyhow!("The parameter source file must hold a JSON array."))?;
+
+    items
+        .iter()
+        .map(|item| match (item, column) {
+            (Value::Object(obj), Some(key)) => Ok(obj.get(key).cloned().unwrap_or(Value::Null)),
+            (Value::Object(_), None) => Err(anyhow!(
+                "The column is required to read a JSON array of objects."
+            )),
+            (Value::Array(cells), column) => {
+                let index = match column {
+                    Some(c) => c.parse::<usize>().map_err(|_| {
+                        anyhow!("The column of a JSON array of arrays must be an index.")
+                    })?,
+                    None => 0,
+                };
+                Ok(cells.get(index).cloned().unwrap_or(Value::Null))
+            }
+            (value, _) => Ok(value.clone()),
+        })
+        .collect()
+}
+
+fn number_range(start: f64, end: f64, step: f64) -> Result<Vec<Value>> {
+    if step == 0.0 || !step.is_finite() || (end - start) * step < 0.0 {
+        return Err(anyhow!(
+            "The step {} never goes from {} to {}.",
+            step,
+            start,
+            end
+        ));
+    }
+
+    // Absorbs the rounding errors of the division, e.g. (0.3 - 0.1) / 0.1. The count is checked
+    // before the cast, which saturates the huge or infinite counts.
+    let count = ((end - start) / step + 1e-9).floor() + 1.0;
+    if !count.is_finite() || count > MAX_SOURCE_VALUES as f64 {
+        return Err(anyhow!(
+            "The number range generates more than {} values.",
+            MAX_SOURCE_VALUES
+        ));
+    }
+    let count = count as usize;
+
+    let integral = start.fract() == 0.0 && step.fract() == 0.0;
+    Ok((0..count)
+        .map(|i| {
+            let val = start + step * i as f64;
+            if integral {
+                Value::from(val as i64)
+            } else {
+                Number::from_f64(val).map_or(Value::Null, Value::Number)
+            }
+        })
+        .collect())
+}
+
+fn date_range(start: &str, end: &str, step_days: i64) -> Result<Vec<Value>> {
+    let start = NaiveDate::parse_from_str(start, DATE_FORMAT)?;
+    let end = NaiveDate::parse_from_str(end, DATE_FORMAT)?;
+    if step_days == 0 || (end - start).num_days() * step_days < 0 {
+        return Err(anyhow!(
+            "The step of {} days never goes from {} to {}.",
+            step_days,
+            start,
+            end
+        ));
+    }
+
+    let count = ((end - start).num_days() / step_days) as usize + 1;
+    if count > MAX_SOURCE_VALUES {
+        return Err(anyhow!(
+            "The date range generates more than {} values.",
+            MAX_SOURCE_VALUES
+        ));
+    }
+
+    Ok((0..count)
+        .map(|i| {
+            let date = start + Duration::days(step_days * i as i64);
+            Value::String(date.format(DATE_FORMAT).to_string())
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_deserialize_seeds() {
+        let seeds: Vec<ParameterSeed> = serde_json::from_value(json!([
+            [1, "a"],
+            {"source": "query", "statement": "select id from company_item"},
+            {"source": "dateRange", "start": "2022-01-30", "end": "2022-02-01"}
+        ]))
+        .unwrap();
+
+        assert_eq!(seeds[0].values(), Some(&[json!(1), json!("a")][..]));
+        assert_eq!(seeds[1].query(), Some("select id from company_item"));
+        assert_eq!(
+            seeds[2].resolve().unwrap(),
+            vec![
+                json!("2022-01-30"),
+                json!("2022-01-31"),
+                json!("2022-02-01")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_number_range() {
+        assert_eq!(
+            number_range(1.0, 7.0, 3.0).unwrap(),
+            vec![json!(1), json!(4), json!(7)]
+        );
+        assert_eq!(
+            number_range(1.0, 0.0, -0.5).unwrap(),
+            vec![json!(1.0), json!(0.5), json!(0.0)]
+        );
+        assert!(number_range(1.0, 7.0, -1.0).is_err());
+        assert!(number_range(1.0, 7.0, 0.0).is_err());
+        assert!(number_range(0.0, 1e300, 1e-300).is_err());
+        assert!(number_range(0.0, f64::INFINITY, 1.0).is_err());
+        assert!(number_range(f64::NAN, 1.0, 1.0).is_err());
+        assert_eq!(
+            number_range(0.0, (MAX_SOURCE_VALUES - 1) as f64, 1.0)
+                .unwrap()
+                .len(),
+            MAX_SOURCE_VALUES
+        );
+    }
+
+    #[test]
+    fn test_first_column_values() {
+        let rows = vec![vec![json!(1), json!("a")], vec![json!(2), json!("b")]];
+        let columns = Some(vec!["ID".to_string(), "NAME".to_string()]);
+        let result_set = SQLResultSet::new(2, columns.clone(), Some(rows.clone()));
+        assert_eq!(
+            first_column_values(result_set).unwrap(),
+            vec![json!(1), json!(2)]
+        );
+
+        let truncated = SQLResultSet::new(2, columns, Some(rows)).with_truncated(true);
+        assert!(first_column_values(truncated).is_err());
+    }
+
+    #[test]
+    fn test_json_column() {
+        let content = json!([{"id": 1, "name": "a"}, {"id": 2}]);
+        assert_eq!(
+            json_column(&content, Some("name")).unwrap(),
+            vec![json!("a"), Value::Null]
+        );
+        assert_eq!(
+            json_column(&json!([[1, 2], [3, 4]]), Some("1")).unwrap(),
+            vec![json!(2), json!(4)]
+        );
+        assert!(json_column(&content, None).is_err());
+    }
+
+    #[test]
+    fn test_parse_cell() {
+        assert_eq!(parse_cell(" 42 "), json!(42));
+        assert_eq!(parse_cell("4.5"), json!(4.5));
+        assert_eq!(parse_cell(""), Value::Null);
+        assert_eq!(parse_cell("GREENCO"), json!("GREENCO"));
+    }
+}
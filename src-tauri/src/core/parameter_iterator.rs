@@ -1,34 +1,47 @@
-use std::cell::RefCell;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
 
 use oracle::sql_type::ToSql as oracle_ToSql;
 use serde::{Deserialize, Serialize};
-use tokio_postgres::{types::ToSql as pg_ToSql, Statement as pg_Statement};
+use serde_json::Value;
+use tokio_postgres::types::ToSql as pg_ToSql;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub enum ParameterGenerateStrategy {
+    /// Steps all the parameters together, the shorter seeds start over until the longest ends.
     Normal,
+    /// Every combination of the seeds.
     Cartesian,
+    /// Steps all the parameters together until the shortest seed ends.
+    ZipShortest,
+    /// A set of combinations covering every pair of values of any two parameters.
+    Pairwise,
+    /// `count` distinct combinations drawn at random, the same seed draws the same combinations.
+    RandomSample { count: usize, seed: u64 },
 }
 
 pub enum DBParamIter<'a> {
     Oracle(RefCell<ParameterIterator<'a, Box<dyn oracle_ToSql>, String>>),
-    Postgres(RefCell<ParameterIterator<'a, Box<dyn pg_ToSql + Sync>, pg_Statement>>),
+    Postgres(RefCell<ParameterIterator<'a, Box<dyn pg_ToSql + Sync>, String>>),
 }
 
+/// The processed statement along with the resolved and the mapped parameter values, the
+/// statement is prepared on each pooled connection executing it.
 pub enum ParamSeeds {
-    Oracle(String, Vec<Vec<Box<dyn oracle_ToSql>>>),
-    Postgres(pg_Statement, Vec<Vec<Box<dyn pg_ToSql + Sync>>>),
+    Oracle(String, Vec<Vec<Value>>, Vec<Vec<Box<dyn oracle_ToSql>>>),
+    Postgres(String, Vec<Vec<Value>>, Vec<Vec<Box<dyn pg_ToSql + Sync>>>),
 }
 
 pub struct ParameterIterator<'a, S: 'a, PS> {
     seeds: &'a [Vec<S>],
     prepared_stmt: PS,
-    current_parameter_index: usize,
-    current_indexes: Vec<usize>,
     mode: &'a ParameterGenerateStrategy,
-    terminal_parameter_index: usize,
-    drained: bool,
+    /// The seed indexes of the strategies which can't derive them from the position.
+    combinations: Option<Vec<Vec<usize>>>,
+    position: usize,
     total: usize,
 }
 
@@ -36,14 +49,18 @@ impl<'a, S, PS> Iterator for ParameterIterator<'a, S, PS> {
     type Item = Vec<&'a S>;
 
     fn next(&mut self) -> Option<Vec<&'a S>> {
+        if self.drained() {
+            return None;
+        }
+
+        let seeds = self.seeds;
         let parameters = self
-            .current_indexes
-            .iter()
+            .current_indexes()
+            .into_iter()
             .enumerate()
-            .map(|(i, &ci)| self.seeds[i].get(ci).unwrap())
+            .map(|(i, ci)| &seeds[i][ci])
             .collect();
-
-        self.next_indexes();
+        self.position += 1;
 
         Some(parameters)
     }
@@ -55,50 +72,35 @@ impl<'a, T, PS> ParameterIterator<'a, T, PS> {
         mode: &'a ParameterGenerateStrategy,
         prepared_stmt: PS,
     ) -> Self {
-        let terminal_parameter_index_opt = Self::find_terminal_parameter_index(seeds, mode);
-        let (drained, terminal_parameter_index, total) = match terminal_parameter_index_opt {
-            Some((tpi, t)) => (false, tpi, t),
-            None => (false, 0, 1),
+        let lens: Vec<usize> = seeds.iter().map(|s| s.len()).collect();
+        let combinations = match mode {
+            _ if lens.is_empty() || lens.contains(&0) => None,
+            ParameterGenerateStrategy::Pairwise => Some(pairwise_combinations(&lens)),
+            ParameterGenerateStrategy::RandomSample { count, seed } => {
+                Some(random_combinations(&lens, *count, *seed))
+            }
+            _ => None,
         };
+        let total = match (&combinations, mode) {
+            // A statement without parameters runs once.
+            _ if lens.is_empty() => 1,
+            _ if lens.contains(&0) => 0,
+            (Some(combinations), _) => combinations.len(),
+            (None, ParameterGenerateStrategy::ZipShortest) => *lens.iter().min().unwrap(),
+            (None, ParameterGenerateStrategy::Cartesian) => cartesian_total(&lens),
+            (None, _) => *lens.iter().max().unwrap(),
+        };
+
         ParameterIterator {
-            current_parameter_index: 0,
-            current_indexes: vec![0; seeds.len()],
-            drained,
+            seeds,
             prepared_stmt,
             mode,
-            seeds,
-            terminal_parameter_index,
+            combinations,
+            position: 0,
             total,
         }
     }
 
-    fn find_terminal_parameter_index(
-        seeds: &'a [Vec<T>],
-        mode: &ParameterGenerateStrategy,
-    ) -> Option<(usize, usize)> {
-        if seeds.is_empty() || seeds.iter().any(|s| s.is_empty()) {
-            return None;
-        }
-        match mode {
-            ParameterGenerateStrategy::Normal => {
-                let mut max_len_index = 0;
-                let mut max_len = 0;
-                for (i, seed) in seeds.iter().enumerate() {
-                    if seed.len() > max_len {
-                        max_len = seed.len();
-                        max_len_index = i;
-                    }
-                }
-
-                return Some((max_len_index, max_len));
-            }
-            ParameterGenerateStrategy::Cartesian => Some((
-                seeds.len() - 1,
-                seeds.iter().fold(1, |acc, s| acc * s.len()),
-            )),
-        }
-    }
-
     pub fn prepared_stmt(&self) -> &PS {
         &self.prepared_stmt
     }
@@ -108,75 +110,291 @@ impl<'a, T, PS> ParameterIterator<'a, T, PS> {
     }
 
     pub fn drained(&self) -> bool {
-        self.drained
+        self.position >= self.total
     }
 
+    /// The seed indexes of the parameters returned by the next call of `next`.
     pub fn current_indexes(&self) -> Vec<usize> {
-        self.current_indexes.clone()
+        if self.drained() {
+            return vec![0; self.seeds.len()];
+        }
+        if let Some(combinations) = &self.combinations {
+            return combinations[self.position].clone();
+        }
+
+        let mut stride = 1;
+        self.seeds
+            .iter()
+            .map(|seed| match self.mode {
+                ParameterGenerateStrategy::Cartesian => {
+                    // The first parameter changes the fastest.
+                    let index = (self.position / stride) % seed.len();
+                    stride = stride.saturating_mul(seed.len());
+                    index
+                }
+                ParameterGenerateStrategy::ZipShortest => self.position,
+                _ => self.position % seed.len(),
+            })
+            .collect()
     }
+}
 
-    fn next_indexes(&mut self) {
-        if self.seeds.is_empty() {
-            self.drained = true;
-        }
+fn cartesian_total(lens: &[usize]) -> usize {
+    lens.iter().fold(1, |acc, &len| acc.saturating_mul(len))
+}
 
-        match self.mode {
-            ParameterGenerateStrategy::Normal => self.next_normal_indexes(),
-            ParameterGenerateStrategy::Cartesian => self.next_cartesian_indexes(),
+/// Builds the combinations greedily: each one starts from a pair not covered yet, then every
+/// other parameter takes the value covering the most new pairs with the values already taken.
+fn pairwise_combinations(lens: &[usize]) -> Vec<Vec<usize>> {
+    if lens.len() < 2 {
+        return (0..lens.first().copied().unwrap_or(0))
+            .map(|i| vec![i])
+            .collect();
+    }
+
+    let mut uncovered: HashMap<(usize, usize), HashSet<(usize, usize)>> = HashMap::new();
+    for i in 0..lens.len() {
+        for j in i + 1..lens.len() {
+            let pairs = (0..lens[i])
+                .flat_map(|a| (0..lens[j]).map(move |b| (a, b)))
+                .collect();
+            uncovered.insert((i, j), pairs);
         }
     }
 
-    fn next_normal_indexes(&mut self) {
-        if self.drained {
-            return;
+    let mut combinations = Vec::new();
+    while let Some((i, j, a, b)) = first_uncovered_pair(&uncovered, lens.len()) {
+        let mut combination: Vec<Option<usize>> = vec![None; lens.len()];
+        combination[i] = Some(a);
+        combination[j] = Some(b);
+
+        for k in 0..lens.len() {
+            if combination[k].is_some() {
+                continue;
+            }
+            let newly_covered = |v: usize| {
+                combination
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(p, val)| val.map(|val| (p, val)))
+                    .filter(|&(p, val)| {
+                        let (key, pair) = if p < k {
+                            ((p, k), (val, v))
+                        } else {
+                            ((k, p), (v, val))
+                        };
+                        uncovered[&key].contains(&pair)
+                    })
+                    .count()
+            };
+            // The first value wins the ties, so the combinations are deterministic.
+            let best = (0..lens[k])
+                .max_by(|&x, &y| newly_covered(x).cmp(&newly_covered(y)).then(y.cmp(&x)))
+                .unwrap();
+            combination[k] = Some(best);
         }
-        if self.current_indexes.is_empty() {
-            return;
+
+        let combination: Vec<usize> = combination.into_iter().map(Option::unwrap).collect();
+        for p in 0..lens.len() {
+            for q in p + 1..lens.len() {
+                uncovered
+                    .get_mut(&(p, q))
+                    .unwrap()
+                    .remove(&(combination[p], combination[q]));
+            }
         }
-        for i in 0..self.current_indexes.len() {
-            let ci = self.current_indexes[i];
-            let len = self.seeds[i].len();
-            if ci == len - 1 {
-                if i == self.terminal_parameter_index {
-                    self.drained = true;
-                } else {
-                    self.current_indexes[i] = 0;
-                }
-            } else {
-                self.current_indexes[i] += 1;
+        combinations.push(combination);
+    }
+
+    combinations
+}
+
+fn first_uncovered_pair(
+    uncovered: &HashMap<(usize, usize), HashSet<(usize, usize)>>,
+    params: usize,
+) -> Option<(usize, usize, usize, usize)> {
+    for i in 0..params {
+        for j in i + 1..params {
+            if let Some(&(a, b)) = uncovered[&(i, j)].iter().min() {
+                return Some((i, j, a, b));
             }
         }
     }
 
-    fn next_cartesian_indexes(&mut self) {
-        if self.drained {
-            return;
+    None
+}
+
+/// Draws distinct combinations with a splitmix64 generator, all of them when there are fewer
+/// combinations than requested.
+fn random_combinations(lens: &[usize], count: usize, seed: u64) -> Vec<Vec<usize>> {
+    let space = cartesian_total(lens);
+    if count >= space {
+        return (0..space)
+            .map(|position| {
+                let mut stride = 1;
+                lens.iter()
+                    .map(|&len| {
+                        let index = (position / stride) % len;
+                        stride *= len;
+                        index
+                    })
+                    .collect()
+            })
+            .collect();
+    }
+
+    let mut state = seed;
+    let mut next_random = move || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+
+    let mut drawn = HashSet::with_capacity(count);
+    let mut combinations = Vec::with_capacity(count);
+    while combinations.len() < count {
+        let combination: Vec<usize> = lens
+            .iter()
+            .map(|&len| (next_random() % len as u64) as usize)
+            .collect();
+        if drawn.insert(combination.clone()) {
+            combinations.push(combination);
         }
-        if self.current_indexes.is_empty() {
-            return;
+    }
+
+    combinations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(seeds: &[Vec<i32>], mode: &ParameterGenerateStrategy) -> (usize, Vec<Vec<i32>>) {
+        let mut iter = ParameterIterator::new(seeds, mode, ());
+        let total = iter.total();
+        let mut generated = Vec::new();
+        while !iter.drained() {
+            generated.push(iter.next().unwrap().into_iter().copied().collect());
         }
+        assert!(iter.next().is_none());
+
+        (total, generated)
+    }
+
+    fn sample_seeds() -> Vec<Vec<i32>> {
+        vec![vec![1, 2, 3], vec![10, 20]]
+    }
+
+    #[test]
+    fn test_normal() {
+        let (total, generated) = generate(&sample_seeds(), &ParameterGenerateStrategy::Normal);
+
+        assert_eq!(total, 3);
+        assert_eq!(generated, vec![vec![1, 10], vec![2, 20], vec![3, 10]]);
+    }
+
+    #[test]
+    fn test_cartesian() {
+        let (total, generated) = generate(&sample_seeds(), &ParameterGenerateStrategy::Cartesian);
+
+        assert_eq!(total, 6);
+        assert_eq!(
+            generated,
+            vec![
+                vec![1, 10],
+                vec![2, 10],
+                vec![3, 10],
+                vec![1, 20],
+                vec![2, 20],
+                vec![3, 20]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_zip_shortest() {
+        let (total, generated) = generate(&sample_seeds(), &ParameterGenerateStrategy::ZipShortest);
 
-        let mut cur_idx = self.current_indexes[self.current_parameter_index];
-        let mut len = self.seeds[cur_idx].len();
-
-        if cur_idx == len - 1 {
-            if self.current_parameter_index == self.terminal_parameter_index {
-                self.drained = true;
-                return;
-            } else {
-                self.current_indexes[self.current_parameter_index] = 0;
-                self.current_parameter_index =
-                    if self.current_parameter_index == self.seeds.len() - 1 {
-                        0
-                    } else {
-                        self.current_parameter_index + 1
-                    };
-                cur_idx = self.current_indexes[self.current_parameter_index];
-                len = self.seeds[cur_idx].len();
+        assert_eq!(total, 2);
+        assert_eq!(generated, vec![vec![1, 10], vec![2, 20]]);
+    }
+
+    #[test]
+    fn test_pairwise_covers_all_pairs() {
+        let seeds: Vec<Vec<i32>> = (0..4)
+            .map(|p| (0..3).map(|v| p * 10 + v).collect())
+            .collect();
+        let (total, generated) = generate(&seeds, &ParameterGenerateStrategy::Pairwise);
+
+        assert_eq!(total, generated.len());
+        assert!(
+            total < 3 * 3 * 3 * 3,
+            "pairwise generated {} combinations",
+            total
+        );
+        for i in 0..seeds.len() {
+            for j in i + 1..seeds.len() {
+                for a in &seeds[i] {
+                    for b in &seeds[j] {
+                        assert!(
+                            generated.iter().any(|c| c[i] == *a && c[j] == *b),
+                            "pair ({}, {}) is not covered",
+                            a,
+                            b
+                        );
+                    }
+                }
             }
         }
+    }
+
+    #[test]
+    fn test_pairwise_single_parameter() {
+        let (total, generated) = generate(&[vec![1, 2, 3]], &ParameterGenerateStrategy::Pairwise);
+
+        assert_eq!(total, 3);
+        assert_eq!(generated, vec![vec![1], vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn test_random_sample() {
+        let seeds: Vec<Vec<i32>> = (0..3)
+            .map(|p| (0..10).map(|v| p * 100 + v).collect())
+            .collect();
+        let mode = ParameterGenerateStrategy::RandomSample {
+            count: 20,
+            seed: 42,
+        };
+        let (total, generated) = generate(&seeds, &mode);
+
+        assert_eq!(total, 20);
+        assert_eq!(generated.iter().collect::<HashSet<_>>().len(), 20);
+        assert_eq!(generate(&seeds, &mode).1, generated);
+
+        let other_seed = ParameterGenerateStrategy::RandomSample { count: 20, seed: 7 };
+        assert_ne!(generate(&seeds, &other_seed).1, generated);
+    }
+
+    #[test]
+    fn test_random_sample_larger_than_space() {
+        let mode = ParameterGenerateStrategy::RandomSample {
+            count: 100,
+            seed: 1,
+        };
+        let (total, generated) = generate(&sample_seeds(), &mode);
+
+        assert_eq!(total, 6);
+        assert_eq!(generated.iter().collect::<HashSet<_>>().len(), 6);
+    }
+
+    #[test]
+    fn test_without_parameters() {
+        let (total, generated) = generate(&[], &ParameterGenerateStrategy::Cartesian);
+        assert_eq!((total, generated), (1, vec![vec![]]));
 
-        self.current_indexes[self.current_parameter_index] =
-            if cur_idx == len - 1 { 0 } else { cur_idx + 1 };
+        let (total, _) = generate(&[vec![1], vec![]], &ParameterGenerateStrategy::Normal);
+        assert_eq!(total, 0);
     }
 }
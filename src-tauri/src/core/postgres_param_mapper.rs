@@ -5,9 +5,11 @@ use chrono::{DateTime, NaiveDateTime};
 use chrono::{FixedOffset, NaiveDate, NaiveTime, Utc};
 use log::debug;
 use serde_json::Value;
-use tokio_postgres::types::{ToSql, Type};
+use tokio_postgres::types::{Field, Kind, ToSql, Type};
 use uuid::Uuid;
 
+use super::postgres_types::{PgComposite, PgEnum, PgInet, PgInterval, PgMoney, PgNumeric};
+
 fn generate_error(param: &Value, sql_type: &Type) -> Error {
     anyhow!(
         "param conversion error: expect {}, got {}.",
@@ -272,6 +274,135 @@ fn map_to_sql_text(param: &Value, sql_type: &Type) -> Result<Option<String>> {
     Ok(val)
 }
 
+/// Numbers are sent as their text, so the decimals of the JSON numbers are kept as they are.
+fn map_to_sql_numeric(param: &Value) -> Result<Option<PgNumeric>> {
+    let text = match param {
+        Value::Null => return Ok(None),
+        Value::Number(num) => num.to_string(),
+        Value::String(str) => str.clone(),
+        _ => return Err(generate_error(param, &Type::NUMERIC)),
+    };
+
+    match PgNumeric::parse(&text) {
+        Ok(numeric) => Ok(Some(numeric)),
+        Err(_) => Err(generate_error(param, &Type::NUMERIC)),
+    }
+}
+
+fn map_to_sql_money(param: &Value) -> Result<Option<PgMoney>> {
+    let text = match param {
+        Value::Null => return Ok(None),
+        Value::Number(num) => num.to_string(),
+        Value::String(str) => str.clone(),
+        _ => return Err(generate_error(param, &Type::MONEY)),
+    };
+
+    match PgMoney::parse(&text) {
+        Ok(money) => Ok(Some(money)),
+        Err(_) => Err(generate_error(param, &Type::MONEY)),
+    }
+}
+
+/// A string holding JSON text is sent as the parsed JSON, any other string as a JSON string.
+fn map_to_sql_json(param: &Value) -> Result<Option<Value>> {
+    let val = match param {
+        Value::Null => None,
+        Value::String(str) => Some(serde_json::from_str(str).unwrap_or_else(|_| param.clone())),
+        _ => Some(param.clone()),
+    };
+
+    Ok(val)
+}
+
+/// A number is the seconds of the interval.
+fn map_to_sql_interval(param: &Value) -> Result<Option<PgInterval>> {
+    match param {
+        Value::Null => Ok(None),
+        Value::Number(num) => match num.as_f64() {
+            Some(seconds) => Ok(Some(PgInterval {
+                micros: (seconds * 1_000_000.0).round() as i64,
+                ..Default::default()
+            })),
+            None => Err(generate_error(param, &Type::INTERVAL)),
+        },
+        Value::String(str) => match PgInterval::parse(str) {
+            Ok(interval) => Ok(Some(interval)),
+            Err(_) => Err(generate_error(param, &Type::INTERVAL)),
+        },
+        _ => Err(generate_error(param, &Type::INTERVAL)),
+    }
+}
+
+fn map_to_sql_inet(param: &Value, sql_type: &Type) -> Result<Option<PgInet>> {
+    match param {
+        Value::Null => Ok(None),
+        Value::String(str) => match PgInet::parse(str) {
+            Ok(inet) => Ok(Some(inet)),
+            Err(_) => Err(generate_error(param, sql_type)),
+        },
+        _ => Err(generate_error(param, sql_type)),
+    }
+}
+
+fn map_to_sql_enum(param: &Value, sql_type: &Type) -> Result<Option<PgEnum>> {
+    match param {
+        Value::Null => Ok(None),
+        Value::String(str) => Ok(Some(PgEnum(str.clone()))),
+        _ => Err(generate_error(param, sql_type)),
+    }
+}
+
+/// The fields are taken from an object by name, or from an array by position. The missing
+/// fields are NULL.
+fn map_to_sql_composite(
+    param: &Value,
+    sql_type: &Type,
+    fields: &[Field],
+) -> Result<Option<PgComposite>> {
+    let field_vals: Vec<&Value> = match param {
+        Value::Null => return Ok(None),
+        Value::Object(obj) => fields
+            .iter()
+            .map(|field| obj.get(field.name()).unwrap_or(&Value::Null))
+            .collect(),
+        Value::Array(arr) if arr.len() <= fields.len() => fields
+            .iter()
+            .enumerate()
+            .map(|(i, _)| arr.get(i).unwrap_or(&Value::Null))
+            .collect(),
+        _ => return Err(generate_error(param, sql_type)),
+    };
+
+    let mut mapped_fields = Vec::with_capacity(fields.len());
+    for (field, val) in fields.iter().zip(field_vals) {
+        mapped_fields.push(map_to_sql(val, field.type_())?);
+    }
+
+    Ok(Some(PgComposite(mapped_fields)))
+}
+
+fn map_to_sql_array<T, F>(
+    param: &Value,
+    sql_type: &Type,
+    map_fn: F,
+) -> Result<Box<dyn ToSql + Sync>>
+where
+    T: ToSql + Sync + 'static,
+    F: Fn(&Value) -> Result<Option<T>>,
+{
+    match param {
+        Value::Null => Ok(Box::new(Option::<Vec<Option<T>>>::None)),
+        Value::Array(arr) => {
+            let mut mapped_arr = Vec::with_capacity(arr.len());
+            for val in arr {
+                mapped_arr.push(map_fn(val)?);
+            }
+            Ok(Box::new(mapped_arr))
+        }
+        _ => Err(generate_error(param, sql_type)),
+    }
+}
+
 pub fn map_to_sql(param: &Value, sql_type: &Type) -> Result<Box<dyn ToSql + Sync>> {
     let mapped_param: Box<dyn ToSql + Sync>;
     match sql_type.kind() {
@@ -298,6 +429,16 @@ pub fn map_to_sql(param: &Value, sql_type: &Type) -> Result<Box<dyn ToSql + Sync
                 mapped_param = Box::new(map_to_sql_date(param)?);
             } else if *sql_type == Type::TIME {
                 mapped_param = Box::new(map_to_sql_time(param)?);
+            } else if *sql_type == Type::NUMERIC {
+                mapped_param = Box::new(map_to_sql_numeric(param)?);
+            } else if *sql_type == Type::MONEY {
+                mapped_param = Box::new(map_to_sql_money(param)?);
+            } else if *sql_type == Type::JSON || *sql_type == Type::JSONB {
+                mapped_param = Box::new(map_to_sql_json(param)?);
+            } else if *sql_type == Type::INTERVAL {
+                mapped_param = Box::new(map_to_sql_interval(param)?);
+            } else if *sql_type == Type::INET || *sql_type == Type::CIDR {
+                mapped_param = Box::new(map_to_sql_inet(param, sql_type)?);
             } else {
                 mapped_param = Box::new(map_to_sql_text(param, sql_type)?);
             }
@@ -479,6 +620,24 @@ pub fn map_to_sql(param: &Value, sql_type: &Type) -> Result<Box<dyn ToSql + Sync
                         return Err(generate_error(param, sql_type));
                     }
                 }
+            } else if *element_type == Type::NUMERIC {
+                mapped_param = map_to_sql_array(param, sql_type, map_to_sql_numeric)?;
+            } else if *element_type == Type::MONEY {
+                mapped_param = map_to_sql_array(param, sql_type, map_to_sql_money)?;
+            } else if *element_type == Type::JSON || *element_type == Type::JSONB {
+                mapped_param = map_to_sql_array(param, sql_type, map_to_sql_json)?;
+            } else if *element_type == Type::INTERVAL {
+                mapped_param = map_to_sql_array(param, sql_type, map_to_sql_interval)?;
+            } else if *element_type == Type::INET || *element_type == Type::CIDR {
+                mapped_param =
+                    map_to_sql_array(param, sql_type, |val| map_to_sql_inet(val, element_type))?;
+            } else if let Kind::Enum(_) = element_type.kind() {
+                mapped_param =
+                    map_to_sql_array(param, sql_type, |val| map_to_sql_enum(val, element_type))?;
+            } else if let Kind::Composite(fields) = element_type.kind() {
+                mapped_param = map_to_sql_array(param, sql_type, |val| {
+                    map_to_sql_composite(val, element_type, fields)
+                })?;
             } else {
                 match param {
                     Value::Null => {
@@ -497,6 +656,12 @@ pub fn map_to_sql(param: &Value, sql_type: &Type) -> Result<Box<dyn ToSql + Sync
                 }
             }
         }
+        tokio_postgres::types::Kind::Enum(_) => {
+            mapped_param = Box::new(map_to_sql_enum(param, sql_type)?);
+        }
+        tokio_postgres::types::Kind::Composite(fields) => {
+            mapped_param = Box::new(map_to_sql_composite(param, sql_type, fields)?);
+        }
         _ => {
             return Err(generate_error(param, sql_type));
         }
@@ -0,0 +1,647 @@
+use std::{
+    convert::{TryFrom, TryInto},
+    error::Error,
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use bytes::{BufMut, BytesMut};
+use tokio_postgres::types::{to_sql_checked, FromSql, IsNull, Kind, ToSql, Type};
+
+type BoxError = Box<dyn Error + Sync + Send>;
+
+const NUMERIC_POS: u16 = 0x0000;
+const NUMERIC_NEG: u16 = 0x4000;
+const NUMERIC_NAN: u16 = 0xC000;
+const NUMERIC_PINF: u16 = 0xD000;
+const NUMERIC_NINF: u16 = 0xF000;
+/// The NUMERIC digits are stored in base 10000, 4 decimal digits each.
+const NUMERIC_DIGIT_LEN: usize = 4;
+/// The most digits a NUMERIC has before and after the decimal point.
+const NUMERIC_MAX_INT_DIGITS: i64 = 131072;
+const NUMERIC_MAX_SCALE: i64 = 16383;
+
+const PGSQL_AF_INET: u8 = 2;
+const PGSQL_AF_INET6: u8 = 3;
+
+/// The fractional digits of MONEY, as with the usual `lc_monetary` settings.
+const MONEY_SCALE: usize = 2;
+
+const MICROS_PER_SECOND: i64 = 1_000_000;
+const MICROS_PER_MINUTE: i64 = 60 * MICROS_PER_SECOND;
+const MICROS_PER_HOUR: i64 = 60 * MICROS_PER_MINUTE;
+
+fn read_i16(raw: &[u8], pos: usize) -> Result<i16, BoxError> {
+    Ok(i16::from_be_bytes(
+        raw.get(pos..pos + 2)
+            .ok_or("invalid buffer size")?
+            .try_into()?,
+    ))
+}
+
+fn read_i32(raw: &[u8], pos: usize) -> Result<i32, BoxError> {
+    Ok(i32::from_be_bytes(
+        raw.get(pos..pos + 4)
+            .ok_or("invalid buffer size")?
+            .try_into()?,
+    ))
+}
+
+fn read_i64(raw: &[u8], pos: usize) -> Result<i64, BoxError> {
+    Ok(i64::from_be_bytes(
+        raw.get(pos..pos + 8)
+            .ok_or("invalid buffer size")?
+            .try_into()?,
+    ))
+}
+
+/// A decimal split into its sign and digits, the digits of the integer part have no leading
+/// zeros.
+struct DecimalParts {
+    negative: bool,
+    int_digits: String,
+    frac_digits: String,
+}
+
+impl DecimalParts {
+    /// Parses plain and scientific decimal text, e.g. `-1.50` or `15e-1`.
+    fn parse(text: &str) -> Result<Self, BoxError> {
+        let invalid = || format!("invalid decimal: {}", text);
+        let text = text.trim();
+        let (negative, unsigned) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+            Some(i) => (
+                &unsigned[..i],
+                unsigned[i + 1..].parse::<i64>().map_err(|_| invalid())?,
+            ),
+            None => (unsigned, 0),
+        };
+        let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let all_digits = int_part
+            .chars()
+            .chain(frac_part.chars())
+            .all(|c| c.is_ascii_digit());
+        if (int_part.is_empty() && frac_part.is_empty()) || !all_digits {
+            return Err(invalid().into());
+        }
+
+        let digits = format!("{}{}", int_part, frac_part);
+        // The exponent is checked before the digits are padded with zeros for it.
+        let point = (int_part.len() as i64)
+            .checked_add(exponent)
+            .filter(|point| (-NUMERIC_MAX_SCALE..=NUMERIC_MAX_INT_DIGITS).contains(point))
+            .ok_or_else(|| format!("decimal out of the numeric range: {}", text))?;
+        let (int_digits, frac_digits) = if point <= 0 {
+            (String::new(), "0".repeat((-point) as usize) + &digits)
+        } else if point as usize >= digits.len() {
+            (
+                digits.clone() + &"0".repeat(point as usize - digits.len()),
+                String::new(),
+            )
+        } else {
+            let (int_digits, frac_digits) = digits.split_at(point as usize);
+            (int_digits.to_string(), frac_digits.to_string())
+        };
+
+        Ok(Self {
+            negative,
+            int_digits: int_digits.trim_start_matches('0').to_string(),
+            frac_digits,
+        })
+    }
+}
+
+/// A NUMERIC kept as its decimal text, so no digit or scale is lost, e.g. `1.50` stays `1.50`.
+/// NaN and the infinities are `NaN`, `Infinity` and `-Infinity`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgNumeric(pub String);
+
+impl PgNumeric {
+    /// Checks the text is a decimal, NaN or an infinity.
+    pub fn parse(text: &str) -> Result<Self, BoxError> {
+        match text.trim() {
+            "NaN" | "Infinity" | "+Infinity" | "-Infinity" => {}
+            decimal => {
+                DecimalParts::parse(decimal)?;
+            }
+        }
+
+        Ok(PgNumeric(text.trim().to_string()))
+    }
+}
+
+impl fmt::Display for PgNumeric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'a> FromSql<'a> for PgNumeric {
+    fn from_sql(_: &Type, raw: &'a [u8]) -> Result<Self, BoxError> {
+        let ndigits = read_i16(raw, 0)?;
+        let weight = read_i16(raw, 2)? as i64;
+        let sign = read_i16(raw, 4)? as u16;
+        let dscale = read_i16(raw, 6)? as usize;
+        let digits = (0..ndigits.max(0) as usize)
+            .map(|i| read_i16(raw, 8 + i * 2))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let text = match sign {
+            NUMERIC_NAN => return Ok(PgNumeric("NaN".to_string())),
+            NUMERIC_PINF => return Ok(PgNumeric("Infinity".to_string())),
+            NUMERIC_NINF => return Ok(PgNumeric("-Infinity".to_string())),
+            NUMERIC_POS | NUMERIC_NEG => {
+                let digit = |i: i64| {
+                    usize::try_from(i)
+                        .ok()
+                        .and_then(|i| digits.get(i))
+                        .copied()
+                        .unwrap_or(0)
+                };
+                let mut int_part = String::new();
+                for i in 0..=weight {
+                    if int_part.is_empty() {
+                        int_part = digit(i).to_string();
+                    } else {
+                        int_part.push_str(&format!("{:04}", digit(i)));
+                    }
+                }
+                if int_part.is_empty() {
+                    int_part.push('0');
+                }
+
+                let mut frac_part = String::new();
+                let mut i = weight + 1;
+                while frac_part.len() < dscale {
+                    frac_part.push_str(&format!("{:04}", digit(i)));
+                    i += 1;
+                }
+                frac_part.truncate(dscale);
+
+                let is_zero = digits.iter().all(|&d| d == 0);
+                let sign = if sign == NUMERIC_NEG && !is_zero {
+                    "-"
+                } else {
+                    ""
+                };
+                if frac_part.is_empty() {
+                    format!("{}{}", sign, int_part)
+                } else {
+                    format!("{}{}.{}", sign, int_part, frac_part)
+                }
+            }
+            _ => return Err(format!("invalid numeric sign: {:#x}", sign).into()),
+        };
+
+        Ok(PgNumeric(text))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::NUMERIC
+    }
+}
+
+impl ToSql for PgNumeric {
+    fn to_sql(&self, _: &Type, out: &mut BytesMut) -> Result<IsNull, BoxError> {
+        let special = match self.0.trim() {
+            "NaN" => Some(NUMERIC_NAN),
+            "Infinity" | "+Infinity" => Some(NUMERIC_PINF),
+            "-Infinity" => Some(NUMERIC_NINF),
+            _ => None,
+        };
+        if let Some(sign) = special {
+            out.put_i16(0);
+            out.put_i16(0);
+            out.put_u16(sign);
+            out.put_u16(0);
+            return Ok(IsNull::No);
+        }
+
+        let parts = DecimalParts::parse(&self.0)?;
+        let dscale = parts.frac_digits.len();
+        // Pads the integer digits on the left and the fractional digits on the right, so both
+        // split into whole base 10000 digits.
+        let int_pad =
+            (NUMERIC_DIGIT_LEN - parts.int_digits.len() % NUMERIC_DIGIT_LEN) % NUMERIC_DIGIT_LEN;
+        let frac_pad = (NUMERIC_DIGIT_LEN - dscale % NUMERIC_DIGIT_LEN) % NUMERIC_DIGIT_LEN;
+        let padded = format!(
+            "{}{}{}{}",
+            "0".repeat(int_pad),
+            parts.int_digits,
+            parts.frac_digits,
+            "0".repeat(frac_pad)
+        );
+        let mut digits: Vec<i16> = padded
+            .as_bytes()
+            .chunks(NUMERIC_DIGIT_LEN)
+            .map(|chunk| std::str::from_utf8(chunk).unwrap().parse().unwrap())
+            .collect();
+        let mut weight = ((int_pad + parts.int_digits.len()) / NUMERIC_DIGIT_LEN) as i64 - 1;
+
+        let leading_zeros = digits.iter().take_while(|&&d| d == 0).count();
+        digits.drain(..leading_zeros);
+        weight -= leading_zeros as i64;
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        if digits.is_empty() {
+            weight = 0;
+        }
+
+        let sign = if parts.negative && !digits.is_empty() {
+            NUMERIC_NEG
+        } else {
+            NUMERIC_POS
+        };
+        out.put_i16(digits.len().try_into()?);
+        out.put_i16(weight.try_into()?);
+        out.put_u16(sign);
+        out.put_u16(dscale.try_into()?);
+        for digit in digits {
+            out.put_i16(digit);
+        }
+
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::NUMERIC
+    }
+
+    to_sql_checked!();
+}
+
+/// MONEY in the smallest unit of the currency, shown as a decimal without the currency symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgMoney(pub i64);
+
+impl PgMoney {
+    /// Parses a decimal with up to 2 fractional digits, the currency symbol and the thousands
+    /// separators are ignored, e.g. `$1,234.50`.
+    pub fn parse(text: &str) -> Result<Self, BoxError> {
+        let cleaned: String = text
+            .trim()
+            .chars()
+            .filter(|c| !matches!(c, '$' | ',' | ' '))
+            .collect();
+        let parts = DecimalParts::parse(&cleaned)?;
+        let frac_digits = parts.frac_digits.trim_end_matches('0');
+        if frac_digits.len() > MONEY_SCALE {
+            return Err(format!("too many fractional digits for money: {}", text).into());
+        }
+
+        let units: i64 = format!(
+            "{}{}{:0<scale$}",
+            if parts.negative { "-" } else { "" },
+            if parts.int_digits.is_empty() {
+                "0"
+            } else {
+                &parts.int_digits
+            },
+            frac_digits,
+            scale = MONEY_SCALE
+        )
+        .parse()?;
+        Ok(PgMoney(units))
+    }
+}
+
+impl fmt::Display for PgMoney {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scale = 10i64.pow(MONEY_SCALE as u32);
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        write!(
+            f,
+            "{}{}.{:0width$}",
+            sign,
+            units / scale as u64,
+            units % scale as u64,
+            width = MONEY_SCALE
+        )
+    }
+}
+
+impl<'a> FromSql<'a> for PgMoney {
+    fn from_sql(_: &Type, raw: &'a [u8]) -> Result<Self, BoxError> {
+        Ok(PgMoney(read_i64(raw, 0)?))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::MONEY
+    }
+}
+
+impl ToSql for PgMoney {
+    fn to_sql(&self, _: &Type, out: &mut BytesMut) -> Result<IsNull, BoxError> {
+        out.put_i64(self.0);
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::MONEY
+    }
+
+    to_sql_checked!();
+}
+
+/// An INTERVAL as stored by Postgres, the months and the days aren't converted as their lengths
+/// vary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PgInterval {
+    pub months: i32,
+    pub days: i32,
+    pub micros: i64,
+}
+
+impl PgInterval {
+    /// Parses the intervals as shown by Postgres, e.g. `1 year 2 mons 3 days 04:05:06.5`, the
+    /// units may also be hours, minutes and seconds, e.g. `90 minutes`.
+    pub fn parse(text: &str) -> Result<Self, BoxError> {
+        let invalid = || format!("invalid interval: {}", text);
+        let mut interval = PgInterval::default();
+        let mut words = text.split_whitespace().peekable();
+        while let Some(word) = words.next() {
+            if word.contains(':') {
+                interval.micros += parse_interval_time(word).ok_or_else(invalid)?;
+                continue;
+            }
+
+            let amount: f64 = word.parse().map_err(|_| invalid())?;
+            let unit = words.next().ok_or_else(invalid)?.to_lowercase();
+            let unit = unit.trim_end_matches('s');
+            match unit {
+                "year" | "yr" => interval.months += (amount * 12.0) as i32,
+                "mon" | "month" => interval.months += amount as i32,
+                "week" => interval.days += (amount * 7.0) as i32,
+                "day" => interval.days += amount as i32,
+                "hour" | "hr" => interval.micros += (amount * MICROS_PER_HOUR as f64) as i64,
+                "min" | "minute" => interval.micros += (amount * MICROS_PER_MINUTE as f64) as i64,
+                "sec" | "second" => interval.micros += (amount * MICROS_PER_SECOND as f64) as i64,
+                _ => return Err(invalid().into()),
+            }
+        }
+
+        Ok(interval)
+    }
+}
+
+/// The microseconds of `[-]HH:MM[:SS[.ffffff]]`.
+fn parse_interval_time(text: &str) -> Option<i64> {
+    let (negative, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let mut parts = unsigned.split(':');
+    let hours: i64 = parts.next()?.parse().ok()?;
+    let minutes: i64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = match parts.next() {
+        Some(seconds) => seconds.parse().ok()?,
+        None => 0.0,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let micros = hours * MICROS_PER_HOUR
+        + minutes * MICROS_PER_MINUTE
+        + (seconds * MICROS_PER_SECOND as f64).round() as i64;
+    Some(if negative { -micros } else { micros })
+}
+
+impl fmt::Display for PgInterval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = |n: i64| if n.abs() == 1 { "" } else { "s" };
+        let mut parts = Vec::new();
+        let (years, months) = (self.months / 12, self.months % 12);
+        if years != 0 {
+            parts.push(format!("{} year{}", years, plural(years as i64)));
+        }
+        if months != 0 {
+            parts.push(format!("{} mon{}", months, plural(months as i64)));
+        }
+        if self.days != 0 {
+            parts.push(format!("{} day{}", self.days, plural(self.days as i64)));
+        }
+        if self.micros != 0 || parts.is_empty() {
+            let sign = if self.micros < 0 { "-" } else { "" };
+            let micros = self.micros.unsigned_abs();
+            let seconds = micros % MICROS_PER_MINUTE as u64;
+            let mut time = format!(
+                "{}{:02}:{:02}:{:02}",
+                sign,
+                micros / MICROS_PER_HOUR as u64,
+                micros % MICROS_PER_HOUR as u64 / MICROS_PER_MINUTE as u64,
+                seconds / MICROS_PER_SECOND as u64
+            );
+            let frac = seconds % MICROS_PER_SECOND as u64;
+            if frac != 0 {
+                time.push_str(format!(".{:06}", frac).trim_end_matches('0'));
+            }
+            parts.push(time);
+        }
+
+        f.write_str(&parts.join(" "))
+    }
+}
+
+impl<'a> FromSql<'a> for PgInterval {
+    fn from_sql(_: &Type, raw: &'a [u8]) -> Result<Self, BoxError> {
+        Ok(PgInterval {
+            micros: read_i64(raw, 0)?,
+            days: read_i32(raw, 8)?,
+            months: read_i32(raw, 12)?,
+        })
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::INTERVAL
+    }
+}
+
+impl ToSql for PgInterval {
+    fn to_sql(&self, _: &Type, out: &mut BytesMut) -> Result<IsNull, BoxError> {
+        out.put_i64(self.micros);
+        out.put_i32(self.days);
+        out.put_i32(self.months);
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::INTERVAL
+    }
+
+    to_sql_checked!();
+}
+
+/// An INET or CIDR address with its netmask length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgInet {
+    pub addr: IpAddr,
+    pub netmask: u8,
+}
+
+impl PgInet {
+    fn max_netmask(addr: &IpAddr) -> u8 {
+        match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+
+    /// Parses `address[/netmask]`, the netmask is the full length of the address when absent.
+    pub fn parse(text: &str) -> Result<Self, BoxError> {
+        let (addr, netmask) = match text.trim().split_once('/') {
+            Some((addr, netmask)) => (addr, Some(netmask)),
+            None => (text.trim(), None),
+        };
+        let addr: IpAddr = addr.parse()?;
+        let max = Self::max_netmask(&addr);
+        let netmask = match netmask {
+            Some(netmask) => netmask.parse()?,
+            None => max,
+        };
+        if netmask > max {
+            return Err(format!("invalid netmask of {}", text).into());
+        }
+
+        Ok(PgInet { addr, netmask })
+    }
+
+    /// Postgres omits the netmask of an INET host address, but not of a CIDR.
+    pub fn to_text(self, cidr: bool) -> String {
+        if !cidr && self.netmask == Self::max_netmask(&self.addr) {
+            self.addr.to_string()
+        } else {
+            format!("{}/{}", self.addr, self.netmask)
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for PgInet {
+    fn from_sql(_: &Type, raw: &'a [u8]) -> Result<Self, BoxError> {
+        let header = raw.get(..4).ok_or("invalid buffer size")?;
+        let (family, netmask, len) = (header[0], header[1], header[3] as usize);
+        let bytes = raw.get(4..4 + len).ok_or("invalid buffer size")?;
+        let addr = match family {
+            PGSQL_AF_INET => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(bytes)?)),
+            PGSQL_AF_INET6 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(bytes)?)),
+            _ => return Err(format!("invalid address family: {}", family).into()),
+        };
+
+        Ok(PgInet { addr, netmask })
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::INET || *ty == Type::CIDR
+    }
+}
+
+impl ToSql for PgInet {
+    fn to_sql(&self, ty: &Type, out: &mut BytesMut) -> Result<IsNull, BoxError> {
+        let is_cidr = *ty == Type::CIDR;
+        match self.addr {
+            IpAddr::V4(addr) => {
+                out.put_slice(&[PGSQL_AF_INET, self.netmask, is_cidr as u8, 4]);
+                out.put_slice(&addr.octets());
+            }
+            IpAddr::V6(addr) => {
+                out.put_slice(&[PGSQL_AF_INET6, self.netmask, is_cidr as u8, 16]);
+                out.put_slice(&addr.octets());
+            }
+        }
+
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::INET || *ty == Type::CIDR
+    }
+
+    to_sql_checked!();
+}
+
+/// A label of a user defined enum, sent and received as its text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgEnum(pub String);
+
+impl<'a> FromSql<'a> for PgEnum {
+    fn from_sql(_: &Type, raw: &'a [u8]) -> Result<Self, BoxError> {
+        Ok(PgEnum(std::str::from_utf8(raw)?.to_string()))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(ty.kind(), Kind::Enum(_))
+    }
+}
+
+impl ToSql for PgEnum {
+    fn to_sql(&self, _: &Type, out: &mut BytesMut) -> Result<IsNull, BoxError> {
+        out.put_slice(self.0.as_bytes());
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(ty.kind(), Kind::Enum(_))
+    }
+
+    to_sql_checked!();
+}
+
+/// The fields of a composite value in the order of the composite type, each already mapped to
+/// the type of its field.
+#[derive(Debug)]
+pub struct PgComposite(pub Vec<Box<dyn ToSql + Sync>>);
+
+impl ToSql for PgComposite {
+    fn to_sql(&self, ty: &Type, out: &mut BytesMut) -> Result<IsNull, BoxError> {
+        let fields = match ty.kind() {
+            Kind::Composite(fields) if fields.len() == self.0.len() => fields,
+            _ => return Err(format!("the fields don't match the composite type {}", ty).into()),
+        };
+
+        out.put_i32(fields.len().try_into()?);
+        for (field, value) in fields.iter().zip(&self.0) {
+            out.put_u32(field.type_().oid());
+            let len_pos = out.len();
+            out.put_i32(0);
+            let len = match value.to_sql_checked(field.type_(), out)? {
+                IsNull::Yes => -1,
+                IsNull::No => (out.len() - len_pos - 4).try_into()?,
+            };
+            out[len_pos..len_pos + 4].copy_from_slice(&i32::to_be_bytes(len));
+        }
+
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(ty.kind(), Kind::Composite(_))
+    }
+
+    to_sql_checked!();
+}
+
+/// Splits a composite value into the raw value of each field, none for NULL.
+pub fn composite_fields(raw: &[u8]) -> Result<Vec<Option<&[u8]>>, BoxError> {
+    let count = read_i32(raw, 0)?;
+    let mut pos = 4;
+    let mut fields = Vec::with_capacity(count.max(0) as usize);
+    for _ in 0..count {
+        // Skips the oid of the field type, the type is known from the composite type.
+        let len = read_i32(raw, pos + 4)?;
+        pos += 8;
+        if len < 0 {
+            fields.push(None);
+        } else {
+            let end = pos + len as usize;
+            fields.push(Some(raw.get(pos..end).ok_or("invalid buffer size")?));
+            pos = end;
+        }
+    }
+
+    Ok(fields)
+}
@@ -1,4 +1,11 @@
-use std::cell::RefCell;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use oracle::Connection;
 
 use crate::{
     core::postgres_param_mapper::map_to_sql,
@@ -10,23 +17,26 @@ use crate::{
 };
 use anyhow::{anyhow, Result};
 use crossbeam::thread;
-use futures::task::SpawnExt;
+use futures::{lock::Mutex as AsyncMutex, task::SpawnExt};
 use oracle::sql_type::ToSql as oracle_ToSql;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tauri::async_runtime;
-use tokio_postgres::{types::ToSql as pg_ToSql, Statement as PgStmt};
+use tokio_postgres::{types::ToSql as pg_ToSql, Client, Statement};
 
 use crate::{
     handlers::query_runner::Query,
     proxies::{
-        postgres::PostgresProxy,
-        sql_common::{SQLError, SQLResultSet},
+        connection_pool::Semaphore,
+        postgres::{PooledClient, PostgresProxy},
+        run_registry::{self, CancelHandle, StatementTimer},
+        sql_common::{DBType, FetchWindow, SQLError, SQLResultSet, StatementKind},
     },
 };
 
 use super::{
     oracle_param_mapper::map_param,
+    param_source::first_column_values,
     parameter_iterator::{DBParamIter, ParamSeeds, ParameterIterator},
 };
 
@@ -36,9 +46,130 @@ pub enum ParameterGenerateStrategy {
     Cartesian,
 }
 
+/// How a scan handles the statements which may change data.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ScanSafetyMode {
+    /// Statements run as they are, the changes are left in the console transaction like the
+    /// changes of the statements run from the console.
+    Unrestricted,
+    /// Only queries are scanned, the other statements are rejected before running.
+    ReadOnly,
+    /// The whole scan runs in one transaction per profile, on a connection dedicated to the scan,
+    /// and the transaction is rolled back once the scan ends.
+    Rollback,
+}
+
+impl Default for ScanSafetyMode {
+    fn default() -> Self {
+        ScanSafetyMode::Unrestricted
+    }
+}
+
+impl ScanSafetyMode {
+    /// Rejects the statement if it can't be scanned safely in this mode.
+    pub fn check(&self, kind: StatementKind, db_type: DBType) -> Result<(), SQLError> {
+        let allowed = match self {
+            ScanSafetyMode::Unrestricted => true,
+            ScanSafetyMode::ReadOnly => kind == StatementKind::Query,
+            ScanSafetyMode::Rollback => kind.is_transactional(db_type),
+        };
+
+        if allowed {
+            Ok(())
+        } else {
+            Err(SQLError::new(format!(
+                "The {:?} statement is rejected by the {:?} scan mode.",
+                kind, self
+            )))
+        }
+    }
+
+    /// The statement opening the transaction wrapping each Postgres statement run on a pooled
+    /// connection.
+    fn postgres_begin(&self) -> Option<&'static str> {
+        match self {
+            ScanSafetyMode::Unrestricted => None,
+            ScanSafetyMode::ReadOnly => Some("BEGIN READ ONLY"),
+            ScanSafetyMode::Rollback => Some("BEGIN"),
+        }
+    }
+}
+
+/// The scan connections of the rollback mode, opened once per profile on first use. They run the
+/// statements of all the schemas of the scan in one transaction.
+#[derive(Default)]
+pub struct ScanTransaction {
+    oracle: Mutex<HashMap<String, Arc<Connection>>>,
+    postgres: Mutex<HashMap<String, Arc<AsyncMutex<PooledClient>>>>,
+}
+
+impl ScanTransaction {
+    fn oracle_conn(&self, profile: &str) -> Result<Arc<Connection>, SQLError> {
+        let mut conns = self.oracle.lock().unwrap();
+        if let Some(conn) = conns.get(profile) {
+            return Ok(Arc::clone(conn));
+        }
+
+        let conn = crate::proxies::oracle::get_proxy(profile)?.open_scan_conn()?;
+        conns.insert(profile.to_string(), Arc::clone(&conn));
+        Ok(conn)
+    }
+
+    fn postgres_conn(&self, profile: &str) -> Result<Arc<AsyncMutex<PooledClient>>, SQLError> {
+        let mut conns = self.postgres.lock().unwrap();
+        if let Some(conn) = conns.get(profile) {
+            return Ok(Arc::clone(conn));
+        }
+
+        let conn = crate::proxies::postgres::get_proxy(profile)?.open_scan_conn()?;
+        let conn = Arc::new(AsyncMutex::new(conn));
+        conns.insert(profile.to_string(), Arc::clone(&conn));
+        Ok(conn)
+    }
+
+    /// Rolls back the changes of the scan and closes its connections.
+    pub fn rollback(&self) {
+        for (profile, conn) in self.oracle.lock().unwrap().drain() {
+            if let Err(e) = conn.rollback() {
+                log::error!("failed to roll back the oracle scan of {}: {}", profile, e);
+            }
+        }
+
+        let postgres_conns: Vec<_> = self.postgres.lock().unwrap().drain().collect();
+        if postgres_conns.is_empty() {
+            return;
+        }
+        let handle = async_runtime::handle();
+        std::thread::spawn(move || {
+            handle.block_on(async {
+                for (profile, conn) in postgres_conns {
+                    if let Err(e) = conn.lock().await.client().batch_execute("ROLLBACK").await {
+                        log::error!(
+                            "failed to roll back the postgres scan of {}: {}",
+                            profile,
+                            e
+                        );
+                    }
+                }
+            })
+        })
+        .join()
+        .unwrap()
+    }
+}
+
 pub struct QueryScanner<'a> {
     params_iter: DBParamIter<'a>,
     query: &'a Query,
+    run_id: Option<&'a str>,
+    timeout: Option<Duration>,
+    /// Limits the statements of the whole scan running at the same time.
+    permits: Option<&'a Semaphore>,
+    safety_mode: ScanSafetyMode,
+    statement_kind: StatementKind,
+    /// Runs every statement in the transaction of the scan, in the rollback mode.
+    transaction: Option<&'a ScanTransaction>,
     drained: bool,
     finished: usize,
 }
@@ -56,15 +187,34 @@ impl<'a> Iterator for QueryScanner<'a> {
 }
 
 impl<'a> QueryScanner<'a> {
-    pub fn new(query: &'a Query, params_iter: DBParamIter<'a>) -> Self {
+    pub fn new(
+        query: &'a Query,
+        params_iter: DBParamIter<'a>,
+        run_id: Option<&'a str>,
+        timeout: Option<Duration>,
+        permits: Option<&'a Semaphore>,
+        safety_mode: ScanSafetyMode,
+        statement_kind: StatementKind,
+    ) -> Self {
         Self {
             params_iter,
             drained: false,
             finished: 0,
             query,
+            run_id,
+            timeout,
+            permits,
+            safety_mode,
+            statement_kind,
+            transaction: None,
         }
     }
 
+    pub fn with_transaction(mut self, transaction: Option<&'a ScanTransaction>) -> Self {
+        self.transaction = transaction;
+        self
+    }
+
     pub fn total(&self) -> usize {
         let params_iter = &self.params_iter;
         match params_iter {
@@ -88,7 +238,10 @@ impl<'a> QueryScanner<'a> {
                 .enumerate()
                 .map(|(i, p)| {
                     let index = cur_idx[i];
-                    p.get(index).unwrap_or(&Value::Null).clone()
+                    p.values()
+                        .and_then(|values| values.get(index))
+                        .unwrap_or(&Value::Null)
+                        .clone()
                 })
                 .collect(),
         )
@@ -96,6 +249,7 @@ impl<'a> QueryScanner<'a> {
 
     fn next_result(&mut self) -> Result<SQLResultSet, SQLError> {
         let param_iter = &self.params_iter;
+        let _permit = self.permits.map(|permits| permits.acquire());
         let result = match param_iter {
             DBParamIter::Oracle(params_iter) => self.next_oracle_result(params_iter),
             DBParamIter::Postgres(params_iter) => self.next_postgres_result(params_iter),
@@ -108,15 +262,20 @@ impl<'a> QueryScanner<'a> {
         result
     }
 
+    /// Only the queries run on the pooled connections, the other statements run in the console
+    /// transaction like the statements run from the console.
+    fn uses_console(&self) -> bool {
+        self.statement_kind != StatementKind::Query
+            && self.safety_mode == ScanSafetyMode::Unrestricted
+    }
+
     fn next_oracle_result(
         &self,
         params_iter: &RefCell<ParameterIterator<Box<dyn oracle_ToSql>, String>>,
     ) -> Result<SQLResultSet, SQLError> {
         let mut param_iter_ref = params_iter.borrow_mut();
         let next_params_opt = param_iter_ref.next();
-        let client = crate::proxies::oracle::get_proxy();
-        let mut manager = client.get_console_manager()?;
-        let conn = manager.get_console_conn()?;
+        let client = crate::proxies::oracle::get_proxy(self.query.profile())?;
         let next_params = match next_params_opt {
             Some(params) => params,
             None => vec![],
@@ -128,12 +287,47 @@ impl<'a> QueryScanner<'a> {
 
         let stmt = param_iter_ref.prepared_stmt();
 
-        OracleClient::execute_stmt_mapped(stmt, &params_unboxed, conn)
+        if let Some(transaction) = self.transaction {
+            let conn = transaction.oracle_conn(self.query.profile())?;
+            return self.execute_oracle_stmt(stmt, &params_unboxed, &conn);
+        }
+
+        if self.uses_console() {
+            let mut manager = client.get_console_manager()?;
+            let conn = manager.get_console_conn()?;
+            let result = self.execute_oracle_stmt(stmt, &params_unboxed, conn);
+            if let Ok(rs) = &result {
+                manager.track_result(self.statement_kind, rs);
+            }
+            return result;
+        }
+
+        let conn = client.get_pooled_conn()?;
+        let result = self.execute_oracle_stmt(stmt, &params_unboxed, &conn);
+        // Pooled connections are shared by the scans, nothing is left pending on them.
+        let ended = conn.rollback().map_err(SQLError::from);
+        ended.and(result)
+    }
+
+    fn execute_oracle_stmt(
+        &self,
+        stmt: &str,
+        params: &[&dyn oracle_ToSql],
+        conn: &Arc<Connection>,
+    ) -> Result<SQLResultSet, SQLError> {
+        let cancel_handle = CancelHandle::Oracle(Arc::clone(conn));
+        let timer = self
+            .timeout
+            .map(|t| StatementTimer::start(cancel_handle.clone(), t));
+        let _guard = run_registry::attach_statement(self.run_id, cancel_handle);
+
+        let result = OracleClient::execute_stmt_mapped(stmt, params, conn, FetchWindow::default());
+        map_timeout(result, timer, self.timeout)
     }
 
     fn next_postgres_result(
         &self,
-        params_iter: &RefCell<ParameterIterator<Box<dyn pg_ToSql + Sync>, PgStmt>>,
+        params_iter: &RefCell<ParameterIterator<Box<dyn pg_ToSql + Sync>, String>>,
     ) -> Result<SQLResultSet, SQLError> {
         let mut param_iter_ref = params_iter.borrow_mut();
         let next_params_opt = param_iter_ref.next();
@@ -143,18 +337,91 @@ impl<'a> QueryScanner<'a> {
             params_unboxed.push(param_boxed.as_ref().to_owned());
         }
 
-        let prepared_stmt = param_iter_ref.prepared_stmt();
+        let stmt = param_iter_ref.prepared_stmt();
+        // The scanner holds RefCells, only the plain values are borrowed into the runtime thread.
+        let (profile, run_id, timeout) = (self.query.profile(), self.run_id, self.timeout);
+        let begin = self.safety_mode.postgres_begin();
+        let (uses_console, statement_kind) = (self.uses_console(), self.statement_kind);
+        let transaction = self.transaction;
         let handle = async_runtime::handle();
         thread::scope(|s| {
             s.spawn(|_| {
+                if let Some(transaction) = transaction {
+                    let conn = transaction.postgres_conn(profile)?;
+                    return handle.block_on(async {
+                        let mut conn = conn.lock().await;
+                        // A failed statement aborts the whole transaction, so each statement is
+                        // wrapped in a savepoint rolled back on failure.
+                        conn.client()
+                            .batch_execute("SAVEPOINT scan_statement")
+                            .await?;
+                        let result = match conn.prepare(stmt).await {
+                            Ok(prepared_stmt) => {
+                                execute_postgres_stmt(
+                                    &prepared_stmt,
+                                    &params_unboxed,
+                                    conn.client(),
+                                    conn.cancel_handle(),
+                                    run_id,
+                                    timeout,
+                                )
+                                .await
+                            }
+                            Err(e) => Err(e),
+                        };
+                        let end = if result.is_ok() {
+                            "RELEASE SAVEPOINT scan_statement"
+                        } else {
+                            "ROLLBACK TO SAVEPOINT scan_statement"
+                        };
+                        conn.client().batch_execute(end).await?;
+                        result
+                    });
+                }
+
+                let proxy = crate::proxies::postgres::get_proxy(profile)?;
+                if uses_console {
+                    return handle.block_on(async {
+                        let mut manager = proxy.get_console_manager().await?;
+                        let (client, cancel_handle) =
+                            manager.get_console_conn_with_cancel().await?;
+                        let prepared_stmt = client.prepare(stmt).await?;
+                        let result = execute_postgres_stmt(
+                            &prepared_stmt,
+                            &params_unboxed,
+                            client,
+                            cancel_handle,
+                            run_id,
+                            timeout,
+                        )
+                        .await;
+                        if let Ok(rs) = &result {
+                            manager.track_result(statement_kind, rs);
+                        }
+                        result
+                    });
+                }
+
+                let mut conn = proxy.get_pooled_conn()?;
+                log::debug!("got pooled postgres connection.");
                 handle.block_on(async {
-                    log::debug!("got postgres runtime lock.");
-                    let proxy = crate::proxies::postgres::get_proxy();
-                    let mut manager = proxy.get_console_manager().await?;
-                    log::debug!("got postgres proxy lock.");
-                    let client = manager.get_console_conn().await?;
-                    log::debug!("got postgres connection lock.");
-                    PostgresProxy::execute_prepared(prepared_stmt, &params_unboxed, client).await
+                    let prepared_stmt = conn.prepare(stmt).await?;
+                    if let Some(begin) = begin {
+                        conn.client().batch_execute(begin).await?;
+                    }
+                    let result = execute_postgres_stmt(
+                        &prepared_stmt,
+                        &params_unboxed,
+                        conn.client(),
+                        conn.cancel_handle(),
+                        run_id,
+                        timeout,
+                    )
+                    .await;
+                    if begin.is_some() {
+                        conn.client().batch_execute("ROLLBACK").await?;
+                    }
+                    result
                 })
             })
             .join()
@@ -178,18 +445,41 @@ impl<'a> QueryScanner<'a> {
         }
     }
 
-    pub fn map_oracle_param_seeds(schema: String, query: &Query) -> Result<ParamSeeds> {
+    /// Resolves and maps the parameters of the query, and classifies its statement.
+    pub fn map_oracle_param_seeds(
+        schema: String,
+        query: &Query,
+    ) -> Result<(ParamSeeds, StatementKind)> {
         let emptry_params = vec![];
         let params = query.parameters().unwrap_or(&emptry_params);
-        let processed_stmt = process_oracle_statement(query.statement(), &schema)?;
-
-        let proxy = crate::proxies::oracle::get_proxy();
-        let mut manager = proxy.get_console_manager()?;
-        let conn = manager.get_console_conn()?;
+        let proxy = crate::proxies::oracle::get_proxy(query.profile())?;
+        let config = proxy.config();
+        let placeholders = config.schema_placeholders();
+        let processed_stmt = process_oracle_statement(query.statement(), &schema, &placeholders)?;
+        let conn = proxy.get_pooled_conn()?;
+        let kind = OracleClient::classify_statement(&processed_stmt, &conn)?;
+
+        let mut resolved_params = Vec::with_capacity(params.len());
+        for seed in params {
+            let values = match seed.query() {
+                Some(seed_stmt) => {
+                    let seed_stmt = process_oracle_statement(seed_stmt, &schema, &placeholders)?;
+                    check_seed_query(OracleClient::classify_statement(&seed_stmt, &conn)?)?;
+                    first_column_values(OracleClient::execute_stmt_mapped(
+                        &seed_stmt,
+                        &[],
+                        &conn,
+                        FetchWindow::default(),
+                    )?)?
+                }
+                None => seed.resolve()?,
+            };
+            resolved_params.push(values);
+        }
 
         let mut mapped_params = Vec::with_capacity(params.len());
 
-        for (i, pp) in params.iter().enumerate() {
+        for (i, pp) in resolved_params.iter().enumerate() {
             let mut mapped_p = Vec::with_capacity(pp.len());
             for p in pp {
                 mapped_p.push(map_param(Some(&processed_stmt), Some(i), p, &conn)?)
@@ -197,18 +487,27 @@ impl<'a> QueryScanner<'a> {
             mapped_params.push(mapped_p);
         }
 
-        Ok(ParamSeeds::Oracle(processed_stmt, mapped_params))
+        Ok((
+            ParamSeeds::Oracle(processed_stmt, resolved_params, mapped_params),
+            kind,
+        ))
     }
 
-    pub async fn map_postgres_param_seeds(schema: String, query: &Query) -> Result<ParamSeeds> {
+    /// Resolves and maps the parameters of the query, and classifies its statement.
+    pub async fn map_postgres_param_seeds(
+        schema: String,
+        query: &Query,
+    ) -> Result<(ParamSeeds, StatementKind)> {
         let emptry_params = vec![];
 
-        let proxy = crate::proxies::postgres::get_proxy();
-        let mut manager = proxy.get_console_manager().await?;
-        let client = manager.get_console_conn().await?;
+        let proxy = crate::proxies::postgres::get_proxy(query.profile())?;
+        let mut conn = proxy.get_pooled_conn()?;
+        let config = proxy.config();
+        let placeholders = config.schema_placeholders();
 
-        let processed_stmt = process_pg_statement(query.statement(), &schema)?;
-        let prepared_stmt = client.prepare(&processed_stmt).await?;
+        let processed_stmt = process_pg_statement(query.statement(), &schema, &placeholders)?;
+        let kind = PostgresProxy::classify_statement(&processed_stmt);
+        let prepared_stmt = conn.prepare(&processed_stmt).await?;
         let params = query.parameters().unwrap_or(&emptry_params);
         let param_types = prepared_stmt.params();
 
@@ -220,9 +519,30 @@ impl<'a> QueryScanner<'a> {
             ));
         }
 
+        let mut resolved_params = Vec::with_capacity(params.len());
+        for seed in params {
+            let values = match seed.query() {
+                Some(seed_stmt) => {
+                    let seed_stmt = process_pg_statement(seed_stmt, &schema, &placeholders)?;
+                    check_seed_query(PostgresProxy::classify_statement(&seed_stmt))?;
+                    first_column_values(
+                        PostgresProxy::execute_string_statement(
+                            &seed_stmt,
+                            &[],
+                            conn.client(),
+                            FetchWindow::default(),
+                        )
+                        .await?,
+                    )?
+                }
+                None => seed.resolve()?,
+            };
+            resolved_params.push(values);
+        }
+
         let mut mapped_params = Vec::with_capacity(params.len());
 
-        for (i, param_list) in params.iter().enumerate() {
+        for (i, param_list) in resolved_params.iter().enumerate() {
             let mut mapped_param_list = Vec::with_capacity(param_list.len());
 
             for param in param_list {
@@ -231,6 +551,52 @@ impl<'a> QueryScanner<'a> {
             mapped_params.push(mapped_param_list);
         }
 
-        Ok(ParamSeeds::Postgres(prepared_stmt, mapped_params))
+        Ok((
+            ParamSeeds::Postgres(processed_stmt, resolved_params, mapped_params),
+            kind,
+        ))
+    }
+}
+
+/// The seed queries are run whatever the safety mode of the scan is, so only queries are allowed.
+fn check_seed_query(kind: StatementKind) -> Result<()> {
+    if kind != StatementKind::Query {
+        return Err(anyhow!(
+            "The parameter source statement must be a query, got a {:?} statement.",
+            kind
+        ));
+    }
+
+    Ok(())
+}
+
+async fn execute_postgres_stmt(
+    prepared_stmt: &Statement,
+    params: &[&(dyn pg_ToSql + Sync)],
+    client: &Client,
+    cancel_handle: CancelHandle,
+    run_id: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<SQLResultSet, SQLError> {
+    let timer = timeout.map(|t| StatementTimer::start(cancel_handle.clone(), t));
+    let _guard = run_registry::attach_statement(run_id, cancel_handle);
+
+    let result =
+        PostgresProxy::execute_prepared(prepared_stmt, params, client, FetchWindow::default())
+            .await;
+    map_timeout(result, timer, timeout)
+}
+
+/// Reports the failure of a statement cancelled by its timer as a timeout.
+fn map_timeout(
+    result: Result<SQLResultSet, SQLError>,
+    timer: Option<StatementTimer>,
+    timeout: Option<Duration>,
+) -> Result<SQLResultSet, SQLError> {
+    match (result, timer, timeout) {
+        (Err(_), Some(timer), Some(timeout)) if timer.timed_out() => {
+            Err(SQLError::new_timeout(timeout))
+        }
+        (result, _, _) => result,
     }
 }
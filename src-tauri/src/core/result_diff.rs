@@ -1,7 +1,16 @@
-use std::{cmp::Ordering, collections::HashMap};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, VecDeque},
+};
 
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::proxies::sql_common::SQLResultSet;
+
+use super::canonical_value::{CanonicalValue, EquivalenceRules};
+
 pub type DiffResults = HashMap<usize, Vec<usize>>;
 
 fn map_arr_len<E>(arr_opt: Option<&[E]>) -> usize {
@@ -14,7 +23,7 @@ fn first_cmp<E>(x: &&Option<&[Vec<E>]>, y: &&Option<&[Vec<E>]>) -> Ordering {
     x_len.cmp(&y_len)
 }
 
-pub fn diff(results: &[Option<&[Vec<Value>]>]) -> Option<DiffResults> {
+pub fn diff(results: &[Option<&[Vec<Value>]>], rules: &EquivalenceRules) -> Option<DiffResults> {
     let mut result: DiffResults = HashMap::new();
     if results.iter().any(|rst_opt| rst_opt.is_none()) {
         result.insert(usize::MAX, vec![]);
@@ -45,7 +54,7 @@ pub fn diff(results: &[Option<&[Vec<Value>]>]) -> Option<DiffResults> {
             .iter()
             .map(|rst| rst.map_or(&empty_row, |rs| rs.get(row).unwrap_or(&empty_row)))
             .collect();
-        let row_diff_rst = diff_row(&rows, max_col);
+        let row_diff_rst = diff_row(&rows, max_col, rules);
         if !row_diff_rst.is_empty() {
             result.insert(row, row_diff_rst);
         }
@@ -57,7 +66,7 @@ pub fn diff(results: &[Option<&[Vec<Value>]>]) -> Option<DiffResults> {
     Some(result)
 }
 
-fn diff_row(row_results: &[&Vec<Value>], max_col: usize) -> Vec<usize> {
+fn diff_row(row_results: &[&Vec<Value>], max_col: usize, rules: &EquivalenceRules) -> Vec<usize> {
     let mut row_diff_result = vec![];
 
     for col in 0..max_col {
@@ -69,7 +78,7 @@ fn diff_row(row_results: &[&Vec<Value>], max_col: usize) -> Vec<usize> {
 
         let all_eq = cell_values
             .iter()
-            .all(|cell| cell.map_or(false, |cv| cv.eq(*first_value)));
+            .all(|cell| cell.map_or(false, |cv| rules.equivalent(cv, first_value)));
         if !all_eq {
             row_diff_result.push(col);
         }
@@ -77,3 +86,311 @@ fn diff_row(row_results: &[&Vec<Value>], max_col: usize) -> Vec<usize> {
 
     row_diff_result
 }
+
+/// Options of the diff which matches the rows by key columns instead of by position.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyDiffOptions {
+    /// The names of the key columns, matched case-insensitively as Oracle upper-cases the
+    /// column names and Postgres lower-cases them.
+    pub key_columns: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnChange {
+    column: String,
+    left: Value,
+    right: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyedRow {
+    key: Vec<Value>,
+    row: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangedRow {
+    key: Vec<Value>,
+    left_row: usize,
+    right_row: usize,
+    changes: Vec<ColumnChange>,
+}
+
+/// The differences of a result from the first result, the rows matched by key.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyDiff {
+    /// The index of the result compared with the first result.
+    result_index: usize,
+    added_rows: Vec<KeyedRow>,
+    removed_rows: Vec<KeyedRow>,
+    changed_rows: Vec<ChangedRow>,
+    /// The columns of only one of the results, they aren't compared.
+    added_columns: Vec<String>,
+    removed_columns: Vec<String>,
+}
+
+impl KeyDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_rows.is_empty()
+            && self.removed_rows.is_empty()
+            && self.changed_rows.is_empty()
+            && self.added_columns.is_empty()
+            && self.removed_columns.is_empty()
+    }
+}
+
+/// Compares every result with the first one, the rows with the same key are compared column by
+/// column. Rows sharing a key are matched in order.
+pub fn diff_by_key(
+    results: &[&SQLResultSet],
+    options: &KeyDiffOptions,
+    rules: &EquivalenceRules,
+) -> Result<Vec<KeyDiff>> {
+    let (first, others) = match results.split_first() {
+        Some(split) => split,
+        None => return Ok(vec![]),
+    };
+
+    others
+        .iter()
+        .enumerate()
+        .map(|(i, other)| diff_pair(first, other, i + 1, options, rules))
+        .collect()
+}
+
+fn diff_pair(
+    left: &SQLResultSet,
+    right: &SQLResultSet,
+    result_index: usize,
+    options: &KeyDiffOptions,
+    rules: &EquivalenceRules,
+) -> Result<KeyDiff> {
+    let left_columns = left.columns().unwrap_or_default();
+    let right_columns = right.columns().unwrap_or_default();
+    let left_keys = key_indexes(left_columns, &options.key_columns)?;
+    let right_keys = key_indexes(right_columns, &options.key_columns)?;
+
+    let column_pairs: Vec<(usize, usize)> = left_columns
+        .iter()
+        .enumerate()
+        .filter_map(|(l, name)| column_index(right_columns, name).map(|r| (l, r)))
+        .collect();
+    let only_in = |columns: &[String], others: &[String]| -> Vec<String> {
+        columns
+            .iter()
+            .filter(|name| column_index(others, name).is_none())
+            .cloned()
+            .collect()
+    };
+
+    let empty_rows = vec![];
+    let left_rows = left.get_rows().as_ref().unwrap_or(&empty_rows);
+    let right_rows = right.get_rows().as_ref().unwrap_or(&empty_rows);
+
+    let mut right_by_key: HashMap<String, VecDeque<usize>> = HashMap::new();
+    for (r, row) in right_rows.iter().enumerate() {
+        right_by_key
+            .entry(key_text(row, &right_keys, rules))
+            .or_default()
+            .push_back(r);
+    }
+
+    let mut removed_rows = vec![];
+    let mut changed_rows = vec![];
+    for (l, left_row) in left_rows.iter().enumerate() {
+        let matched = right_by_key
+            .get_mut(&key_text(left_row, &left_keys, rules))
+            .and_then(VecDeque::pop_front);
+        let r = match matched {
+            Some(r) => r,
+            None => {
+                removed_rows.push(KeyedRow {
+                    key: key_values(left_row, &left_keys),
+                    row: l,
+                });
+                continue;
+            }
+        };
+
+        let right_row = &right_rows[r];
+        let changes: Vec<ColumnChange> = column_pairs
+            .iter()
+            .filter_map(|&(lc, rc)| {
+                let left_val = left_row.get(lc).unwrap_or(&Value::Null);
+                let right_val = right_row.get(rc).unwrap_or(&Value::Null);
+                if rules.equivalent(left_val, right_val) {
+                    return None;
+                }
+                Some(ColumnChange {
+                    column: left_columns[lc].clone(),
+                    left: left_val.clone(),
+                    right: right_val.clone(),
+                })
+            })
+            .collect();
+        if !changes.is_empty() {
+            changed_rows.push(ChangedRow {
+                key: key_values(left_row, &left_keys),
+                left_row: l,
+                right_row: r,
+                changes,
+            });
+        }
+    }
+
+    let mut added_rows: Vec<KeyedRow> = right_by_key
+        .values()
+        .flatten()
+        .map(|&r| KeyedRow {
+            key: key_values(&right_rows[r], &right_keys),
+            row: r,
+        })
+        .collect();
+    added_rows.sort_by_key(|row| row.row);
+
+    Ok(KeyDiff {
+        result_index,
+        added_rows,
+        removed_rows,
+        changed_rows,
+        added_columns: only_in(right_columns, left_columns),
+        removed_columns: only_in(left_columns, right_columns),
+    })
+}
+
+fn column_index(columns: &[String], name: &str) -> Option<usize> {
+    columns.iter().position(|c| c.eq_ignore_ascii_case(name))
+}
+
+fn key_indexes(columns: &[String], key_columns: &[String]) -> Result<Vec<usize>> {
+    if key_columns.is_empty() {
+        return Err(anyhow!("The key columns of the diff are required."));
+    }
+
+    key_columns
+        .iter()
+        .map(|key| {
+            column_index(columns, key)
+                .ok_or_else(|| anyhow!("The key column {} is not in the result.", key))
+        })
+        .collect()
+}
+
+fn key_values(row: &[Value], keys: &[usize]) -> Vec<Value> {
+    keys.iter()
+        .map(|&k| row.get(k).cloned().unwrap_or(Value::Null))
+        .collect()
+}
+
+/// The key as text, a number key matches the same number read as text but two texts only
+/// match when equal.
+fn key_text(row: &[Value], keys: &[usize], rules: &EquivalenceRules) -> String {
+    keys.iter()
+        .map(|&k| CanonicalValue::new(row.get(k).unwrap_or(&Value::Null), rules).key_text())
+        .collect::<Vec<String>>()
+        .join("\u{1f}")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn result_set(columns: &[&str], rows: Value) -> SQLResultSet {
+        let rows: Vec<Vec<Value>> = serde_json::from_value(rows).unwrap();
+        SQLResultSet::new(
+            rows.len(),
+            Some(columns.iter().map(|c| c.to_string()).collect()),
+            Some(rows),
+        )
+    }
+
+    #[test]
+    fn test_diff_across_databases() {
+        let oracle_rows = vec![vec![json!(1), json!("1.50"), json!("2022-01-30 00:00:00")]];
+        let postgres_rows = vec![vec![json!(true), json!(1.5), json!("2022-01-30")]];
+        let rules = EquivalenceRules::default();
+
+        assert_eq!(
+            diff(&[Some(&oracle_rows), Some(&postgres_rows)], &rules),
+            None
+        );
+
+        let postgres_rows = vec![vec![json!(true), json!(1.25), json!("2022-01-30")]];
+        assert_eq!(
+            diff(&[Some(&oracle_rows), Some(&postgres_rows)], &rules),
+            Some(HashMap::from([(0, vec![1])]))
+        );
+    }
+
+    #[test]
+    fn test_diff_by_key() {
+        let left = result_set(&["ID", "NAME"], json!([[1, "a"], [2, "b"], [3, "c"]]));
+        let right = result_set(&["name", "id"], json!([["a", 1], ["c", 3], ["x", 4]]));
+        let options = KeyDiffOptions {
+            key_columns: vec!["id".to_string()],
+        };
+
+        let diffs = diff_by_key(&[&left, &right], &options, &EquivalenceRules::default()).unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert!(diffs[0].changed_rows.is_empty());
+        assert_eq!(
+            diffs[0].removed_rows,
+            vec![KeyedRow {
+                key: vec![json!(2)],
+                row: 1
+            }]
+        );
+        assert_eq!(
+            diffs[0].added_rows,
+            vec![KeyedRow {
+                key: vec![json!(4)],
+                row: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_by_key_with_tolerance() {
+        let left = result_set(
+            &["ID", "PRICE", "UPDATED_AT"],
+            json!([
+                [1, 1.0, "2022-01-30 12:00:00.100"],
+                [2, 2.0, "2022-01-30 12:00:00"]
+            ]),
+        );
+        let right = result_set(
+            &["ID", "PRICE", "UPDATED_AT"],
+            json!([
+                [1, 1.0000001, "2022-01-30 12:00:00"],
+                [2, 2.5, "2022-01-30 12:00:00"]
+            ]),
+        );
+        let options = KeyDiffOptions {
+            key_columns: vec!["ID".to_string()],
+        };
+        let rules = EquivalenceRules {
+            float_tolerance: Some(0.001),
+            timestamp_tolerance_ms: Some(500),
+            ..Default::default()
+        };
+
+        let diffs = diff_by_key(&[&left, &right], &options, &rules).unwrap();
+        assert_eq!(diffs[0].changed_rows.len(), 1);
+        assert_eq!(
+            diffs[0].changed_rows[0].changes,
+            vec![ColumnChange {
+                column: "PRICE".to_string(),
+                left: json!(2.0),
+                right: json!(2.5)
+            }]
+        );
+        assert!(diff_by_key(&[&left, &right], &KeyDiffOptions::default(), &rules).is_err());
+    }
+}
@@ -0,0 +1,336 @@
+use std::{collections::HashSet, fs::File, io::BufWriter, io::Write, path::Path};
+
+use anyhow::Result;
+use async_graphql::Enum;
+use rust_xlsxwriter::{Color, Format, Workbook, Worksheet};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::proxies::{
+    query_runner::RunResults,
+    sql_common::{SQLResult, SQLResultSet},
+};
+
+use super::result_diff::DiffResults;
+
+/// The max length of a worksheet name allowed by Excel.
+const MAX_SHEET_NAME_LEN: usize = 31;
+const DIFF_CELL_COLOR: u32 = 0xFFC7CE;
+
+#[derive(Enum, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+    Xlsx,
+}
+
+/// The rows of a single query result, with the schema, query and parameters they came from.
+struct ExportTable<'a> {
+    schema: &'a str,
+    query_index: usize,
+    parameters: Option<&'a [Value]>,
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+    /// The cells which differ from the results of the other queries of the schema.
+    diff: Option<&'a DiffResults>,
+}
+
+impl<'a> ExportTable<'a> {
+    fn new(
+        schema: &'a str,
+        query_index: usize,
+        parameters: Option<&'a [Value]>,
+        result: Option<&SQLResult>,
+        diff: Option<&'a DiffResults>,
+    ) -> Self {
+        let (columns, rows) = match result {
+            Some(SQLResult::Error(e)) => {
+                (vec!["error".to_string()], vec![vec![e.message().into()]])
+            }
+            Some(result) => result.result_set().map_or((vec![], vec![]), |rs| {
+                (
+                    rs.columns().map_or(vec![], |c| c.to_vec()),
+                    rs.get_rows().clone().unwrap_or_default(),
+                )
+            }),
+            None => (vec![], vec![]),
+        };
+
+        Self {
+            schema,
+            query_index,
+            parameters,
+            columns,
+            rows,
+            diff,
+        }
+    }
+
+    fn diff_columns(&self, row: usize) -> &[usize] {
+        self.diff
+            .and_then(|diff| diff.get(&row))
+            .map_or(&[], |cols| cols.as_slice())
+    }
+
+    fn diff_column_names(&self, row: usize) -> Vec<&str> {
+        self.diff_columns(row)
+            .iter()
+            .map(|&col| self.columns.get(col).map_or("", String::as_str))
+            .collect()
+    }
+
+    fn parameters_text(&self) -> String {
+        self.parameters
+            .map_or(String::new(), |p| Value::from(p).to_string())
+    }
+}
+
+/// Writes the results of all the schemas of a scan, the schemas ordered by name.
+pub fn export_run_results(results: &RunResults, format: ExportFormat, path: &Path) -> Result<()> {
+    let mut schemas: Vec<_> = results.schema_results().iter().collect();
+    schemas.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut tables = Vec::new();
+    for (schema, schema_result) in schemas {
+        for (i, query_result) in schema_result.query_results().iter().enumerate() {
+            if let Some(qr) = query_result {
+                tables.push(ExportTable::new(
+                    schema,
+                    i,
+                    qr.parameters(),
+                    qr.results(),
+                    schema_result.diff_results(),
+                ));
+            }
+        }
+    }
+
+    write_tables(&tables, format, path)
+}
+
+pub fn export_result_set(
+    result_set: &SQLResultSet,
+    parameters: Option<&[Value]>,
+    format: ExportFormat,
+    path: &Path,
+) -> Result<()> {
+    let result = SQLResult::new_result(Some(result_set.clone()));
+    let table = ExportTable::new("", 0, parameters, Some(&result), None);
+    write_tables(&[table], format, path)
+}
+
+fn write_tables(tables: &[ExportTable], format: ExportFormat, path: &Path) -> Result<()> {
+    log::debug!(
+        "export {} results to {:?} as {:?}",
+        tables.len(),
+        path,
+        format
+    );
+    match format {
+        ExportFormat::Csv => write_csv(tables, path),
+        ExportFormat::Jsonl => write_jsonl(tables, path),
+        ExportFormat::Xlsx => write_xlsx(tables, path),
+    }
+}
+
+fn cell_text(cell: &Value) -> String {
+    match cell {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Each table starts with its own header row, as the queries have different columns. The
+/// diffed cells are listed by column name in the diff column.
+fn write_csv(tables: &[ExportTable], path: &Path) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_path(path)?;
+    for table in tables {
+        let header = ["schema", "query", "parameters", "diff"]
+            .iter()
+            .map(|h| h.to_string())
+            .chain(table.columns.iter().cloned());
+        writer.write_record(header)?;
+
+        for (r, row) in table.rows.iter().enumerate() {
+            let record = vec![
+                table.schema.to_string(),
+                (table.query_index + 1).to_string(),
+                table.parameters_text(),
+                table.diff_column_names(r).join(";"),
+            ]
+            .into_iter()
+            .chain(row.iter().map(cell_text));
+            writer.write_record(record)?;
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// A JSON object per row, with the cells keyed by column name.
+fn write_jsonl(tables: &[ExportTable], path: &Path) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for table in tables {
+        for (r, row) in table.rows.iter().enumerate() {
+            let cells: Map<String, Value> = table
+                .columns
+                .iter()
+                .cloned()
+                .zip(row.iter().cloned())
+                .collect();
+            let line = json!({
+                "schema": table.schema,
+                "query": table.query_index + 1,
+                "parameters": table.parameters,
+                "row": cells,
+                "diff": table.diff_column_names(r),
+            });
+            writeln!(writer, "{}", line)?;
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// A worksheet per table, the diffed cells are filled.
+fn write_xlsx(tables: &[ExportTable], path: &Path) -> Result<()> {
+    let mut workbook = Workbook::new();
+    let header_format = Format::new().set_bold();
+    let diff_format = Format::new().set_background_color(Color::RGB(DIFF_CELL_COLOR));
+    let mut sheet_names = HashSet::new();
+
+    for (t, table) in tables.iter().enumerate() {
+        let worksheet = workbook.add_worksheet();
+        let name = sheet_name(table, t, &sheet_names);
+        worksheet.set_name(name.as_str())?;
+        sheet_names.insert(name.to_lowercase());
+
+        worksheet.write_string_with_format(0, 0, "parameters", &header_format)?;
+        for (c, column) in table.columns.iter().enumerate() {
+            worksheet.write_string_with_format(0, c as u16 + 1, column, &header_format)?;
+        }
+        worksheet.set_freeze_panes(1, 0)?;
+
+        let parameters = table.parameters_text();
+        for (r, row) in table.rows.iter().enumerate() {
+            let xlsx_row = r as u32 + 1;
+            worksheet.write_string(xlsx_row, 0, &parameters)?;
+            let diff_columns = table.diff_columns(r);
+            for (c, cell) in row.iter().enumerate() {
+                let format = if diff_columns.contains(&c) {
+                    Some(&diff_format)
+                } else {
+                    None
+                };
+                write_xlsx_cell(worksheet, xlsx_row, c as u16 + 1, cell, format)?;
+            }
+        }
+    }
+
+    workbook.save(path)?;
+    Ok(())
+}
+
+fn write_xlsx_cell(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    cell: &Value,
+    format: Option<&Format>,
+) -> Result<()> {
+    let default_format = Format::new();
+    let format = format.unwrap_or(&default_format);
+    match cell {
+        Value::Number(num) if num.as_f64().is_some() => {
+            worksheet.write_number_with_format(row, col, num.as_f64().unwrap(), format)?
+        }
+        Value::Bool(b) => worksheet.write_boolean_with_format(row, col, *b, format)?,
+        Value::Null => worksheet.write_blank(row, col, format)?,
+        other => worksheet.write_string_with_format(row, col, &cell_text(other), format)?,
+    };
+
+    Ok(())
+}
+
+/// A unique worksheet name within the length limit, without the characters Excel rejects.
+/// A valid worksheet name of the table, `taken` holds the names in lowercase since the names of
+/// a workbook must differ regardless of the case.
+fn sheet_name(table: &ExportTable, index: usize, taken: &HashSet<String>) -> String {
+    let base = if table.schema.is_empty() {
+        format!("Result {}", table.query_index + 1)
+    } else {
+        format!("{} {}", table.schema, table.query_index + 1)
+    };
+    let base: String = base
+        .chars()
+        .map(|c| match c {
+            '[' | ']' | ':' | '*' | '?' | '/' | '\\' => '_',
+            c => c,
+        })
+        .take(MAX_SHEET_NAME_LEN)
+        .collect();
+
+    if !taken.contains(&base.to_lowercase()) {
+        return base;
+    }
+    let suffix = format!("~{}", index + 1);
+    let prefix: String = base
+        .chars()
+        .take(MAX_SHEET_NAME_LEN - suffix.len())
+        .collect();
+    format!("{}{}", prefix, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn test_write_csv_with_diff() {
+        let params = [json!(1)];
+        let diff: DiffResults = HashMap::from([(1, vec![1])]);
+        let table = ExportTable {
+            schema: "GREENCO",
+            query_index: 0,
+            parameters: Some(&params),
+            columns: vec!["ID".to_string(), "NAME".to_string()],
+            rows: vec![vec![json!(1), json!("a")], vec![json!(2), Value::Null]],
+            diff: Some(&diff),
+        };
+
+        let path = std::env::temp_dir().join(format!("result_export_{}.csv", Uuid::new_v4()));
+        write_csv(&[table], &path).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            content,
+            "schema,query,parameters,diff,ID,NAME\nGREENCO,1,[1],,1,a\nGREENCO,1,[1],NAME,2,\n"
+        );
+    }
+
+    #[test]
+    fn test_sheet_name() {
+        let table = ExportTable {
+            schema: "GREENCO",
+            query_index: 0,
+            parameters: None,
+            columns: Vec::new(),
+            rows: Vec::new(),
+            diff: None,
+        };
+        let mut taken = HashSet::new();
+        assert_eq!(sheet_name(&table, 0, &taken), "GREENCO 1");
+
+        taken.insert("greenco 1".to_string());
+        assert_eq!(sheet_name(&table, 1, &taken), "GREENCO 1~2");
+    }
+}
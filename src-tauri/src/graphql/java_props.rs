@@ -7,7 +7,7 @@ use crate::proxies::{
     app_state::{get_state, set_state, AppStateKey},
     java_props::{load_props, save_java_prop, PropKey, PropVal, ValidationStatus},
     rocksdb::{get_conn, RocksDataStore},
-    sql_common::{get_schema_stmt, SQLClient, SQLError, SQLResult},
+    sql_common::{SQLClient, SQLError, SQLResult},
 };
 
 #[derive(Default, SimpleObject)]
@@ -108,10 +108,22 @@ impl JavaPropsMutation {
         filepath: String,
         class_pattern: String,
         validate_queries: bool,
+        oracle_profile: Option<String>,
+        postgres_profile: Option<String>,
     ) -> Result<JavaPropsResponse> {
         let mut file_props_map = load_props(&filepath, &class_pattern)?;
         if validate_queries {
-            file_props_map = validate_sql_queries(&mut file_props_map).await?;
+            let (oracle_profile, postgres_profile) = match (oracle_profile, postgres_profile) {
+                (Some(op), Some(pp)) => (op, pp),
+                _ => {
+                    return Err(Error::new(
+                        "Both Oracle and Postgres profiles are required to validate queries.",
+                    ))
+                }
+            };
+            file_props_map =
+                validate_sql_queries(&mut file_props_map, &oracle_profile, &postgres_profile)
+                    .await?;
         }
         save_java_props(&file_props_map)?;
         load_java_porops_state(&file_props_map)
@@ -328,9 +340,15 @@ fn load_java_porops_state(
 
 async fn validate_sql_queries(
     file_props_map: &mut HashMap<String, HashMap<PropKey, PropVal>>,
+    oracle_profile: &str,
+    postgres_profile: &str,
 ) -> Result<HashMap<String, HashMap<PropKey, PropVal>>> {
-    let ora_proxy: &dyn SQLClient = crate::proxies::oracle::get_proxy();
-    let pg_proxy: &dyn SQLClient = crate::proxies::postgres::get_proxy();
+    let ora_client = crate::proxies::oracle::get_proxy(oracle_profile)?;
+    let pg_client = crate::proxies::postgres::get_proxy(postgres_profile)?;
+    let ora_config = ora_client.config();
+    let pg_config = pg_client.config();
+    let ora_proxy: &dyn SQLClient = &ora_client;
+    let pg_proxy: &dyn SQLClient = &pg_client;
     let mut new_file_props_map = HashMap::new();
 
     for (java_class, mut prop_key_val_map) in file_props_map.drain() {
@@ -343,14 +361,14 @@ async fn validate_sql_queries(
             validate_results.push(&mut prop_val.validation_error);
 
             if !ora_query.is_empty() {
-                ora_queries.push(get_schema_stmt("GREENCO", ora_query));
+                ora_queries.push(ora_config.default_schema_stmt(ora_query));
             } else {
                 ora_queries.push(String::new());
             }
             if !pg_query.is_empty() {
-                pg_queries.push(get_schema_stmt("GREENCO", pg_query));
+                pg_queries.push(pg_config.default_schema_stmt(pg_query));
             } else if !ora_query.is_empty() {
-                pg_queries.push(get_schema_stmt("GREENCO", ora_query));
+                pg_queries.push(pg_config.default_schema_stmt(ora_query));
             } else {
                 pg_queries.push(String::new());
             }
@@ -2,7 +2,10 @@ mod app_state;
 mod db_schema;
 mod java_props;
 mod log;
+mod query_runner;
+mod result_export;
 mod rocksdb;
+mod run_history;
 mod sql;
 mod sql_explain;
 mod sql_formatter;
@@ -19,9 +22,18 @@ use tokio_stream::StreamExt;
 use db_schema::DbSchemaQuery;
 use sql_formatter::SqlFormatterQuery;
 
+use crate::proxies::{
+    connection_health::{self, ConnectionStatusEvent},
+    credentials,
+    scan_progress::{self, ScanProgressEvent},
+};
+
 use self::java_props::{JavaPropsMutation, JavaPropsQuery};
 use self::log::LogMutation;
+use self::query_runner::QueryRunnerMutation;
+use self::result_export::ResultExportMutation;
 use self::rocksdb::{RocksDbMutation, RocksDbQuery};
+use self::run_history::{RunHistoryMutation, RunHistoryQuery};
 use self::sql::SqlMutation;
 use self::{
     app_state::{AppStateMutation, AppStateQuery},
@@ -29,12 +41,16 @@ use self::{
     sql_explain::SqlExplainQuery,
 };
 use warp::{http::Response as HttpResponse, Filter};
+
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
 #[derive(MergedObject, Default)]
 pub struct Query(
     AppStateQuery,
     DbSchemaQuery,
     JavaPropsQuery,
     RocksDbQuery,
+    RunHistoryQuery,
     SqlQuery,
     SqlExplainQuery,
     SqlFormatterQuery,
@@ -44,27 +60,43 @@ pub struct Query(
 pub struct Mutation(
     AppStateMutation,
     RocksDbMutation,
+    ResultExportMutation,
+    RunHistoryMutation,
     SqlMutation,
     JavaPropsMutation,
     LogMutation,
+    QueryRunnerMutation,
 );
 
 pub struct Subscription;
 
 #[Subscription]
 impl Subscription {
-    async fn integers(&self, #[graphql(default = 1)] step: i32) -> impl Stream<Item = i32> {
-        let mut value = 0;
-        tokio_stream::wrappers::IntervalStream::new(tokio::time::interval(Duration::from_secs(1)))
-            .map(move |_| {
-                value += step;
-                value
-            })
+    /// Status changes of the console connections, of all the profiles when no profile is given.
+    async fn connection_status(
+        &self,
+        profile: Option<String>,
+    ) -> impl Stream<Item = ConnectionStatusEvent> {
+        connection_health::subscribe()
+            .filter(move |event| profile.as_deref().map_or(true, |p| p == event.profile))
+    }
+
+    /// The progress and the schema results of the scans, of all the scans when no run id is given.
+    async fn scan_progress(&self, run_id: Option<String>) -> impl Stream<Item = ScanProgressEvent> {
+        scan_progress::subscribe()
+            .filter(move |event| run_id.as_deref().map_or(true, |id| id == event.run_id))
     }
 }
 
 #[tokio::main]
 pub async fn run_graphql_server(port: u16) {
+    if let Err(e) = credentials::recover_key_rotation() {
+        ::log::error!("failed to recover the credential key rotation: {}", e);
+    }
+    if let Err(e) = credentials::migrate_stored_credentials() {
+        ::log::error!("failed to encrypt the stored credentials: {}", e);
+    }
+    connection_health::start_monitor(HEALTH_CHECK_INTERVAL);
     let schema = Schema::build(Query::default(), Mutation::default(), Subscription).finish();
 
     let graphql_post =
@@ -0,0 +1,33 @@
+use std::sync::Arc;
+
+use crate::handlers::query_runner::{self, ScanInputs};
+use crate::proxies::{query_runner::RunResults, scan_progress::SubscriptionSink};
+use async_graphql::{Json, Object, Result};
+use uuid::Uuid;
+
+#[derive(Default)]
+pub struct QueryRunnerMutation;
+
+#[Object]
+impl QueryRunnerMutation {
+    /// Runs the scan and saves it to the run history, the progress is published to the scan
+    /// progress subscription under the run id.
+    async fn scan_queries(
+        &self,
+        inputs: Json<ScanInputs>,
+        run_id: Option<String>,
+    ) -> Result<Json<RunResults>> {
+        let inputs = inputs.0;
+        inputs.validate()?;
+        let run_id = run_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+        let sink = Arc::new(SubscriptionSink::new(&run_id));
+
+        // The scan blocks until every schema finished, so it's kept off the async workers.
+        let run = tokio::task::spawn_blocking(move || {
+            query_runner::run_scan(sink, Some(run_id), inputs, None)
+        })
+        .await?;
+
+        Ok(Json(run.into_results()))
+    }
+}
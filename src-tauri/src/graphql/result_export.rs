@@ -0,0 +1,42 @@
+use std::path::Path;
+
+use crate::core::result_export::{self, ExportFormat};
+use crate::proxies::{run_history, sql_common::SQLResultSet};
+use async_graphql::{Json, Object, Result};
+
+#[derive(Default)]
+pub struct ResultExportMutation;
+
+#[Object]
+impl ResultExportMutation {
+    /// Writes the results of a stored scan run to the file, with the diffed cells highlighted.
+    async fn export_scan_run(
+        &self,
+        run_id: String,
+        format: ExportFormat,
+        path: String,
+    ) -> Result<bool> {
+        let run = run_history::load_run(&run_id)?
+            .ok_or_else(|| format!("The scan run {} is not found.", run_id))?;
+        result_export::export_run_results(run.results(), format, Path::new(&path))?;
+        Ok(true)
+    }
+
+    async fn export_result_set(
+        &self,
+        result_set: Json<SQLResultSet>,
+        parameters: Option<Vec<Json<serde_json::Value>>>,
+        format: ExportFormat,
+        path: String,
+    ) -> Result<bool> {
+        let parameters: Option<Vec<serde_json::Value>> =
+            parameters.map(|params| params.into_iter().map(|p| p.0).collect());
+        result_export::export_result_set(
+            &result_set,
+            parameters.as_deref(),
+            format,
+            Path::new(&path),
+        )?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,29 @@
+use crate::proxies::run_history::{self, ScanRun, ScanRunSummary};
+use async_graphql::{Json, Object, Result};
+
+#[derive(Default)]
+pub struct RunHistoryQuery;
+
+#[Object]
+impl RunHistoryQuery {
+    /// The stored scan runs, the latest first.
+    async fn scan_runs(&self) -> Result<Vec<ScanRunSummary>> {
+        run_history::list_runs().map_err(|e| e.into())
+    }
+
+    /// The inputs and results of a stored scan run.
+    async fn scan_run(&self, run_id: String) -> Result<Option<Json<ScanRun>>> {
+        Ok(run_history::load_run(&run_id)?.map(Json))
+    }
+}
+
+#[derive(Default)]
+pub struct RunHistoryMutation;
+
+#[Object]
+impl RunHistoryMutation {
+    async fn delete_scan_run(&self, run_id: String) -> Result<bool> {
+        run_history::delete_run(&run_id)?;
+        Ok(true)
+    }
+}
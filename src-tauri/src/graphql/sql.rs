@@ -1,6 +1,10 @@
-use crate::proxies::oracle;
-use crate::proxies::postgres::{self};
-use crate::proxies::sql_common::{get_schema_stmt, Config, DBType, SQLClient, SQLResult};
+use crate::proxies::connection_profile::{self, ConnectionProfile, ConnectionProfileInfo};
+use crate::proxies::credentials::{self, CredentialStatus};
+use crate::proxies::run_registry;
+use crate::proxies::sql_common::{
+    bind_parameters, get_schema_stmt, Config, DBType, SQLClient, SQLResult, SavePoint,
+    TransactionStatus,
+};
 use async_graphql::*;
 
 #[derive(Default)]
@@ -10,13 +14,19 @@ pub struct SqlQuery;
 impl SqlQuery {
     async fn execute_stmt(
         &self,
-        db_type: DBType,
+        profile: String,
         schema: String,
         stmt: String,
         params: Vec<Json<serde_json::Value>>,
         with_statistics: bool,
+        page_size: Option<usize>,
+        run_id: Option<String>,
+        named_params: Option<Json<serde_json::Map<String, serde_json::Value>>>,
     ) -> Result<Json<SQLResult>> {
-        let task_fn = |proxy: &'static dyn SQLClient| -> anyhow::Result<SQLResult> {
+        let client = connection_profile::get_client(&profile)?;
+        let profile_config = client.config();
+        let db_type = named_params.as_ref().map(|_| client.db_type());
+        let task_fn = |proxy: &dyn SQLClient| -> anyhow::Result<SQLResult> {
             let params_json: Vec<serde_json::Value> = params
                 .iter()
                 .map(|param| {
@@ -25,30 +35,76 @@ impl SqlQuery {
                         .unwrap()
                 })
                 .collect();
+            let schema_stmt =
+                get_schema_stmt(&schema, &stmt, &profile_config.schema_placeholders());
+            let (schema_stmt, params_json) = match (&named_params, db_type) {
+                (Some(Json(named)), Some(db_type)) => {
+                    bind_parameters(&schema_stmt, db_type, &params_json, named)?
+                }
+                _ => (schema_stmt, params_json),
+            };
+            let run_id = run_id.as_deref();
 
-            proxy.execute_stmt(
-                &get_schema_stmt(&schema, &stmt),
-                &params_json,
-                with_statistics,
-            )
+            match page_size {
+                Some(size) if !with_statistics => {
+                    proxy.execute_stmt_paged(&schema_stmt, &params_json, size, run_id)
+                }
+                _ => proxy.execute_stmt(&schema_stmt, &params_json, with_statistics, run_id),
+            }
         };
 
-        match run_sql_task(db_type, task_fn) {
-            Ok(sql_res) => Ok(Json::from(sql_res)),
-            Err(e) => Err(Error::from(e)),
-        }
+        match_json_results(run_tracked_sql_task(&profile, run_id.as_deref(), task_fn))
     }
 
     async fn validate_stmts(
         &self,
-        db_type: DBType,
+        profile: String,
         stmts: Vec<String>,
     ) -> Result<Json<Vec<SQLResult>>> {
-        let task_fn = |proxy: &'static dyn SQLClient| -> Vec<SQLResult> {
+        let task_fn = |proxy: &dyn SQLClient| -> anyhow::Result<Vec<SQLResult>> {
             let stmts_ref: Vec<&str> = stmts.iter().map(|stmt| stmt.as_ref()).collect();
-            proxy.validate_stmts(&stmts_ref).unwrap()
+            proxy.validate_stmts(&stmts_ref)
+        };
+
+        match_json_results(run_sql_task(&profile, task_fn))
+    }
+
+    async fn fetch_next_page(
+        &self,
+        profile: String,
+        cursor: String,
+        run_id: Option<String>,
+    ) -> Result<Json<SQLResult>> {
+        let task_fn = |proxy: &dyn SQLClient| -> anyhow::Result<SQLResult> {
+            proxy.fetch_next_page(&cursor, run_id.as_deref())
+        };
+
+        match_json_results(run_tracked_sql_task(&profile, run_id.as_deref(), task_fn))
+    }
+
+    async fn savepoints(&self, profile: String) -> Result<Vec<SavePoint>> {
+        let task_fn =
+            |proxy: &dyn SQLClient| -> anyhow::Result<Vec<SavePoint>> { proxy.savepoints() };
+
+        run_sql_task(&profile, task_fn).map_err(|e| e.into())
+    }
+
+    /// The state of the console transaction, lets the UI warn about pending changes before the
+    /// console is closed or its config switched.
+    async fn transaction_status(&self, profile: String) -> Result<TransactionStatus> {
+        let task_fn = |proxy: &dyn SQLClient| -> anyhow::Result<TransactionStatus> {
+            proxy.transaction_status()
         };
-        Ok(Json::from(run_sql_task(db_type, task_fn)))
+
+        run_sql_task(&profile, task_fn).map_err(|e| e.into())
+    }
+
+    async fn connection_profiles(&self) -> Result<Vec<ConnectionProfileInfo>> {
+        connection_profile::list_profiles().map_err(|e| e.into())
+    }
+
+    async fn credential_status(&self) -> CredentialStatus {
+        credentials::status()
     }
 }
 
@@ -57,50 +113,117 @@ pub struct SqlMutation;
 
 #[Object]
 impl SqlMutation {
-    async fn db_config(&self, db_type: DBType, db_config: Config) -> Result<Json<SQLResult>> {
-        let task_fn = |proxy: &'static dyn SQLClient| -> anyhow::Result<SQLResult> {
-            proxy.set_config(db_config)
-        };
-        match run_sql_task(db_type, task_fn) {
-            Ok(res) => Ok(Json(res)),
-            Err(e) => Err(Error::from(e)),
-        }
+    async fn db_config(
+        &self,
+        profile: String,
+        db_type: DBType,
+        db_config: Config,
+    ) -> Result<Json<SQLResult>> {
+        match_json_results(connection_profile::save_profile(ConnectionProfile::new(
+            profile, db_type, db_config,
+        )))
+    }
+
+    async fn unlock_credentials(&self, passphrase: String) -> Result<bool> {
+        credentials::unlock(&passphrase).map_err(|e| e.into())
     }
 
-    async fn db_autocommit(&self, db_type: DBType, db_autocommit: bool) -> Result<Json<SQLResult>> {
-        let task_fn = |proxy: &'static dyn SQLClient| -> anyhow::Result<SQLResult> {
+    /// Protects the stored credentials with the passphrase, or with the file keystore when absent.
+    async fn set_credential_passphrase(&self, passphrase: Option<String>) -> Result<bool> {
+        credentials::set_passphrase(passphrase.as_deref())
+            .map(|_| true)
+            .map_err(|e| e.into())
+    }
+
+    async fn delete_connection_profile(&self, profile: String) -> Result<bool> {
+        connection_profile::delete_profile(&profile)
+            .map(|_| true)
+            .map_err(|e| e.into())
+    }
+
+    async fn db_autocommit(&self, profile: String, db_autocommit: bool) -> Result<Json<SQLResult>> {
+        let task_fn = |proxy: &dyn SQLClient| -> anyhow::Result<SQLResult> {
             proxy.set_autocommit(db_autocommit)
         };
-        match run_sql_task(db_type, task_fn) {
-            Ok(res) => Ok(Json(res)),
-            Err(e) => Err(e.into()),
-        }
+
+        match_json_results(run_sql_task(&profile, task_fn))
+    }
+
+    async fn commit_console(&self, profile: String) -> Result<Json<SQLResult>> {
+        let task_fn =
+            |proxy: &dyn SQLClient| -> anyhow::Result<SQLResult> { proxy.commit_console() };
+
+        match_json_results(run_sql_task(&profile, task_fn))
     }
 
-    async fn commit_console(&self, db_type: DBType) -> Result<Json<SQLResult>> {
+    async fn rollback_console(&self, profile: String) -> Result<Json<SQLResult>> {
         let task_fn =
-            |proxy: &'static dyn SQLClient| -> anyhow::Result<SQLResult> { proxy.commit_console() };
+            |proxy: &dyn SQLClient| -> anyhow::Result<SQLResult> { proxy.rollback_console() };
 
-        match_json_results(run_sql_task(db_type, task_fn))
+        match_json_results(run_sql_task(&profile, task_fn))
     }
 
-    async fn rollback_console(&self, db_type: DBType) -> Result<Json<SQLResult>> {
-        let task_fn = |proxy: &'static dyn SQLClient| -> anyhow::Result<SQLResult> {
-            proxy.rollback_console()
+    async fn close_cursor(&self, profile: String, cursor: String) -> Result<bool> {
+        let task_fn =
+            |proxy: &dyn SQLClient| -> anyhow::Result<bool> { proxy.close_cursor(&cursor) };
+
+        run_sql_task(&profile, task_fn).map_err(|e| e.into())
+    }
+
+    async fn add_savepoint(&self, profile: String, name: String) -> Result<Json<SQLResult>> {
+        let task_fn =
+            |proxy: &dyn SQLClient| -> anyhow::Result<SQLResult> { proxy.add_savepoint(&name) };
+
+        match_json_results(run_sql_task(&profile, task_fn))
+    }
+
+    async fn rollback_to_savepoint(
+        &self,
+        profile: String,
+        name: String,
+    ) -> Result<Json<SQLResult>> {
+        let task_fn = |proxy: &dyn SQLClient| -> anyhow::Result<SQLResult> {
+            proxy.rollback_to_savepoint(&name)
         };
 
-        match_json_results(run_sql_task(db_type, task_fn))
+        match_json_results(run_sql_task(&profile, task_fn))
+    }
+
+    async fn release_savepoint(&self, profile: String, name: String) -> Result<Json<SQLResult>> {
+        let task_fn =
+            |proxy: &dyn SQLClient| -> anyhow::Result<SQLResult> { proxy.release_savepoint(&name) };
+
+        match_json_results(run_sql_task(&profile, task_fn))
+    }
+
+    async fn cancel_run(&self, run_id: String) -> Result<bool> {
+        run_registry::cancel_run(&run_id).map_err(|e| e.into())
     }
 }
 
-fn run_sql_task<F, R>(db_type: DBType, task_fn: F) -> R
+fn run_sql_task<F, R>(profile: &str, task_fn: F) -> anyhow::Result<R>
 where
-    F: FnOnce(&'static dyn SQLClient) -> R,
+    F: FnOnce(&dyn SQLClient) -> anyhow::Result<R>,
 {
-    match db_type {
-        DBType::Oracle => task_fn(oracle::get_proxy()),
-        DBType::Postgres => task_fn(postgres::get_proxy()),
-    }
+    let client = connection_profile::get_client(profile)?;
+    task_fn(client.as_sql_client())
+}
+
+/// Registers the task under the run id while it's executing, so it can be cancelled.
+fn run_tracked_sql_task<F, R>(profile: &str, run_id: Option<&str>, task_fn: F) -> anyhow::Result<R>
+where
+    F: FnOnce(&dyn SQLClient) -> anyhow::Result<R>,
+{
+    let run_id = match run_id {
+        Some(id) => id,
+        None => return run_sql_task(profile, task_fn),
+    };
+
+    run_registry::start_run(run_id);
+    let res = run_sql_task(profile, task_fn);
+    run_registry::finish_run(run_id);
+
+    res
 }
 
 fn match_json_results<R>(res: anyhow::Result<R>) -> Result<Json<R>> {
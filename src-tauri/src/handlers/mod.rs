@@ -106,18 +106,22 @@ impl std::fmt::Display for CommandError {
 
 impl std::error::Error for CommandError {}
 
+// Async so a long running command (e.g. a scan) doesn't block the main thread, and the cancel
+// command can be handled while the scan is still running.
 #[tauri::command]
-pub fn invoke_handler(
+pub async fn invoke_handler(
     window: tauri::Window,
-    _state: tauri::State<AppState>,
+    _state: tauri::State<'_, AppState>,
     handler: Handler,
 ) -> Result<String, CommandError> {
     let now = Instant::now();
-    let result = match handler {
+    let result = tauri::async_runtime::spawn_blocking(move || match handler {
         Handler::QueryRunner(e) => generate_response(
             query_runner::handle_command(window, e.action, e.payload),
             now.elapsed(),
         ),
-    };
+    })
+    .await
+    .map_err(|e| CommandError::new(e.to_string()))?;
     result.or_else(|e| Err(CommandError::new(e.to_string())))
 }
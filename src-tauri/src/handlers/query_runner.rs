@@ -1,24 +1,47 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use uuid::Uuid;
 
 use crate::{
-    core::parameter_iterator::ParameterGenerateStrategy,
-    proxies::{query_runner::scan_queries, query_runner::RunResults, sql_common::DBType},
+    core::{
+        canonical_value::EquivalenceRules, param_source::ParameterSeed,
+        parameter_iterator::ParameterGenerateStrategy, query_scanner::ScanSafetyMode,
+        result_diff::KeyDiffOptions,
+    },
+    proxies::{
+        query_runner::{scan_queries, ComparisonGroup, RunResults, ScanSettings},
+        run_history::{self, RunReplay, ScanRun},
+        run_registry,
+        scan_progress::ProgressSink,
+        sql_common::DBType,
+    },
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Query {
+    profile: String,
     db_type: DBType,
     statement: String,
-    parameters: Option<Vec<Vec<Value>>>,
+    parameters: Option<Vec<ParameterSeed>>,
     mode: ParameterGenerateStrategy,
+    /// The schema the query runs against instead of the schema it's scanned under.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schema: Option<String>,
 }
 
 impl Query {
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
+
     pub fn db_type(&self) -> DBType {
         self.db_type.clone()
     }
@@ -27,38 +50,396 @@ impl Query {
         &self.statement
     }
 
-    pub fn parameters(&self) -> Option<&Vec<Vec<Value>>> {
+    pub fn parameters(&self) -> Option<&Vec<ParameterSeed>> {
         self.parameters.as_ref()
     }
 
+    /// A copy of the query with the parameter sources replaced by their values.
+    pub fn with_parameters(&self, parameters: Vec<Vec<Value>>) -> Self {
+        Self {
+            parameters: Some(parameters.into_iter().map(ParameterSeed::Values).collect()),
+            ..self.clone()
+        }
+    }
+
     pub fn mode(&self) -> ParameterGenerateStrategy {
         self.mode.clone()
     }
+
+    pub fn schema(&self) -> Option<&str> {
+        self.schema.as_deref()
+    }
+
+    pub fn with_schema(&self, schema: &str) -> Self {
+        Self {
+            schema: Some(schema.to_string()),
+            ..self.clone()
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Runs each query against all the schemas and diffs the results of the schemas, aligned by
+/// parameter set.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct Payload {
+pub struct SchemaComparison {
+    schemas: Vec<String>,
+    queries: Vec<Query>,
+}
+
+impl SchemaComparison {
+    /// A group per query with a copy of the query for each schema, so the queries of the group
+    /// are diffed like the queries of a schema. The diff indexes are the indexes of the schemas.
+    fn groups(&self) -> impl Iterator<Item = (ComparisonGroup, Vec<Query>)> + '_ {
+        self.queries.iter().enumerate().map(move |(i, query)| {
+            let group = ComparisonGroup::new(i, self.schemas.clone());
+            let queries = self
+                .schemas
+                .iter()
+                .map(|schema| query.with_schema(schema))
+                .collect();
+            (group, queries)
+        })
+    }
+}
+
+/// Everything a scan runs with, stored with the results so the scan can be replayed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanInputs {
+    #[serde(default)]
     schema_queries: HashMap<String, Vec<Query>>,
+    #[serde(default)]
     diff_results: bool,
+    /// Diffs every parameter set and summarizes the mismatches, instead of stopping at the
+    /// first difference.
+    #[serde(default)]
+    diff_all: bool,
+    /// The max number of mismatching parameter sets in the summary.
+    max_mismatches: Option<usize>,
+    /// Timeout of each scanned statement in milliseconds.
+    statement_timeout: Option<u64>,
+    /// The max number of statements of the scan running at the same time.
+    concurrency: Option<usize>,
+    /// How the statements which may change data are handled, unrestricted by default.
+    #[serde(default)]
+    safety_mode: ScanSafetyMode,
+    /// Matches the diffed rows by the key columns instead of by position.
+    key_diff: Option<KeyDiffOptions>,
+    /// Which values of different databases are equal when the results are diffed.
+    #[serde(default)]
+    equivalence_rules: EquivalenceRules,
+    /// Compares the results of the queries across schemas, the results of the comparison are
+    /// always diffed.
+    compare_schemas: Option<SchemaComparison>,
+}
+
+impl ScanInputs {
+    pub fn schema_queries(&self) -> &HashMap<String, Vec<Query>> {
+        &self.schema_queries
+    }
+
+    /// The query groups of the schema comparison, which are diffed even when the results of the
+    /// schemas aren't.
+    pub fn comparison_groups(&self) -> Vec<(ComparisonGroup, Vec<Query>)> {
+        self.compare_schemas
+            .iter()
+            .flat_map(SchemaComparison::groups)
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        match &self.compare_schemas {
+            Some(comparison) if comparison.schemas.len() < 2 => Err(anyhow!(
+                "At least two schemas are required to compare the results."
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A query of a scan spec, which runs with the connection of the spec unless it has its own.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SpecQuery {
+    profile: Option<String>,
+    db_type: Option<DBType>,
+    statement: String,
+    parameters: Option<Vec<ParameterSeed>>,
+    /// The strategy of the spec when absent.
+    mode: Option<ParameterGenerateStrategy>,
+}
+
+/// A scan written by hand for the batch runner, each query runs against every schema with the
+/// connection profile of the spec, or with its own, e.g. to check an Oracle query against a
+/// Postgres one. The other settings of the scan are the same as the scan inputs.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanSpec {
+    profile: Option<String>,
+    db_type: Option<DBType>,
+    #[serde(default)]
+    schemas: Vec<String>,
+    #[serde(default)]
+    queries: Vec<SpecQuery>,
+    /// The parameter generate strategy of the queries.
+    mode: Option<ParameterGenerateStrategy>,
+    #[serde(flatten)]
+    inputs: ScanInputs,
+}
+
+impl ScanSpec {
+    /// Parses the spec as YAML, which also accepts JSON.
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(serde_yaml::from_str(text)?)
+    }
+
+    pub fn into_inputs(self) -> Result<ScanInputs> {
+        let ScanSpec {
+            profile,
+            db_type,
+            schemas,
+            queries,
+            mode,
+            mut inputs,
+        } = self;
+        if !queries.is_empty() && schemas.is_empty() {
+            return Err(anyhow!(
+                "At least one schema is required to run the queries."
+            ));
+        }
+
+        let queries = queries
+            .into_iter()
+            .enumerate()
+            .map(|(i, query)| {
+                Ok(Query {
+                    profile: query.profile.or_else(|| profile.clone()).ok_or_else(|| {
+                        anyhow!("The connection profile of the query {} is required.", i + 1)
+                    })?,
+                    db_type: query.db_type.or(db_type).ok_or_else(|| {
+                        anyhow!("The database type of the query {} is required.", i + 1)
+                    })?,
+                    statement: query.statement,
+                    parameters: query.parameters,
+                    mode: query
+                        .mode
+                        .or_else(|| mode.clone())
+                        .unwrap_or(ParameterGenerateStrategy::Normal),
+                    schema: None,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for schema in schemas {
+            inputs
+                .schema_queries
+                .entry(schema)
+                .or_default()
+                .extend(queries.iter().cloned());
+        }
+        inputs.validate()?;
+
+        Ok(inputs)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+    /// The id of the new scan, or of the scan to cancel or replay.
+    run_id: Option<String>,
+    #[serde(flatten)]
+    inputs: ScanInputs,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub enum Action {
     ScanQueries,
+    CancelScan,
+    ReplayRun,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum ActionResult {
+    RunResults(RunResults),
+    Cancelled(bool),
+    Replayed(RunReplay),
 }
 
 pub fn handle_command(
     window: tauri::Window,
     action: Action,
-    Payload {
-        schema_queries,
-        diff_results,
-    }: Payload,
-) -> Result<RunResults> {
+    Payload { run_id, inputs }: Payload,
+) -> Result<ActionResult> {
     log::debug!("got query runner command.");
     match action {
-        Action::ScanQueries => Ok(scan_queries(window, schema_queries, diff_results)),
+        Action::ScanQueries => {
+            inputs.validate()?;
+            Ok(ActionResult::RunResults(
+                run_scan(Arc::new(window), run_id, inputs, None).into_results(),
+            ))
+        }
+        Action::CancelScan => {
+            let run_id = run_id.ok_or_else(|| anyhow!("The run id of the scan is required."))?;
+            Ok(ActionResult::Cancelled(run_registry::cancel_run(&run_id)?))
+        }
+        Action::ReplayRun => {
+            let run_id =
+                run_id.ok_or_else(|| anyhow!("The run id of the replayed scan is required."))?;
+            let stored = run_history::load_run(&run_id)?
+                .ok_or_else(|| anyhow!("The scan run {} is not found.", run_id))?;
+            let replayed = run_scan(
+                Arc::new(window),
+                None,
+                stored.inputs().clone(),
+                Some(run_id),
+            );
+            Ok(ActionResult::Replayed(RunReplay::new(&stored, replayed)))
+        }
+    }
+}
+
+/// Runs the scan and saves it to the run history, a failed save doesn't fail the scan.
+pub fn run_scan(
+    sink: Arc<dyn ProgressSink>,
+    run_id: Option<String>,
+    inputs: ScanInputs,
+    replay_of: Option<String>,
+) -> ScanRun {
+    let run_id = run_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+    let started_at = chrono::Local::now().to_rfc3339();
+    let now = Instant::now();
+    let settings = ScanSettings {
+        diff_results: inputs.diff_results,
+        diff_all: inputs.diff_all,
+        max_mismatches: inputs.max_mismatches,
+        sort: true,
+        statement_timeout: inputs.statement_timeout.map(Duration::from_millis),
+        safety_mode: inputs.safety_mode,
+        key_diff: inputs.key_diff.clone(),
+        equivalence_rules: inputs.equivalence_rules.clone(),
+    };
+    let results = scan_queries(
+        sink,
+        inputs.schema_queries().clone(),
+        inputs.comparison_groups(),
+        Some(run_id.clone()),
+        inputs.concurrency,
+        settings,
+    );
+
+    let run = ScanRun::new(
+        run_id,
+        started_at,
+        now.elapsed(),
+        inputs,
+        results,
+        replay_of,
+    );
+    if let Err(e) = run_history::save_run(&run) {
+        log::error!("failed to save the scan run {}: {}", run.run_id(), e);
+    }
+
+    run
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn comparison_inputs(schemas: &[&str]) -> ScanInputs {
+        serde_json::from_value(json!({
+            "schemaQueries": {
+                "GREENCO": [{
+                    "profile": "pg",
+                    "dbType": "postgres",
+                    "statement": "SELECT * FROM COMPANY_ITEM",
+                    "mode": "normal"
+                }]
+            },
+            "compareSchemas": {
+                "schemas": schemas,
+                "queries": [
+                    {
+                        "profile": "pg",
+                        "dbType": "postgres",
+                        "statement": "SELECT * FROM COMPANY_ITEM",
+                        "mode": "normal"
+                    },
+                    {
+                        "profile": "pg",
+                        "dbType": "postgres",
+                        "statement": "SELECT * FROM COMPANY_LOCATION",
+                        "mode": "normal"
+                    }
+                ]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_schema_comparison_queries() {
+        let inputs = comparison_inputs(&["GREENCO", "BLUECO"]);
+        assert!(inputs.validate().is_ok());
+
+        let schema_queries = inputs.schema_queries();
+        assert_eq!(schema_queries.len(), 1);
+        assert_eq!(schema_queries["GREENCO"][0].schema(), None);
+
+        let groups = inputs.comparison_groups();
+        assert_eq!(groups.len(), 2);
+        let (group, queries) = &groups[1];
+        assert_eq!(group.query_index(), 1);
+        assert_eq!(group.schemas(), ["GREENCO", "BLUECO"]);
+        assert_eq!(group.label(), "GREENCO vs BLUECO #2");
+        assert_eq!(
+            queries.iter().map(Query::schema).collect::<Vec<_>>(),
+            vec![Some("GREENCO"), Some("BLUECO")]
+        );
+        assert!(queries
+            .iter()
+            .all(|query| query.statement() == "SELECT * FROM COMPANY_LOCATION"));
+    }
+
+    #[test]
+    fn test_spec_query_profiles() {
+        let spec = ScanSpec::parse(
+            r#"
+profile: ora
+dbType: oracle
+schemas: [GREENCO]
+queries:
+  - statement: SELECT * FROM COMPANY_ITEM
+  - profile: pg
+    dbType: postgres
+    statement: SELECT * FROM COMPANY_ITEM
+"#,
+        )
+        .unwrap();
+        let inputs = spec.into_inputs().unwrap();
+        let queries = &inputs.schema_queries()["GREENCO"];
+        assert_eq!(
+            queries
+                .iter()
+                .map(|query| (query.profile(), query.db_type()))
+                .collect::<Vec<_>>(),
+            vec![("ora", DBType::Oracle), ("pg", DBType::Postgres)]
+        );
+
+        let spec =
+            ScanSpec::parse("{schemas: [GREENCO], queries: [{statement: SELECT 1, profile: pg}]}")
+                .unwrap();
+        assert!(spec.into_inputs().is_err());
+    }
+
+    #[test]
+    fn test_schema_comparison_validate() {
+        assert!(comparison_inputs(&["GREENCO"]).validate().is_err());
+        assert!(comparison_inputs(&[]).validate().is_err());
     }
 }
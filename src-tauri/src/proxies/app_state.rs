@@ -1,6 +1,11 @@
-use crate::proxies::rocksdb::{self, RocksDataStore};
+use crate::proxies::{
+    credentials,
+    rocksdb::{self, RocksDataStore},
+};
 
+use ::rocksdb::DB;
 use anyhow::{anyhow, Result};
+use serde_json::Value;
 
 #[derive(async_graphql::Enum, Copy, Clone, Debug, Eq, PartialEq)]
 pub enum AppStateKey {
@@ -21,18 +26,35 @@ pub enum AppStateKey {
 
 static APP_STATE_CF: &str = "APP_STATE";
 
+static CONFIG_KEYS: [AppStateKey; 2] = [AppStateKey::OralceConfig, AppStateKey::PostgresConfig];
+static PASSWORD_FIELD: &str = "password";
+
+/// The stored configs never return their password.
 pub fn get_state(state_keys: Vec<AppStateKey>) -> Result<Vec<Option<String>>> {
     let db = rocksdb::get_conn();
     let key_strs: Vec<String> = state_keys.iter().map(|key| format!("{:?}", key)).collect();
     let keys_ref: Vec<&str> = key_strs.iter().map(|k| k.as_ref()).collect();
 
-    RocksDataStore::multi_get(Some(APP_STATE_CF), &keys_ref, &db)
+    let mut vals = RocksDataStore::multi_get(Some(APP_STATE_CF), &keys_ref, &db)?;
+    for (key, val) in state_keys.iter().zip(vals.iter_mut()) {
+        if let (true, Some(v)) = (CONFIG_KEYS.contains(key), val.as_mut()) {
+            *v = map_config_password(v, |_| Ok(String::new()))?;
+        }
+    }
+
+    Ok(vals)
 }
 
-pub fn set_state(state_keys: Vec<AppStateKey>, state_vals: Vec<String>) -> Result<bool> {
+/// The passwords of the configs are stored encrypted, an empty one keeps the stored password.
+pub fn set_state(state_keys: Vec<AppStateKey>, mut state_vals: Vec<String>) -> Result<bool> {
     if state_keys.len() != state_vals.len() {
         return Err(anyhow!("The sizes of keys and values are not same."));
     }
+    for (key, val) in state_keys.iter().zip(state_vals.iter_mut()) {
+        if CONFIG_KEYS.contains(key) {
+            *val = encrypt_config_password(key, val)?;
+        }
+    }
     let mut db = rocksdb::get_conn();
     let mut key_vals: Vec<(&str, &str)> = Vec::with_capacity(state_keys.len());
     let key_strs: Vec<String> = state_keys.iter().map(|key| format!("{:?}", key)).collect();
@@ -51,3 +73,64 @@ pub fn delete_state(state_keys: Vec<AppStateKey>) -> Result<()> {
 
     RocksDataStore::delete_batch(Some(APP_STATE_CF), &keys_ref, &db).map_err(|e| e.into())
 }
+
+fn encrypt_config_password(key: &AppStateKey, val: &str) -> Result<String> {
+    let stored_password = {
+        let db = rocksdb::get_conn();
+        RocksDataStore::get(&format!("{:?}", key), &db, Some(APP_STATE_CF))?
+            .map(|stored| get_config_password(&stored))
+            .unwrap_or_default()
+    };
+
+    map_config_password(val, |password| match password {
+        "" => Ok(stored_password.clone()),
+        p if credentials::is_encrypted(p) => Ok(p.to_string()),
+        p => credentials::encrypt(p),
+    })
+}
+
+fn get_config_password(config: &str) -> String {
+    serde_json::from_str::<Value>(config)
+        .ok()
+        .and_then(|v| v.get(PASSWORD_FIELD)?.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+/// Maps the password of a config json, the value is kept as is if it isn't a json object.
+fn map_config_password<F>(config: &str, map_fn: F) -> Result<String>
+where
+    F: FnOnce(&str) -> Result<String>,
+{
+    let mut config_val: Value = match serde_json::from_str(config) {
+        Ok(v @ Value::Object(_)) => v,
+        _ => return Ok(config.to_string()),
+    };
+    let password = config_val
+        .get(PASSWORD_FIELD)
+        .and_then(|p| p.as_str())
+        .unwrap_or_default()
+        .to_string();
+    config_val[PASSWORD_FIELD] = Value::String(map_fn(&password)?);
+
+    Ok(config_val.to_string())
+}
+
+/// Rewrites the stored passwords of the configs, used to encrypt or re-encrypt them. Returns the
+/// column family and the rewritten configs to write them in one batch with the other credentials.
+pub fn rewrite_config_passwords(
+    rewrite_fn: &dyn Fn(&str) -> Result<String>,
+    db: &DB,
+) -> Result<(&'static str, Vec<(String, String)>)> {
+    let mut rewritten = Vec::with_capacity(CONFIG_KEYS.len());
+    for key in CONFIG_KEYS.iter().map(|key| format!("{:?}", key)) {
+        if let Some(val) = RocksDataStore::get(&key, db, Some(APP_STATE_CF))? {
+            let new_val = map_config_password(&val, |password| match password {
+                "" => Ok(String::new()),
+                p => rewrite_fn(p),
+            })?;
+            rewritten.push((key, new_val));
+        }
+    }
+
+    Ok((APP_STATE_CF, rewritten))
+}
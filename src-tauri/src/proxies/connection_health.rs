@@ -0,0 +1,94 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, Once},
+    thread,
+    time::Duration,
+};
+
+use async_graphql::{Enum, SimpleObject};
+use futures::{stream, Stream};
+use lazy_static::lazy_static;
+use tokio::sync::broadcast::{self, error::RecvError, Sender};
+
+use super::connection_profile;
+
+static EVENTS_CAPACITY: usize = 64;
+static MONITOR: Once = Once::new();
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ConnectionStatus {
+    Connected,
+    Reconnecting,
+    Disconnected,
+    /// The connection was lost with uncommitted changes, it won't reconnect until the console
+    /// is rolled back.
+    TransactionLost,
+}
+
+#[derive(SimpleObject, Clone, Debug)]
+pub struct ConnectionStatusEvent {
+    pub profile: String,
+    status: ConnectionStatus,
+    message: Option<String>,
+    timestamp: String,
+}
+
+lazy_static! {
+    static ref EVENTS: Sender<ConnectionStatusEvent> = broadcast::channel(EVENTS_CAPACITY).0;
+    static ref LAST_STATUS: Mutex<HashMap<String, ConnectionStatus>> = Mutex::new(HashMap::new());
+}
+
+/// Publishes the status of the console connection of the profile if it has changed.
+pub fn publish(profile: &str, status: ConnectionStatus, message: Option<String>) {
+    {
+        let mut last_status = LAST_STATUS.lock().unwrap();
+        if last_status.get(profile) == Some(&status) {
+            return;
+        }
+        last_status.insert(profile.to_string(), status);
+    }
+
+    log::info!(
+        "connection status of profile {} changed to {:?}, {:?}",
+        profile,
+        status,
+        message
+    );
+    // Fails only when there is no subscriber.
+    let _ = EVENTS.send(ConnectionStatusEvent {
+        profile: profile.to_string(),
+        status,
+        message,
+        timestamp: chrono::Local::now().to_rfc3339(),
+    });
+}
+
+pub fn forget(profile: &str) {
+    LAST_STATUS.lock().unwrap().remove(profile);
+}
+
+pub fn subscribe() -> impl Stream<Item = ConnectionStatusEvent> {
+    stream::unfold(EVENTS.subscribe(), |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((event, receiver)),
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("connection status subscriber skipped {} events.", skipped)
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+/// Starts the thread pinging the opened consoles periodically, only the first call starts it.
+pub fn start_monitor(interval: Duration) {
+    MONITOR.call_once(|| {
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            for client in connection_profile::opened_clients() {
+                client.check_health();
+            }
+        });
+    });
+}
@@ -0,0 +1,244 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+};
+
+use super::sql_common::{Config, SQLError};
+
+/// A connection which can tell when it's no longer usable, broken connections aren't reused.
+pub trait PoolConnection {
+    fn is_broken(&self) -> bool;
+}
+
+struct PoolState<C> {
+    config: Config,
+    idle: Vec<C>,
+    /// The number of opened connections of the current generation, idle or checked out.
+    size: usize,
+    /// Bumped when the config changes, connections of an older generation are dropped.
+    generation: usize,
+}
+
+/// A bounded pool of connections of a profile, used by the scans so they don't serialize on
+/// the console connection. Connections are opened lazily up to the pool size of the config.
+pub struct ConnectionPool<C> {
+    state: Mutex<PoolState<C>>,
+    available: Condvar,
+}
+
+impl<C: PoolConnection> ConnectionPool<C> {
+    pub fn new(config: Config) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(PoolState {
+                config,
+                idle: Vec::new(),
+                size: 0,
+                generation: 0,
+            }),
+            available: Condvar::new(),
+        })
+    }
+
+    /// Drops the idle connections and opens the new ones with the config, the checked out
+    /// connections are closed once returned and no longer count against the pool size.
+    pub fn reset(&self, config: Config) {
+        let mut state = self.state.lock().unwrap();
+        state.size = 0;
+        state.idle.clear();
+        state.config = config;
+        state.generation += 1;
+        self.available.notify_all();
+    }
+
+    /// The config the new connections are opened with.
+    pub fn config(&self) -> Config {
+        self.state.lock().unwrap().config.clone()
+    }
+
+    /// Checks out a connection, opening one with `connect` while the pool isn't full, otherwise
+    /// waits until a connection is returned.
+    pub fn get<F>(self: &Arc<Self>, connect: F) -> Result<PooledConnection<C>, SQLError>
+    where
+        F: FnOnce(&Config) -> Result<C, SQLError>,
+    {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            while let Some(conn) = state.idle.pop() {
+                if conn.is_broken() {
+                    log::debug!("drop a broken pooled connection.");
+                    state.size -= 1;
+                    continue;
+                }
+                return Ok(self.checked_out(conn, state.generation));
+            }
+
+            if state.size < state.config.pool_size() {
+                state.size += 1;
+                let (config, generation) = (state.config.clone(), state.generation);
+                drop(state);
+
+                return match connect(&config) {
+                    Ok(conn) => Ok(self.checked_out(conn, generation)),
+                    Err(e) => {
+                        self.release_slot(self.state.lock().unwrap(), generation);
+                        Err(e)
+                    }
+                };
+            }
+
+            state = self.available.wait(state).unwrap();
+        }
+    }
+
+    fn checked_out(self: &Arc<Self>, conn: C, generation: usize) -> PooledConnection<C> {
+        PooledConnection {
+            pool: Arc::clone(self),
+            conn: Some(conn),
+            generation,
+        }
+    }
+
+    fn put_back(&self, conn: C, generation: usize) {
+        let mut state = self.state.lock().unwrap();
+        if generation != state.generation || conn.is_broken() {
+            self.release_slot(state, generation);
+            return;
+        }
+
+        state.idle.push(conn);
+        self.available.notify_one();
+    }
+
+    /// Frees the slot of a dropped connection, the slots of older generations were already freed.
+    fn release_slot(&self, mut state: MutexGuard<PoolState<C>>, generation: usize) {
+        if generation == state.generation {
+            state.size -= 1;
+        }
+        self.available.notify_one();
+    }
+}
+
+/// A connection checked out of the pool, returned to the pool when dropped.
+pub struct PooledConnection<C: PoolConnection> {
+    pool: Arc<ConnectionPool<C>>,
+    conn: Option<C>,
+    generation: usize,
+}
+
+impl<C: PoolConnection> Deref for PooledConnection<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.conn.as_ref().unwrap()
+    }
+}
+
+impl<C: PoolConnection> DerefMut for PooledConnection<C> {
+    fn deref_mut(&mut self) -> &mut C {
+        self.conn.as_mut().unwrap()
+    }
+}
+
+impl<C: PoolConnection> Drop for PooledConnection<C> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool.put_back(conn, self.generation);
+        }
+    }
+}
+
+/// Limits the number of statements of a scan running at the same time.
+pub struct Semaphore {
+    permits: Mutex<usize>,
+    available: Condvar,
+}
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Self {
+        Self {
+            permits: Mutex::new(permits),
+            available: Condvar::new(),
+        }
+    }
+
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        let mut permits = self.permits.lock().unwrap();
+        while *permits == 0 {
+            permits = self.available.wait(permits).unwrap();
+        }
+        *permits -= 1;
+
+        SemaphorePermit { semaphore: self }
+    }
+}
+
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl<'a> Drop for SemaphorePermit<'a> {
+    fn drop(&mut self) {
+        *self.semaphore.permits.lock().unwrap() += 1;
+        self.semaphore.available.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    struct TestConn(usize);
+
+    impl PoolConnection for TestConn {
+        fn is_broken(&self) -> bool {
+            false
+        }
+    }
+
+    fn config_with_size(size: u32) -> Config {
+        let mut config = Config::new("localhost", "5432", "postgres", "postgres", "");
+        config.pool_size = Some(size);
+        config
+    }
+
+    #[test]
+    fn test_reuse_returned_connections() {
+        let pool = ConnectionPool::new(config_with_size(2));
+        let opened = AtomicUsize::new(0);
+        let connect = |_: &Config| Ok(TestConn(opened.fetch_add(1, Ordering::SeqCst)));
+
+        let first = pool.get(connect).unwrap();
+        let second = pool.get(connect).unwrap();
+        assert_eq!((first.0, second.0), (0, 1));
+
+        drop(first);
+        assert_eq!(pool.get(connect).unwrap().0, 0);
+        assert_eq!(opened.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_reset_drops_old_connections() {
+        let pool = ConnectionPool::new(config_with_size(1));
+        let opened = AtomicUsize::new(0);
+        let connect = |_: &Config| Ok(TestConn(opened.fetch_add(1, Ordering::SeqCst)));
+
+        let old = pool.get(connect).unwrap();
+        pool.reset(config_with_size(1));
+        let new = pool.get(connect).unwrap();
+        assert_eq!(new.0, 1);
+
+        drop(old);
+        drop(new);
+        assert_eq!(pool.get(connect).unwrap().0, 1);
+    }
+
+    #[test]
+    fn test_failed_connect_frees_slot() {
+        let pool: Arc<ConnectionPool<TestConn>> = ConnectionPool::new(config_with_size(1));
+
+        assert!(pool.get(|_| Err(SQLError::new_str("refused"))).is_err());
+        assert_eq!(pool.get(|_| Ok(TestConn(7))).unwrap().0, 7);
+    }
+}
@@ -0,0 +1,235 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use ::rocksdb::DB;
+use anyhow::{anyhow, Result};
+use async_graphql::SimpleObject;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    connection_health, credentials,
+    oracle::OracleClient,
+    postgres::PostgresProxy,
+    rocksdb::{self, RocksDataStore},
+    sql_common::{Config, DBType, SQLClient, SQLResult},
+};
+
+static CONNECTION_PROFILES_CF: &str = "CONNECTION_PROFILES";
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionProfile {
+    pub name: String,
+    pub db_type: DBType,
+    pub config: Config,
+}
+
+impl ConnectionProfile {
+    pub fn new(name: String, db_type: DBType, config: Config) -> Self {
+        Self {
+            name,
+            db_type,
+            config,
+        }
+    }
+}
+
+#[derive(SimpleObject, Clone, Debug)]
+pub struct ConnectionProfileInfo {
+    name: String,
+    db_type: DBType,
+    host: String,
+    port: String,
+    db: String,
+    username: String,
+    opened: bool,
+}
+
+impl ConnectionProfileInfo {
+    fn new(profile: &ConnectionProfile, opened: bool) -> Self {
+        Self {
+            name: profile.name.clone(),
+            db_type: profile.db_type,
+            host: profile.config.host.clone(),
+            port: profile.config.port.clone(),
+            db: profile.config.db.clone(),
+            username: profile.config.username.clone(),
+            opened,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum ProfileClient {
+    Oracle(OracleClient),
+    Postgres(PostgresProxy),
+}
+
+impl ProfileClient {
+    fn new(profile: &ConnectionProfile) -> Self {
+        match profile.db_type {
+            DBType::Oracle => {
+                ProfileClient::Oracle(OracleClient::new(&profile.name, profile.config.clone()))
+            }
+            DBType::Postgres => {
+                ProfileClient::Postgres(PostgresProxy::new(&profile.name, profile.config.clone()))
+            }
+        }
+    }
+
+    pub fn check_health(&self) {
+        match self {
+            ProfileClient::Oracle(client) => client.check_health(),
+            ProfileClient::Postgres(proxy) => proxy.check_health(),
+        }
+    }
+
+    pub fn db_type(&self) -> DBType {
+        match self {
+            ProfileClient::Oracle(_) => DBType::Oracle,
+            ProfileClient::Postgres(_) => DBType::Postgres,
+        }
+    }
+
+    /// The config the client connects with, its password is decrypted already.
+    pub fn config(&self) -> Config {
+        match self {
+            ProfileClient::Oracle(client) => client.config(),
+            ProfileClient::Postgres(proxy) => proxy.config(),
+        }
+    }
+
+    pub fn as_sql_client(&self) -> &dyn SQLClient {
+        match self {
+            ProfileClient::Oracle(client) => client,
+            ProfileClient::Postgres(proxy) => proxy,
+        }
+    }
+}
+
+lazy_static! {
+    static ref CLIENTS: Mutex<HashMap<String, ProfileClient>> = Mutex::new(HashMap::new());
+}
+
+pub fn list_profiles() -> Result<Vec<ConnectionProfileInfo>> {
+    let key_vals = {
+        let db = rocksdb::get_conn();
+        RocksDataStore::get_all(Some(CONNECTION_PROFILES_CF), &db)?
+    };
+    let clients = CLIENTS.lock().unwrap();
+
+    let mut profiles = Vec::with_capacity(key_vals.len());
+    for (name, val) in key_vals {
+        let profile: ConnectionProfile = serde_json::from_str(&val)?;
+        profiles.push(ConnectionProfileInfo::new(
+            &profile,
+            clients.contains_key(&name),
+        ));
+    }
+
+    Ok(profiles)
+}
+
+fn load_stored_profile(name: &str) -> Result<Option<ConnectionProfile>> {
+    let db = rocksdb::get_conn();
+    match RocksDataStore::get(name, &db, Some(CONNECTION_PROFILES_CF))? {
+        Some(val) => Ok(Some(serde_json::from_str(&val)?)),
+        None => Ok(None),
+    }
+}
+
+/// Loads the profile with its password decrypted.
+pub fn load_profile(name: &str) -> Result<ConnectionProfile> {
+    match load_stored_profile(name)? {
+        Some(mut profile) => {
+            profile.config.password = credentials::decrypt(&profile.config.password)?;
+            Ok(profile)
+        }
+        None => Err(anyhow!("The connection profile {} doesn't exist.", name)),
+    }
+}
+
+/// Persists the profile with its password encrypted and (re)connects its console with the new
+/// config. An empty password keeps the stored one, since it's never sent back to the client.
+pub fn save_profile(mut profile: ConnectionProfile) -> Result<SQLResult> {
+    if profile.name.trim().is_empty() {
+        return Err(anyhow!("The connection profile name can't be empty."));
+    }
+
+    let stored_password = match load_stored_profile(&profile.name)? {
+        Some(stored) if profile.config.password.is_empty() => {
+            profile.config.password = credentials::decrypt(&stored.config.password)?;
+            stored.config.password
+        }
+        _ => credentials::encrypt(&profile.config.password)?,
+    };
+
+    let mut stored_profile = profile.clone();
+    stored_profile.config.password = stored_password;
+    let val = serde_json::to_string(&stored_profile)?;
+    {
+        let mut db = rocksdb::get_conn();
+        RocksDataStore::write_batch(CONNECTION_PROFILES_CF, &[(&profile.name, &val)], &mut db)?;
+    }
+
+    let client = {
+        let mut clients = CLIENTS.lock().unwrap();
+        match clients.get(&profile.name) {
+            Some(client) if client.db_type() == profile.db_type => client.clone(),
+            _ => {
+                let client = ProfileClient::new(&profile);
+                clients.insert(profile.name.clone(), client.clone());
+                client
+            }
+        }
+    };
+
+    client.as_sql_client().set_config(profile.config)
+}
+
+pub fn delete_profile(name: &str) -> Result<()> {
+    {
+        let db = rocksdb::get_conn();
+        RocksDataStore::delete(Some(CONNECTION_PROFILES_CF), name, &db)?;
+    }
+    CLIENTS.lock().unwrap().remove(name);
+    connection_health::forget(name);
+
+    Ok(())
+}
+
+pub fn opened_clients() -> Vec<ProfileClient> {
+    CLIENTS.lock().unwrap().values().cloned().collect()
+}
+
+/// Rewrites the stored password of every profile, used to encrypt or re-encrypt them. Returns the
+/// column family and the rewritten profiles to write them in one batch with the other credentials.
+pub fn rewrite_passwords(
+    rewrite_fn: &dyn Fn(&str) -> Result<String>,
+    db: &DB,
+) -> Result<(&'static str, Vec<(String, String)>)> {
+    let key_vals = RocksDataStore::get_all(Some(CONNECTION_PROFILES_CF), db)?;
+
+    let mut rewritten = Vec::with_capacity(key_vals.len());
+    for (name, val) in key_vals {
+        let mut profile: ConnectionProfile = serde_json::from_str(&val)?;
+        profile.config.password = rewrite_fn(&profile.config.password)?;
+        rewritten.push((name, serde_json::to_string(&profile)?));
+    }
+
+    Ok((CONNECTION_PROFILES_CF, rewritten))
+}
+
+/// Returns the client of the profile, opening it from the stored profile on first use.
+pub fn get_client(name: &str) -> Result<ProfileClient> {
+    let mut clients = CLIENTS.lock().unwrap();
+    if let Some(client) = clients.get(name) {
+        return Ok(client.clone());
+    }
+
+    let profile = load_profile(name)?;
+    let client = ProfileClient::new(&profile);
+    clients.insert(profile.name, client.clone());
+
+    Ok(client)
+}
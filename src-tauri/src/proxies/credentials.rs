@@ -0,0 +1,442 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Result};
+use async_graphql::{Enum, SimpleObject};
+use lazy_static::lazy_static;
+use openssl::{
+    base64,
+    hash::MessageDigest,
+    pkcs5::pbkdf2_hmac,
+    rand::rand_bytes,
+    sha::sha256,
+    symm::{decrypt_aead, encrypt_aead, Cipher},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    app_state, connection_profile,
+    dirs::get_data_dir,
+    rocksdb::{self, RocksDataStore},
+};
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: usize = 310_000;
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const PASSPHRASE_CHECK: &str = "credentials";
+
+static KEYSTORE_FILE: &str = "credentials.key";
+static PASSPHRASE_FILE: &str = "credentials.passphrase";
+static PENDING_SUFFIX: &str = ".new";
+static CREDENTIALS_CF: &str = "CREDENTIALS";
+static KEY_FINGERPRINT_KEY: &str = "keyFingerprint";
+
+type Key = [u8; KEY_LEN];
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CredentialKeySource {
+    /// A random key kept in a file of the data dir.
+    Keystore,
+    /// A key derived from the user passphrase, has to be unlocked once per session.
+    Passphrase,
+}
+
+#[derive(SimpleObject, Clone, Debug)]
+pub struct CredentialStatus {
+    key_source: CredentialKeySource,
+    unlocked: bool,
+}
+
+/// The salt of the passphrase and a known value encrypted with the derived key, used to tell if
+/// a passphrase is right.
+#[derive(Serialize, Deserialize)]
+struct PassphraseFile {
+    salt: String,
+    check: String,
+}
+
+lazy_static! {
+    static ref KEY: Mutex<Option<Key>> = Mutex::new(None);
+}
+
+fn get_file_path(name: &str) -> PathBuf {
+    let mut path = get_data_dir();
+    path.push(name);
+    path
+}
+
+fn get_pending_path(name: &str) -> PathBuf {
+    get_file_path(&format!("{}{}", name, PENDING_SUFFIX))
+}
+
+/// Identifies a key file, stored with the credentials it encrypted to tell which key file is the
+/// current one after an interrupted rotation.
+fn fingerprint(content: &str) -> String {
+    base64::encode_block(&sha256(content.as_bytes()))
+}
+
+fn key_source() -> CredentialKeySource {
+    if get_file_path(PASSPHRASE_FILE).exists() {
+        CredentialKeySource::Passphrase
+    } else {
+        CredentialKeySource::Keystore
+    }
+}
+
+pub fn status() -> CredentialStatus {
+    let key_source = key_source();
+    let unlocked = key_source == CredentialKeySource::Keystore || KEY.lock().unwrap().is_some();
+
+    CredentialStatus {
+        key_source,
+        unlocked,
+    }
+}
+
+fn current_key() -> Result<Key> {
+    let mut key = KEY.lock().unwrap();
+    if let Some(k) = *key {
+        return Ok(k);
+    }
+
+    match key_source() {
+        CredentialKeySource::Keystore => {
+            let k = load_or_create_keystore()?;
+            *key = Some(k);
+            Ok(k)
+        }
+        CredentialKeySource::Passphrase => Err(anyhow!(
+            "The credential store is locked, unlock it with the passphrase first."
+        )),
+    }
+}
+
+fn load_or_create_keystore() -> Result<Key> {
+    let path = get_file_path(KEYSTORE_FILE);
+    if path.exists() {
+        let decoded = base64::decode_block(fs::read_to_string(&path)?.trim())?;
+        if decoded.len() != KEY_LEN {
+            return Err(anyhow!("The keystore {:?} is corrupted.", path));
+        }
+        let mut key = [0; KEY_LEN];
+        key.copy_from_slice(&decoded);
+        return Ok(key);
+    }
+
+    log::info!("no credential keystore found, create a new one: {:?}", path);
+    let key = new_keystore_key()?;
+    write_private_file(&path, &base64::encode_block(&key))?;
+
+    Ok(key)
+}
+
+fn new_keystore_key() -> Result<Key> {
+    let mut key = [0; KEY_LEN];
+    rand_bytes(&mut key)?;
+    Ok(key)
+}
+
+fn write_private_file(path: &PathBuf, content: &str) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key> {
+    let mut key = [0; KEY_LEN];
+    pbkdf2_hmac(
+        passphrase.as_bytes(),
+        salt,
+        PBKDF2_ITERATIONS,
+        MessageDigest::sha256(),
+        &mut key,
+    )?;
+    Ok(key)
+}
+
+pub fn is_encrypted(val: &str) -> bool {
+    val.starts_with(ENCRYPTED_PREFIX)
+}
+
+fn encrypt_with(key: &Key, plain: &str) -> Result<String> {
+    let mut nonce = [0; NONCE_LEN];
+    rand_bytes(&mut nonce)?;
+    let mut tag = [0; TAG_LEN];
+    let cipher_text = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(&nonce),
+        &[],
+        plain.as_bytes(),
+        &mut tag,
+    )?;
+
+    let mut payload = Vec::with_capacity(NONCE_LEN + TAG_LEN + cipher_text.len());
+    payload.extend_from_slice(&nonce);
+    payload.extend_from_slice(&tag);
+    payload.extend_from_slice(&cipher_text);
+
+    Ok(format!(
+        "{}{}",
+        ENCRYPTED_PREFIX,
+        base64::encode_block(&payload)
+    ))
+}
+
+fn decrypt_with(key: &Key, stored: &str) -> Result<String> {
+    if !is_encrypted(stored) {
+        // Stored before the credentials were encrypted.
+        return Ok(stored.to_string());
+    }
+
+    let payload = base64::decode_block(&stored[ENCRYPTED_PREFIX.len()..])?;
+    if payload.len() < NONCE_LEN + TAG_LEN {
+        return Err(anyhow!("The encrypted credential is corrupted."));
+    }
+    let (nonce, rest) = payload.split_at(NONCE_LEN);
+    let (tag, cipher_text) = rest.split_at(TAG_LEN);
+    let plain = decrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(nonce),
+        &[],
+        cipher_text,
+        tag,
+    )
+    .map_err(|_| anyhow!("Failed to decrypt the credential, the key doesn't match."))?;
+
+    Ok(String::from_utf8(plain)?)
+}
+
+pub fn encrypt(plain: &str) -> Result<String> {
+    encrypt_with(&current_key()?, plain)
+}
+
+pub fn decrypt(stored: &str) -> Result<String> {
+    if !is_encrypted(stored) {
+        return Ok(stored.to_string());
+    }
+    decrypt_with(&current_key()?, stored)
+}
+
+/// Unlocks the store with the passphrase, returns false if the passphrase is wrong.
+pub fn unlock(passphrase: &str) -> Result<bool> {
+    let path = get_file_path(PASSPHRASE_FILE);
+    if !path.exists() {
+        return Err(anyhow!(
+            "The credential store isn't protected by a passphrase."
+        ));
+    }
+
+    let file: PassphraseFile = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    let key = derive_key(passphrase, &base64::decode_block(&file.salt)?)?;
+    match decrypt_with(&key, &file.check) {
+        Ok(check) if check == PASSPHRASE_CHECK => {
+            *KEY.lock().unwrap() = Some(key);
+            migrate_stored_credentials()?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Switches the key to one derived from the passphrase, or back to the file keystore when no
+/// passphrase is given, and re-encrypts the stored credentials with the new key.
+///
+/// The new key file is written next to the current one and only moved into place after all the
+/// credentials were re-encrypted in one batch, the old key file is removed last.
+pub fn set_passphrase(passphrase: Option<&str>) -> Result<()> {
+    recover_key_rotation()?;
+    let old_key = current_key()?;
+
+    let (new_key, key_file, old_key_file, content) = match passphrase {
+        Some(p) if !p.is_empty() => {
+            let mut salt = [0; SALT_LEN];
+            rand_bytes(&mut salt)?;
+            let key = derive_key(p, &salt)?;
+            let file = PassphraseFile {
+                salt: base64::encode_block(&salt),
+                check: encrypt_with(&key, PASSPHRASE_CHECK)?,
+            };
+            (
+                key,
+                PASSPHRASE_FILE,
+                KEYSTORE_FILE,
+                serde_json::to_string(&file)?,
+            )
+        }
+        Some(_) => return Err(anyhow!("The passphrase can't be empty.")),
+        None => {
+            let key = new_keystore_key()?;
+            (
+                key,
+                KEYSTORE_FILE,
+                PASSPHRASE_FILE,
+                base64::encode_block(&key),
+            )
+        }
+    };
+
+    let pending_path = get_pending_path(key_file);
+    write_private_file(&pending_path, &content)?;
+    let rewrite_res = rewrite_stored_credentials(
+        &|stored| encrypt_with(&new_key, &decrypt_with(&old_key, stored)?),
+        Some(&fingerprint(&content)),
+    );
+    if let Err(e) = rewrite_res {
+        if let Err(remove_err) = fs::remove_file(&pending_path) {
+            log::warn!("failed to remove the unused key file: {}", remove_err);
+        }
+        return Err(e);
+    }
+
+    finish_key_rotation(key_file, old_key_file)?;
+    *KEY.lock().unwrap() = Some(new_key);
+
+    Ok(())
+}
+
+fn finish_key_rotation(key_file: &str, old_key_file: &str) -> Result<()> {
+    fs::rename(get_pending_path(key_file), get_file_path(key_file))?;
+    let old_path = get_file_path(old_key_file);
+    if old_path.exists() {
+        fs::remove_file(&old_path)?;
+    }
+    Ok(())
+}
+
+/// Finishes a key rotation which stopped after the credentials were re-encrypted, or discards the
+/// new key file if they weren't.
+pub fn recover_key_rotation() -> Result<()> {
+    let committed = {
+        let db = rocksdb::get_conn();
+        RocksDataStore::get(KEY_FINGERPRINT_KEY, &db, Some(CREDENTIALS_CF))?
+    };
+    let is_committed = |path: &PathBuf| -> Result<bool> {
+        Ok(committed.as_deref() == Some(fingerprint(&fs::read_to_string(path)?).as_str()))
+    };
+
+    let key_files = [
+        (KEYSTORE_FILE, PASSPHRASE_FILE),
+        (PASSPHRASE_FILE, KEYSTORE_FILE),
+    ];
+    for (key_file, old_key_file) in key_files.iter() {
+        let pending_path = get_pending_path(key_file);
+        if !pending_path.exists() {
+            continue;
+        }
+        if is_committed(&pending_path)? {
+            log::info!(
+                "finish the interrupted credential key rotation to {}.",
+                key_file
+            );
+            finish_key_rotation(key_file, old_key_file)?;
+        } else {
+            log::info!("discard the unused credential key {:?}.", pending_path);
+            fs::remove_file(&pending_path)?;
+        }
+    }
+
+    // The rotation stopped before the old key file was removed.
+    for (key_file, old_key_file) in key_files.iter() {
+        let (path, old_path) = (get_file_path(key_file), get_file_path(old_key_file));
+        if path.exists() && old_path.exists() && is_committed(&path)? {
+            log::info!("remove the replaced credential key {:?}.", old_path);
+            fs::remove_file(&old_path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Encrypts the credentials still stored in plain text, does nothing while the store is locked.
+pub fn migrate_stored_credentials() -> Result<()> {
+    if !status().unlocked {
+        log::info!("the credential store is locked, skip the credentials migration.");
+        return Ok(());
+    }
+
+    let key = current_key()?;
+    rewrite_stored_credentials(
+        &|stored| {
+            if is_encrypted(stored) {
+                Ok(stored.to_string())
+            } else {
+                encrypt_with(&key, stored)
+            }
+        },
+        None,
+    )
+}
+
+/// Rewrites the credentials of the profiles and the configs in one batch, along with the
+/// fingerprint of the key file they're encrypted with after a key rotation.
+fn rewrite_stored_credentials(
+    rewrite_fn: &dyn Fn(&str) -> Result<String>,
+    key_fingerprint: Option<&str>,
+) -> Result<()> {
+    let mut db = rocksdb::get_conn();
+    let mut batches = vec![
+        connection_profile::rewrite_passwords(rewrite_fn, &db)?,
+        app_state::rewrite_config_passwords(rewrite_fn, &db)?,
+    ];
+    if let Some(fingerprint) = key_fingerprint {
+        batches.push((
+            CREDENTIALS_CF,
+            vec![(KEY_FINGERPRINT_KEY.to_string(), fingerprint.to_string())],
+        ));
+    }
+    RocksDataStore::write_batches(&batches, &mut db)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let key = [7; KEY_LEN];
+        let encrypted = encrypt_with(&key, "#postgres#").unwrap();
+
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("#postgres#"));
+        assert_ne!(encrypted, encrypt_with(&key, "#postgres#").unwrap());
+        assert_eq!(decrypt_with(&key, &encrypted).unwrap(), "#postgres#");
+        assert!(decrypt_with(&[8; KEY_LEN], &encrypted).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_plain_text() {
+        assert_eq!(decrypt_with(&[7; KEY_LEN], "anaconda").unwrap(), "anaconda");
+    }
+
+    #[test]
+    fn test_derive_key() {
+        let salt = [1; SALT_LEN];
+        assert_eq!(
+            derive_key("passphrase", &salt).unwrap(),
+            derive_key("passphrase", &salt).unwrap()
+        );
+        assert_ne!(
+            derive_key("passphrase", &salt).unwrap(),
+            derive_key("passphrase", &[2; SALT_LEN]).unwrap()
+        );
+    }
+}
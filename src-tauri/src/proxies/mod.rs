@@ -1,4 +1,8 @@
 pub mod app_state;
+pub mod connection_health;
+pub mod connection_pool;
+pub mod connection_profile;
+pub mod credentials;
 pub mod db_explain_tree;
 pub mod db_schema;
 pub mod dirs;
@@ -8,4 +12,7 @@ pub mod oracle;
 pub mod postgres;
 pub mod query_runner;
 pub mod rocksdb;
+pub mod run_history;
+pub mod run_registry;
+pub mod scan_progress;
 pub mod sql_common;
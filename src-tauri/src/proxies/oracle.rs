@@ -1,56 +1,398 @@
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
 
-use anyhow::Result;
-use lazy_static::lazy_static;
-use oracle::{sql_type::ToSql, Connection};
+use anyhow::{anyhow, Result};
+use oracle::{sql_type::ToSql, ConnStatus, Connection};
 use oracle::{ColumnInfo, Statement, StmtParam};
 
+use regex::Regex;
 use serde_json::Value;
 use uuid::Uuid;
 
 use crate::{core::oracle_param_mapper::map_params, utilities::oracle::get_row_values};
 
+use super::connection_health::{self, ConnectionStatus};
+use super::connection_pool::{ConnectionPool, PoolConnection, PooledConnection};
+use super::connection_profile::{self, ProfileClient};
+use super::run_registry::{self, CancelHandle};
 use super::sql_common::{
-    generate_param_stmt, Config, ConsoleManager, SQLClient, SQLError, SQLResult, SQLResultSet,
+    generate_param_stmt, Config, ConsoleConnection, ConsoleManager, FetchWindow, OracleConnectMode,
+    PagedStatement, SQLClient, SQLCursor, SQLError, SQLResult, SQLResultSet, SavePoint,
+    StatementKind, TransactionStatus,
 };
 
-impl ConsoleManager<Connection> {
-    pub fn get_console_conn(&mut self) -> Result<&Connection, SQLError> {
-        if self.console_client.is_none() || self.console_client.as_ref().unwrap().ping().is_err() {
-            log::debug!("try to obtain a new oracle console connection, is the connection present: {}, thread id: {:?}", self.console_client.is_some(), std::thread::current().id());
+/// A query result kept open on the console connection, the pages are fetched from it.
+///
+/// An oracle result set can't leave the thread which executed its query, so it's kept on a
+/// thread of its own until the cursor is dropped.
+pub struct OracleCursor {
+    requests: Sender<usize>,
+    pages: Receiver<Result<Vec<Vec<Value>>, SQLError>>,
+}
+
+impl OracleCursor {
+    /// Executes the query on a new thread, returns the cursor along with the column names.
+    fn open(
+        conn: &Arc<Connection>,
+        stmt: &str,
+        params: &[Value],
+    ) -> Result<(Self, Vec<String>), SQLError> {
+        let (opened_tx, opened) = mpsc::channel();
+        let (requests, requests_rx) = mpsc::channel();
+        let (pages_tx, pages) = mpsc::channel();
+        let (conn, stmt, params) = (Arc::clone(conn), stmt.to_string(), params.to_vec());
+        thread::spawn(move || {
+            if let Err(e) = Self::serve(&conn, &stmt, &params, &opened_tx, requests_rx, pages_tx) {
+                let _ = opened_tx.send(Err(e));
+            }
+        });
+
+        let columns = opened.recv().map_err(|_| Self::closed_error())??;
+        Ok((OracleCursor { requests, pages }, columns))
+    }
 
-            let conn_res = OracleClient::connect(&self.config)?;
+    fn serve(
+        conn: &Connection,
+        stmt: &str,
+        params: &[Value],
+        opened: &Sender<Result<Vec<String>, SQLError>>,
+        requests: Receiver<usize>,
+        pages: Sender<Result<Vec<Vec<Value>>, SQLError>>,
+    ) -> Result<(), SQLError> {
+        let (stmt, mapped_params) = OracleClient::bind_params(stmt, params, conn)?;
+        let unboxed_params: Vec<&dyn ToSql> = mapped_params.iter().map(|p| p.as_ref()).collect();
+        let mut prepared_stmt =
+            conn.prepare(&stmt, &[StmtParam::FetchArraySize(FETCH_ARRAY_SIZE)])?;
+        let mut result_set = prepared_stmt.query(&unboxed_params)?;
+        let column_info = result_set.column_info().to_vec();
+        let columns = column_info
+            .iter()
+            .map(|info| String::from(info.name()))
+            .collect();
+        if opened.send(Ok(columns)).is_err() {
+            return Ok(());
+        }
 
-            self.console_client = Some(conn_res);
+        // Ends once the cursor is dropped.
+        for count in requests {
+            let mut page = || -> Result<Vec<Vec<Value>>, SQLError> {
+                let mut rows = Vec::with_capacity(count);
+                for row_result in result_set.by_ref().take(count) {
+                    rows.push(get_row_values(&row_result?, &column_info)?);
+                }
+                Ok(rows)
+            };
+            if pages.send(page()).is_err() {
+                break;
+            }
         }
+
+        Ok(())
+    }
+
+    fn fetch(&self, count: usize) -> Result<Vec<Vec<Value>>, SQLError> {
+        self.requests
+            .send(count)
+            .map_err(|_| Self::closed_error())?;
+        self.pages.recv().map_err(|_| Self::closed_error())?
+    }
+
+    fn closed_error() -> SQLError {
+        SQLError::new(String::from("The cursor has been closed."))
+    }
+}
+
+impl ConsoleConnection for Arc<Connection> {
+    type Cursor = OracleCursor;
+}
+
+impl ConsoleManager<Arc<Connection>> {
+    pub fn get_console_conn(&mut self) -> Result<&Arc<Connection>, SQLError> {
+        let lost = self
+            .console_client
+            .as_ref()
+            .map_or(false, |conn| conn.ping().is_err());
+        if lost {
+            self.on_connection_lost()?;
+        }
+
+        if self.console_client.is_none() {
+            log::debug!(
+                "try to obtain a new oracle console connection, thread id: {:?}",
+                std::thread::current().id()
+            );
+
+            let mut conn_res = match OracleClient::connect(&self.config) {
+                Ok(conn) => conn,
+                Err(e) => {
+                    connection_health::publish(
+                        &self.profile,
+                        ConnectionStatus::Disconnected,
+                        Some(e.message()),
+                    );
+                    return Err(e);
+                }
+            };
+            conn_res.set_autocommit(self.autocommit);
+
+            self.end_transaction();
+            self.console_client = Some(Arc::new(conn_res));
+        }
+        connection_health::publish(&self.profile, ConnectionStatus::Connected, None);
+
         Ok(self.console_client.as_ref().unwrap())
     }
 }
 
-pub struct OracleClient(Arc<Mutex<ConsoleManager<Connection>>>);
+impl PoolConnection for Arc<Connection> {
+    fn is_broken(&self) -> bool {
+        !matches!(self.status(), Ok(ConnStatus::Normal))
+    }
+}
+
+/// The console connection of the profile along with the pooled connections of its scans.
+#[derive(Clone)]
+pub struct OracleClient(
+    Arc<Mutex<ConsoleManager<Arc<Connection>>>>,
+    Arc<ConnectionPool<Arc<Connection>>>,
+);
 
 static PARAM_SIGN: &str = ":";
+static FETCH_ARRAY_SIZE: u32 = 1000;
+static EZCONNECT_PATTERN: &str =
+    r"(?i)^(//)?[\w.\-]+(:\d{1,5})?(/[\w.\-$#]*)?(:(dedicated|shared|pooled))?(/[\w.\-]+)?$";
 
 impl OracleClient {
-    fn new(config: Config) -> Self {
-        Self(Arc::new(Mutex::new(ConsoleManager {
-            config: config,
-            console_client: None,
-            autocommit: false,
-        })))
+    pub fn new(profile: &str, config: Config) -> Self {
+        let pool = ConnectionPool::new(config.clone());
+        Self(
+            Arc::new(Mutex::new(ConsoleManager::new(profile.to_string(), config))),
+            pool,
+        )
     }
 
-    pub fn get_console_manager(&self) -> Result<MutexGuard<'_, ConsoleManager<Connection>>> {
+    /// The config of the profile.
+    pub fn config(&self) -> Config {
+        self.1.config()
+    }
+
+    /// Checks out a pooled connection for read-only work, it never sees the uncommitted changes
+    /// of the console.
+    pub fn get_pooled_conn(&self) -> Result<PooledConnection<Arc<Connection>>, SQLError> {
+        self.1.get(|config| {
+            log::debug!("open a new pooled oracle connection.");
+            Ok(Arc::new(Self::connect(config)?))
+        })
+    }
+
+    /// Opens a connection outside of the pool, for a scan running all its statements in one
+    /// transaction.
+    pub fn open_scan_conn(&self) -> Result<Arc<Connection>, SQLError> {
+        log::debug!("open a new oracle scan connection.");
+        Ok(Arc::new(Self::connect(&self.1.config())?))
+    }
+
+    /// Pings the opened console, reconnects it when lost and there is no uncommitted work.
+    /// A console busy with a statement is skipped.
+    pub fn check_health(&self) {
+        let mut manager = match self.0.try_lock() {
+            Ok(manager) => manager,
+            Err(_) => return,
+        };
+        if manager.console_client.is_none() {
+            return;
+        }
+        if let Err(e) = manager.get_console_conn() {
+            log::warn!(
+                "oracle console of profile {} is unhealthy: {}",
+                manager.profile,
+                e
+            );
+        }
+    }
+
+    pub fn get_console_manager(&self) -> Result<MutexGuard<'_, ConsoleManager<Arc<Connection>>>> {
         Ok(self.0.lock().unwrap())
     }
 
+    fn fetch_cursor_page(
+        manager: &mut ConsoleManager<Arc<Connection>>,
+        cursor_id: &str,
+        mut cursor: SQLCursor<OracleCursor>,
+    ) -> SQLResult {
+        let count = cursor.rows_to_fetch();
+        match cursor.server_cursor().fetch(count) {
+            Ok(rows) => {
+                let mut rs = cursor.next_page(rows);
+                manager.sync_cursor(cursor_id, cursor, &mut rs);
+                SQLResult::new_result(Some(rs))
+            }
+            Err(e) => SQLResult::new_error(e),
+        }
+    }
+
+    /// Maps the parameters of the statement, and numbers its placeholders to bind them.
+    fn bind_params(
+        stmt: &str,
+        params: &[Value],
+        conn: &Connection,
+    ) -> Result<(String, Vec<Box<dyn ToSql>>), SQLError> {
+        let mapped_params = map_params(Some(stmt), params, conn)?;
+        let stmt = if mapped_params.is_empty() {
+            stmt.to_string()
+        } else {
+            generate_param_stmt(stmt, PARAM_SIGN)
+        };
+
+        Ok((stmt, mapped_params))
+    }
+
+    /// Opens the query as a cursor on the connection, a statement which isn't a query is executed
+    /// right away.
+    fn open_cursor(
+        stmt: &str,
+        params: &[Value],
+        conn: &Arc<Connection>,
+        page_size: usize,
+    ) -> Result<PagedStatement<OracleCursor>, SQLError> {
+        let (param_stmt, mapped_params) = Self::bind_params(stmt, params, conn)?;
+        let mut unboxed_params = Vec::with_capacity(mapped_params.len());
+        for b in &mapped_params {
+            unboxed_params.push(b.as_ref());
+        }
+
+        let mut prepared_stmt =
+            conn.prepare(&param_stmt, &[StmtParam::FetchArraySize(FETCH_ARRAY_SIZE)])?;
+        let kind = Self::statement_kind(&prepared_stmt);
+        if kind != StatementKind::Query {
+            return Ok(PagedStatement::Executed(
+                kind,
+                Self::execute_prepared(
+                    &mut prepared_stmt,
+                    &unboxed_params,
+                    FetchWindow::default(),
+                )?,
+            ));
+        }
+
+        let (cursor, columns) = OracleCursor::open(conn, stmt, params)?;
+        Ok(PagedStatement::Cursor(SQLCursor::new(
+            cursor, columns, page_size,
+        )))
+    }
+
     fn connect(config: &Config) -> Result<Connection, SQLError> {
-        let connect_string: String = format!("(DESCRIPTION=(ADDRESS=(PROTOCOL=TCP)(HOST={})(PORT={}))(CONNECT_DATA=(SERVER=DEDICATED)(SID={})))", config.host, config.port, config.db);
-        Ok(Connection::connect(
-            &config.username,
-            &config.password,
-            connect_string,
-        )?)
+        let connect_string = Self::build_connect_string(config)?;
+        let conn = Connection::connect(&config.username, &config.password, connect_string)?;
+        Self::init_session(&conn, config)?;
+
+        Ok(conn)
+    }
+
+    pub fn build_connect_string(config: &Config) -> Result<String, SQLError> {
+        let mode = config.connect_mode();
+        match mode {
+            OracleConnectMode::Sid | OracleConnectMode::ServiceName => {
+                let host = config.host.trim();
+                let port = config.port.trim();
+                let db = config.db.trim();
+                if host.is_empty() || db.is_empty() {
+                    return Err(SQLError::new_str(
+                        "The host and the database of the connection are required.",
+                    ));
+                }
+                if port.parse::<u16>().is_err() {
+                    return Err(SQLError::new(format!("Invalid port: {}", port)));
+                }
+
+                let connect_data = match mode {
+                    OracleConnectMode::Sid => format!("SID={}", db),
+                    _ => format!("SERVICE_NAME={}", db),
+                };
+                Ok(format!("(DESCRIPTION=(ADDRESS=(PROTOCOL=TCP)(HOST={})(PORT={}))(CONNECT_DATA=(SERVER=DEDICATED)({})))", host, port, connect_data))
+            }
+            OracleConnectMode::Descriptor => {
+                let descriptor = Self::required_connect_string(config, "connect descriptor")?;
+                Self::validate_descriptor(descriptor)?;
+                Ok(descriptor.to_string())
+            }
+            OracleConnectMode::TnsAlias => {
+                let alias = Self::required_connect_string(config, "TNS alias")?;
+                if !alias
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_.-$#".contains(c))
+                {
+                    return Err(SQLError::new(format!("Invalid TNS alias: {}", alias)));
+                }
+                Ok(alias.to_string())
+            }
+            OracleConnectMode::EzConnect => {
+                let url = Self::required_connect_string(config, "EZConnect URL")?;
+                let re = Regex::new(EZCONNECT_PATTERN).unwrap();
+                if !re.is_match(url) {
+                    return Err(SQLError::new(format!(
+                        "Invalid EZConnect URL: {}, expect [//]host[:port][/service_name][:server][/instance_name].",
+                        url
+                    )));
+                }
+                Ok(url.to_string())
+            }
+        }
+    }
+
+    fn required_connect_string<'a>(config: &'a Config, name: &str) -> Result<&'a str, SQLError> {
+        match config.connect_string.as_deref().map(str::trim) {
+            Some(s) if !s.is_empty() => Ok(s),
+            _ => Err(SQLError::new(format!("The {} is required.", name))),
+        }
+    }
+
+    fn validate_descriptor(descriptor: &str) -> Result<(), SQLError> {
+        if !descriptor.starts_with('(')
+            || !descriptor
+                .to_uppercase()
+                .replace(char::is_whitespace, "")
+                .starts_with("(DESCRIPTION")
+        {
+            return Err(SQLError::new_str(
+                "The connect descriptor must start with (DESCRIPTION=.",
+            ));
+        }
+
+        let mut depth = 0;
+        for (i, c) in descriptor.chars().enumerate() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 => {
+                    return Err(SQLError::new(format!(
+                        "Unbalanced parenthesis at position {} of the connect descriptor.",
+                        i
+                    )))
+                }
+                ')' => depth -= 1,
+                _ => {}
+            }
+        }
+        if depth != 0 {
+            return Err(SQLError::new_str(
+                "Unclosed parenthesis in the connect descriptor.",
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn init_session(conn: &Connection, config: &Config) -> Result<(), SQLError> {
+        if let Some(timeout) = config.statement_timeout() {
+            conn.set_call_timeout(Some(timeout))?;
+        }
+        for stmt in config.init_statements() {
+            log::debug!("execute oracle session init statement: {}", stmt);
+            conn.execute(stmt, &[])?;
+        }
+
+        Ok(())
     }
 
     pub fn validate_stmt(stmt: &str, conn: &Connection) -> SQLResult {
@@ -91,7 +433,7 @@ impl OracleClient {
         let mut new_statement = stmt.to_owned();
         new_statement.insert_str(insert_pos, &gather_plan_statistics_hint);
 
-        let res = Self::execute_stmt(&new_statement, params, conn)?;
+        let res = Self::execute_stmt(&new_statement, params, conn, FetchWindow::default())?;
         let statistics_res = Self::retrieve_statistics(&new_statement, conn);
 
         Ok(match statistics_res {
@@ -107,7 +449,8 @@ impl OracleClient {
             "SELECT sql_id, child_number FROM V$SQL WHERE sql_text = '{}'",
             stmt
         );
-        let sql_id_res = Self::execute_stmt(&sql_id_stmt, &Vec::new(), conn)?;
+        let sql_id_res =
+            Self::execute_stmt(&sql_id_stmt, &Vec::new(), conn, FetchWindow::default())?;
 
         let (sql_id, child_number) = match sql_id_res.get_rows() {
             Some(rows) => {
@@ -120,13 +463,19 @@ impl OracleClient {
         };
 
         let statistics_stmt = "SELECT * FROM TABLE(DBMS_XPLAN.DISPLAY_CURSOR(?,?,'ALLSTATS LAST'))";
-        Self::execute_stmt(statistics_stmt, &vec![sql_id, child_number], conn)
+        Self::execute_stmt(
+            statistics_stmt,
+            &vec![sql_id, child_number],
+            conn,
+            FetchWindow::default(),
+        )
     }
 
     pub fn execute_stmt(
         stmt: &str,
         params: &[Value],
         conn: &Connection,
+        window: FetchWindow,
     ) -> Result<SQLResultSet, SQLError> {
         let mapped_params = map_params(Some(stmt), params, conn)?;
         let mut unboxed_params = Vec::with_capacity(mapped_params.len());
@@ -136,9 +485,28 @@ impl OracleClient {
 
         if mapped_params.len() > 0 {
             let replaced_stmt = generate_param_stmt(stmt, PARAM_SIGN);
-            Self::execute_stmt_mapped(&replaced_stmt, &unboxed_params, conn)
+            Self::execute_stmt_mapped(&replaced_stmt, &unboxed_params, conn, window)
+        } else {
+            Self::execute_stmt_mapped(stmt, &unboxed_params, conn, window)
+        }
+    }
+
+    /// Classifies the statement as parsed by the server, without executing it.
+    pub fn classify_statement(stmt: &str, conn: &Connection) -> Result<StatementKind, SQLError> {
+        Ok(Self::statement_kind(&conn.prepare(stmt, &[])?))
+    }
+
+    fn statement_kind(prepared: &Statement) -> StatementKind {
+        if prepared.is_query() {
+            StatementKind::Query
+        } else if prepared.is_dml() {
+            StatementKind::Dml
+        } else if prepared.is_ddl() {
+            StatementKind::Ddl
+        } else if prepared.is_plsql() {
+            StatementKind::Plsql
         } else {
-            Self::execute_stmt_mapped(stmt, &unboxed_params, conn)
+            StatementKind::Other
         }
     }
 
@@ -146,16 +514,19 @@ impl OracleClient {
         stmt_str: &str,
         params: &[&dyn ToSql],
         conn: &Connection,
+        window: FetchWindow,
     ) -> Result<SQLResultSet, SQLError> {
         log::debug!("execute oracle statement: {}", stmt_str);
-        let mut prepared_stmt = conn.prepare(stmt_str, &[StmtParam::FetchArraySize(100000)])?;
+        let mut prepared_stmt =
+            conn.prepare(stmt_str, &[StmtParam::FetchArraySize(FETCH_ARRAY_SIZE)])?;
 
-        Self::execute_prepared(&mut prepared_stmt, params)
+        Self::execute_prepared(&mut prepared_stmt, params, window)
     }
 
     pub fn execute_prepared(
         stmt: &mut Statement,
         params: &[&dyn ToSql],
+        window: FetchWindow,
     ) -> Result<SQLResultSet, SQLError> {
         let res = if stmt.is_query() {
             let mut result_set = stmt.query(params)?;
@@ -172,14 +543,19 @@ impl OracleClient {
                 columns.push(String::from(info.name()));
             }
 
+            let mut truncated = false;
             for row_result in result_set.by_ref() {
-                row_count += 1;
                 let row = row_result?;
+                if row_count == window.limit {
+                    truncated = true;
+                    break;
+                }
+                row_count += 1;
                 let row_values = get_row_values(&row, &column_info)?;
                 rows.push(row_values);
             }
 
-            SQLResultSet::new(row_count, Some(columns), Some(rows))
+            SQLResultSet::new(row_count, Some(columns), Some(rows)).with_truncated(truncated)
         } else {
             stmt.execute(params)?;
             let row_count = stmt.row_count()? as usize;
@@ -192,10 +568,13 @@ impl OracleClient {
 }
 
 impl SQLClient for OracleClient {
-    fn set_config(&'static self, db_config: Config) -> Result<SQLResult> {
+    fn set_config(&self, db_config: Config) -> Result<SQLResult> {
         let mut console_manager = self.get_console_manager()?;
+        self.1.reset(db_config.clone());
         console_manager.config = db_config;
         console_manager.console_client = None;
+        console_manager.end_transaction();
+        console_manager.cursors.clear();
 
         match Self::connect(&console_manager.config) {
             Ok(_) => Ok(SQLResult::new_result(None)),
@@ -203,7 +582,7 @@ impl SQLClient for OracleClient {
         }
     }
 
-    fn set_autocommit(&'static self, autocommit: bool) -> Result<SQLResult> {
+    fn set_autocommit(&self, autocommit: bool) -> Result<SQLResult> {
         let mut manager = self.get_console_manager()?;
         if manager.autocommit == autocommit {
             log::warn!(
@@ -216,57 +595,118 @@ impl SQLClient for OracleClient {
         log::debug!("Set oracle autocommit: {}", autocommit);
         manager.autocommit = autocommit;
         if let Some(conn) = manager.console_client.as_mut() {
-            conn.set_autocommit(autocommit);
+            match Arc::get_mut(conn) {
+                Some(conn) => conn.set_autocommit(autocommit),
+                None => {
+                    return Ok(SQLResult::new_error(SQLError::new_str(
+                        "The console connection is busy, try again later.",
+                    )))
+                }
+            }
             if autocommit {
                 conn.commit()?;
             }
         }
+        if autocommit {
+            manager.end_transaction();
+        }
 
         Ok(SQLResult::Result(None))
     }
 
-    fn commit_console(&'static self) -> Result<SQLResult> {
-        let manager = self.get_console_manager()?;
+    fn commit_console(&self) -> Result<SQLResult> {
+        let mut manager = self.get_console_manager()?;
         if let Some(conn) = manager.console_client.as_ref() {
             conn.commit()?;
         }
+        manager.end_transaction();
 
         Ok(SQLResult::new_result(None))
     }
 
-    fn rollback_console(&'static self) -> Result<SQLResult> {
-        let manager = self.get_console_manager()?;
+    fn rollback_console(&self) -> Result<SQLResult> {
+        let mut manager = self.get_console_manager()?;
+
+        // Rolling back a lost connection acknowledges the lost transaction.
+        let lost = match manager.console_client.as_ref() {
+            Some(conn) => match conn.rollback() {
+                Ok(_) => false,
+                Err(e) if conn.ping().is_ok() => return Err(e.into()),
+                Err(_) => true,
+            },
+            None => false,
+        };
+        if lost {
+            manager.console_client = None;
+        }
+        manager.end_transaction();
+
+        Ok(SQLResult::new_result(None))
+    }
+
+    fn add_savepoint(&self, savepoint: &str) -> Result<SQLResult> {
+        let mut manager = self.get_console_manager()?;
+        if manager.autocommit {
+            return Ok(SQLResult::new_error(SQLError::new_str(
+                "Can't add savepoint when autocommit on.",
+            )));
+        }
+        if let Err(e) = SavePoint::validate_name(savepoint) {
+            return Ok(SQLResult::new_error(e));
+        }
+
+        let conn = manager.get_console_conn()?;
+        if let Err(e) = conn.execute(&format!("SAVEPOINT {}", savepoint), &[]) {
+            return Ok(SQLResult::new_error(SQLError::from(e)));
+        }
+        manager.replace_savepoint(savepoint);
+
+        Ok(SQLResult::new_result(None))
+    }
+
+    fn rollback_to_savepoint(&self, savepoint: &str) -> Result<SQLResult> {
+        let mut manager = self.get_console_manager()?;
+        if !manager.has_savepoint(savepoint) {
+            return Ok(SQLResult::new_error(SQLError::new(format!(
+                "Savepoint {} doesn't exist.",
+                savepoint
+            ))));
+        }
 
         if let Some(conn) = manager.console_client.as_ref() {
-            conn.rollback()?;
+            if let Err(e) = conn.execute(&format!("ROLLBACK TO SAVEPOINT {}", savepoint), &[]) {
+                return Ok(SQLResult::new_error(SQLError::from(e)));
+            }
         }
+        manager.rollback_savepoints_to(savepoint);
 
         Ok(SQLResult::new_result(None))
     }
 
-    fn add_savepoint(&'static self, _savepoint: &str) -> Result<SQLResult> {
-        todo!()
-        // if self.autocommit {
-        //     return Ok(SQLResult::new_error(SQLError::new(
-        //         "Can't add savepoint when autocommit on.".to_string(),
-        //     )));
-        // }
-        // if let Some(conn_lock) = &self.conn {
-        //     let conn = conn_lock.lock().unwrap();
-        //     conn.execute(&format!("SAVEPOINT {}", savepoint), &[])?;
-        // }
+    fn release_savepoint(&self, savepoint: &str) -> Result<SQLResult> {
+        let mut manager = self.get_console_manager()?;
+        if !manager.has_savepoint(savepoint) {
+            return Ok(SQLResult::new_error(SQLError::new(format!(
+                "Savepoint {} doesn't exist.",
+                savepoint
+            ))));
+        }
+
+        // Oracle has no RELEASE SAVEPOINT, the savepoint is only dropped from the tracked list
+        // and ends with the transaction.
+        manager.release_savepoints_from(savepoint);
 
-        // Ok(SQLResult::new_result(None))
+        Ok(SQLResult::new_result(None))
     }
 
-    fn rollback_to_savepoint(&'static self, _savepoint: &str) -> Result<SQLResult> {
-        todo!()
-        // if let Some(conn_lock) = &self.conn {
-        //     let conn = conn_lock.lock().unwrap();
-        //     conn.execute(&format!("ROLLBACK TO {}", savepoint), &[])?;
-        // }
+    fn savepoints(&self) -> Result<Vec<SavePoint>> {
+        let manager = self.get_console_manager()?;
+        Ok(manager.savepoints.clone())
+    }
 
-        // Ok(SQLResult::new_result(None))
+    fn transaction_status(&self) -> Result<TransactionStatus> {
+        let manager = self.get_console_manager()?;
+        Ok(manager.transaction_status())
     }
 
     fn execute_stmt(
@@ -274,21 +714,33 @@ impl SQLClient for OracleClient {
         statement: &str,
         parameters: &[Value],
         with_statistics: bool,
+        run_id: Option<&str>,
     ) -> Result<SQLResult> {
         let mut manager = self.get_console_manager()?;
         let conn = manager.get_console_conn()?;
+        let _guard = run_registry::attach_statement(run_id, CancelHandle::Oracle(Arc::clone(conn)));
+        // A statement which fails to prepare fails to execute as well.
+        let kind = Self::classify_statement(statement, conn).unwrap_or(StatementKind::Other);
 
         let exec_res = if with_statistics {
             Self::execute_stmt_with_statistics(statement, parameters, conn)
         } else {
-            let res = Self::execute_stmt(statement, parameters, conn);
+            let res = Self::execute_stmt(statement, parameters, conn, FetchWindow::default());
             res.map(|rs| SQLResult::new_result(Some(rs)))
         };
 
-        exec_res.or_else(|e| Ok(SQLResult::new_error(e)))
+        match exec_res {
+            Ok(res) => {
+                if let Some(rs) = res.result_set() {
+                    manager.track_result(kind, rs);
+                }
+                Ok(res)
+            }
+            Err(e) => Ok(SQLResult::new_error(e)),
+        }
     }
 
-    fn validate_stmts(&'static self, stmts: &[&str]) -> Result<Vec<SQLResult>> {
+    fn validate_stmts(&self, stmts: &[&str]) -> Result<Vec<SQLResult>> {
         let console_manager = self.get_console_manager()?;
         let conn = Self::connect(&console_manager.config)?;
 
@@ -299,18 +751,147 @@ impl SQLClient for OracleClient {
 
         Ok(res)
     }
+
+    fn execute_stmt_paged(
+        &self,
+        statement: &str,
+        parameters: &[Value],
+        page_size: usize,
+        run_id: Option<&str>,
+    ) -> Result<SQLResult> {
+        let mut manager = self.get_console_manager()?;
+        let conn = manager.get_console_conn()?;
+        let _guard = run_registry::attach_statement(run_id, CancelHandle::Oracle(Arc::clone(conn)));
+
+        match Self::open_cursor(statement, parameters, conn, page_size) {
+            Ok(PagedStatement::Cursor(cursor)) => Ok(Self::fetch_cursor_page(
+                &mut manager,
+                &SQLCursor::<OracleCursor>::new_id(),
+                cursor,
+            )),
+            Ok(PagedStatement::Executed(kind, rs)) => {
+                manager.track_result(kind, &rs);
+                Ok(SQLResult::new_result(Some(rs)))
+            }
+            Err(e) => Ok(SQLResult::new_error(e)),
+        }
+    }
+
+    fn fetch_next_page(&self, cursor: &str, run_id: Option<&str>) -> Result<SQLResult> {
+        let mut manager = self.get_console_manager()?;
+        let sql_cursor = match manager.take_cursor(cursor) {
+            Some(c) => c,
+            None => {
+                return Ok(SQLResult::new_error(SQLError::new(format!(
+                    "Cursor {} doesn't exist or has been closed.",
+                    cursor
+                ))))
+            }
+        };
+        let conn = manager.get_console_conn()?;
+        let _guard = run_registry::attach_statement(run_id, CancelHandle::Oracle(Arc::clone(conn)));
+
+        Ok(Self::fetch_cursor_page(&mut manager, cursor, sql_cursor))
+    }
+
+    fn close_cursor(&self, cursor: &str) -> Result<bool> {
+        Ok(self.get_console_manager()?.close_cursor(cursor))
+    }
 }
 
-lazy_static! {
-    static ref INSTANCE: OracleClient = OracleClient::new(Config::new(
-        "localhost",
-        "1521",
-        "anaconda",
-        "anaconda",
-        "anaconda"
-    ));
+pub fn get_proxy(profile: &str) -> Result<OracleClient> {
+    match connection_profile::get_client(profile)? {
+        ProfileClient::Oracle(client) => Ok(client),
+        _ => Err(anyhow!(
+            "The connection profile {} is not an Oracle profile.",
+            profile
+        )),
+    }
 }
 
-pub fn get_proxy() -> &'static OracleClient {
-    &*INSTANCE
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with(mode: OracleConnectMode, connect_string: Option<&str>) -> Config {
+        let mut config = Config::new("localhost", "1521", "ORCL", "scott", "tiger");
+        config.connect_mode = Some(mode);
+        config.connect_string = connect_string.map(String::from);
+        config
+    }
+
+    #[test]
+    fn test_build_sid_and_service_name() {
+        let sid = OracleClient::build_connect_string(&config_with(OracleConnectMode::Sid, None));
+        assert!(sid.unwrap().contains("(SID=ORCL)"));
+
+        let service =
+            OracleClient::build_connect_string(&config_with(OracleConnectMode::ServiceName, None));
+        assert!(service.unwrap().contains("(SERVICE_NAME=ORCL)"));
+
+        let mut bad_port = config_with(OracleConnectMode::Sid, None);
+        bad_port.port = "15x1".to_string();
+        assert!(OracleClient::build_connect_string(&bad_port).is_err());
+    }
+
+    #[test]
+    fn test_validate_descriptor() {
+        let valid = "(DESCRIPTION=(ADDRESS=(PROTOCOL=TCP)(HOST=db)(PORT=1521))(CONNECT_DATA=(SERVICE_NAME=pdb1)))";
+        assert_eq!(
+            OracleClient::build_connect_string(&config_with(
+                OracleConnectMode::Descriptor,
+                Some(valid)
+            ))
+            .unwrap(),
+            valid
+        );
+
+        for invalid in &[
+            "(DESCRIPTION=(ADDRESS=(HOST=db)",
+            "(DESCRIPTION=(ADDRESS=(HOST=db)))))",
+            "ADDRESS=(HOST=db)",
+        ] {
+            assert!(OracleClient::build_connect_string(&config_with(
+                OracleConnectMode::Descriptor,
+                Some(invalid)
+            ))
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn test_validate_tns_alias_and_ezconnect() {
+        assert!(OracleClient::build_connect_string(&config_with(
+            OracleConnectMode::TnsAlias,
+            Some("STAGING_DB.WORLD")
+        ))
+        .is_ok());
+        assert!(OracleClient::build_connect_string(&config_with(
+            OracleConnectMode::TnsAlias,
+            Some("bad alias")
+        ))
+        .is_err());
+        assert!(OracleClient::build_connect_string(&config_with(
+            OracleConnectMode::TnsAlias,
+            None
+        ))
+        .is_err());
+
+        for url in &[
+            "//db.local:1521/pdb1",
+            "db.local/pdb1:dedicated/inst1",
+            "db",
+        ] {
+            assert!(OracleClient::build_connect_string(&config_with(
+                OracleConnectMode::EzConnect,
+                Some(url)
+            ))
+            .is_ok());
+        }
+        assert!(OracleClient::build_connect_string(&config_with(
+            OracleConnectMode::EzConnect,
+            Some("db.local:port/pdb1")
+        ))
+        .is_err());
+    }
 }
@@ -1,23 +1,33 @@
-use std::{sync::Arc, thread};
+use std::{collections::HashMap, sync::Arc, thread};
 
 use anyhow::{anyhow, Result};
 use futures::{
     future,
     lock::{Mutex, MutexGuard},
+    pin_mut, TryStreamExt,
 };
 use lazy_static::lazy_static;
-
+use openssl::ssl::{SslConnector, SslFiletype, SslMethod, SslVerifyMode};
+use postgres_openssl::MakeTlsConnector;
 use serde_json::Value;
+use sqlparser::{ast, dialect::PostgreSqlDialect, parser::Parser};
 use tauri::async_runtime;
 use tokio::{runtime::Runtime, spawn};
-use tokio_postgres::{error::DbError, types::ToSql, Client, Error, NoTls, Statement};
+use tokio_postgres::{error::DbError, types::ToSql, Client, Error, Statement};
 
 use crate::{
     core::postgres_param_mapper::map_to_sql, proxies::sql_common::generate_param_stmt,
     utilities::postgres::get_row_values,
 };
 
-use super::sql_common::{Config, ConsoleManager, SQLClient, SQLError, SQLResult, SQLResultSet};
+use super::connection_health::{self, ConnectionStatus};
+use super::connection_pool::{ConnectionPool, PoolConnection, PooledConnection};
+use super::connection_profile::{self, ProfileClient};
+use super::run_registry::{self, CancelHandle};
+use super::sql_common::{
+    Config, ConsoleConnection, ConsoleManager, FetchWindow, PagedStatement, SQLClient, SQLCursor,
+    SQLError, SQLResult, SQLResultSet, SavePoint, SslMode, StatementKind, TransactionStatus,
+};
 
 pub struct QueryVlidationResult {
     pub pass: bool,
@@ -40,46 +50,202 @@ impl QueryVlidationResult {
 
 static PARAM_SIGN: &str = "$";
 
+/// The paged queries are declared as cursors WITH HOLD, so they outlive the commits of the
+/// console and work in the autocommit mode too. A rollback closes them.
+impl ConsoleConnection for Client {
+    /// The name of the cursor declared on the console connection.
+    type Cursor = String;
+}
+
 impl ConsoleManager<Client> {
-    pub async fn get_console_conn(&mut self) -> Result<&Client, Error> {
-        if self.console_client.is_none() || self.console_client.as_ref().unwrap().is_closed() {
-            log::debug!("try to obtain a new postgres console connection, is the connection present: {}, thread id: {:?}", self.console_client.is_some(), std::thread::current().id());
+    pub async fn get_console_conn(&mut self) -> Result<&Client, SQLError> {
+        let lost = self
+            .console_client
+            .as_ref()
+            .map_or(false, |client| client.is_closed());
+        if lost {
+            self.on_connection_lost()?;
+        }
 
-            let conn_res = PostgresProxy::connect(&self.config).await?;
-            conn_res
-                .batch_execute("SET search_path TO anaconda")
-                .await?;
+        if self.console_client.is_none() {
+            log::debug!(
+                "try to obtain a new postgres console connection, thread id: {:?}",
+                std::thread::current().id()
+            );
+
+            let conn_res = match PostgresProxy::connect(&self.config).await {
+                Ok(client) => client,
+                Err(e) => {
+                    connection_health::publish(
+                        &self.profile,
+                        ConnectionStatus::Disconnected,
+                        Some(e.message()),
+                    );
+                    return Err(e);
+                }
+            };
 
             if !self.autocommit {
                 PostgresProxy::start_transaction(&conn_res).await?;
             }
 
+            self.end_transaction();
             self.console_client = Some(conn_res);
         }
+        connection_health::publish(&self.profile, ConnectionStatus::Connected, None);
+
         Ok(self.console_client.as_ref().unwrap())
     }
+
+    /// Returns the console connection along with the handle which cancels its running statement.
+    pub async fn get_console_conn_with_cancel(
+        &mut self,
+    ) -> Result<(&Client, CancelHandle), SQLError> {
+        self.get_console_conn().await?;
+        let client = self.console_client.as_ref().unwrap();
+        let tls = PostgresProxy::tls_connector(&self.config)?;
+
+        Ok((client, CancelHandle::Postgres(client.cancel_token(), tls)))
+    }
+}
+
+/// A pooled connection, the statements are prepared once per connection.
+pub struct PooledClient {
+    client: Client,
+    tls: MakeTlsConnector,
+    statements: HashMap<String, Statement>,
+}
+
+impl PooledClient {
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle::Postgres(self.client.cancel_token(), self.tls.clone())
+    }
+
+    pub async fn prepare(&mut self, stmt: &str) -> Result<Statement, SQLError> {
+        if let Some(prepared) = self.statements.get(stmt) {
+            return Ok(prepared.clone());
+        }
+
+        let prepared = self.client.prepare(stmt).await?;
+        self.statements.insert(stmt.to_string(), prepared.clone());
+        Ok(prepared)
+    }
 }
 
-pub struct PostgresProxy(Arc<Mutex<ConsoleManager<Client>>>);
+impl PoolConnection for PooledClient {
+    fn is_broken(&self) -> bool {
+        self.client.is_closed()
+    }
+}
+
+/// The console connection of the profile along with the pooled connections of its scans.
+#[derive(Clone)]
+pub struct PostgresProxy(
+    Arc<Mutex<ConsoleManager<Client>>>,
+    Arc<ConnectionPool<PooledClient>>,
+);
 
 impl PostgresProxy {
-    fn new(config: Config) -> Self {
-        let console_manager = Arc::new(Mutex::new(ConsoleManager {
-            autocommit: false,
-            config,
-            console_client: None,
-        }));
-        PostgresProxy(console_manager)
+    pub fn new(profile: &str, config: Config) -> Self {
+        let pool = ConnectionPool::new(config.clone());
+        let console_manager =
+            Arc::new(Mutex::new(ConsoleManager::new(profile.to_string(), config)));
+        PostgresProxy(console_manager, pool)
+    }
+
+    /// The config of the profile.
+    pub fn config(&self) -> Config {
+        self.1.config()
+    }
+
+    /// Checks out a pooled connection for read-only work, it never sees the uncommitted changes
+    /// of the console. Blocks until a connection is available.
+    pub fn get_pooled_conn(&self) -> Result<PooledConnection<PooledClient>, SQLError> {
+        self.1.get(|config| {
+            log::debug!("open a new pooled postgres connection.");
+            Self::connect_pooled(config, false)
+        })
+    }
+
+    /// Opens a connection outside of the pool with a transaction started, for a scan running all
+    /// its statements in one transaction.
+    pub fn open_scan_conn(&self) -> Result<PooledClient, SQLError> {
+        log::debug!("open a new postgres scan connection.");
+        Self::connect_pooled(&self.1.config(), true)
+    }
+
+    fn connect_pooled(config: &Config, in_transaction: bool) -> Result<PooledClient, SQLError> {
+        let handle = async_runtime::handle();
+        let config = config.clone();
+        thread::spawn(move || {
+            handle.block_on(async {
+                let client = Self::connect(&config).await?;
+                if in_transaction {
+                    Self::start_transaction(&client).await?;
+                }
+                let tls = Self::tls_connector(&config)?;
+                Ok::<_, SQLError>(PooledClient {
+                    client,
+                    tls,
+                    statements: HashMap::new(),
+                })
+            })
+        })
+        .join()
+        .unwrap()
+    }
+
+    /// Pings the opened console, reconnects it when lost and there is no uncommitted work.
+    /// A console busy with a statement is skipped.
+    pub fn check_health(&self) {
+        let handle = async_runtime::handle();
+        let proxy = self.clone();
+        thread::spawn(move || {
+            handle.block_on(async {
+                let mut manager = match proxy.0.try_lock() {
+                    Some(manager) => manager,
+                    None => return,
+                };
+                let alive = match manager.console_client.as_ref() {
+                    // A db error means the server has answered.
+                    Some(client) => client
+                        .simple_query("")
+                        .await
+                        .map_or_else(|e| e.code().is_some(), |_| true),
+                    None => return,
+                };
+                if !alive {
+                    if let Err(e) = manager.on_connection_lost() {
+                        log::warn!("postgres console of profile {}: {}", manager.profile, e);
+                        return;
+                    }
+                }
+                if let Err(e) = manager.get_console_conn().await {
+                    log::warn!(
+                        "postgres console of profile {} is unhealthy: {}",
+                        manager.profile,
+                        e
+                    );
+                }
+            })
+        })
+        .join()
+        .unwrap()
     }
 
     pub async fn get_console_manager(&self) -> Result<MutexGuard<'_, ConsoleManager<Client>>> {
         Ok(self.0.lock().await)
     }
 
-    async fn connect(config: &Config) -> Result<Client, Error> {
+    async fn connect(config: &Config) -> Result<Client, SQLError> {
         log::debug!("trying to get new postgres connection.");
+        let tls = Self::tls_connector(config)?;
         let (client, connection) =
-            tokio_postgres::connect(&config.to_key_value_string(), NoTls).await?;
+            tokio_postgres::connect(&config.to_key_value_string(), tls).await?;
         log::debug!("got new postgres connection");
 
         spawn(async move {
@@ -88,23 +254,98 @@ impl PostgresProxy {
             }
             log::debug!("postgres connection closed.");
         });
+        Self::init_session(&client, config).await?;
 
         Ok(client)
     }
 
-    pub fn validate_stmts(stmts: Vec<&str>) -> Result<Vec<QueryVlidationResult>, Error> {
+    /// The TLS connector of the config, built once and shared by the connections and the cancel
+    /// requests of the profiles with the same TLS settings.
+    pub fn tls_connector(config: &Config) -> Result<MakeTlsConnector, SQLError> {
+        let mut connectors = TLS_CONNECTORS.lock().unwrap();
+        let settings = TlsSettings::new(config);
+        if let Some(connector) = connectors.get(&settings) {
+            return Ok(connector.clone());
+        }
+
+        let connector = Self::make_tls_connector(config).map_err(|e| {
+            SQLError::new(format!(
+                "Failed to set up the postgres TLS connector: {}",
+                e
+            ))
+        })?;
+        connectors.insert(settings, connector.clone());
+        Ok(connector)
+    }
+
+    /// Whether the server certificate chain and host name are verified, by the ssl mode.
+    fn verify_mode(config: &Config) -> (SslVerifyMode, bool) {
+        let ssl_mode = config.ssl_mode();
+        let verify_chain = ssl_mode == SslMode::VerifyFull
+            || (ssl_mode == SslMode::Require && config.ssl_root_cert.is_some());
+        let verify_mode = if verify_chain {
+            SslVerifyMode::PEER
+        } else {
+            SslVerifyMode::NONE
+        };
+
+        (verify_mode, ssl_mode == SslMode::VerifyFull)
+    }
+
+    fn make_tls_connector(config: &Config) -> Result<MakeTlsConnector> {
+        let mut builder = SslConnector::builder(SslMethod::tls())?;
+        if let Some(ca_file) = &config.ssl_root_cert {
+            builder.set_ca_file(ca_file)?;
+        }
+        if let Some(cert_file) = &config.ssl_cert {
+            builder.set_certificate_chain_file(cert_file)?;
+        }
+        if let Some(key_file) = &config.ssl_key {
+            builder.set_private_key_file(key_file, SslFiletype::PEM)?;
+        }
+
+        let (verify_mode, verify_hostname) = Self::verify_mode(config);
+        builder.set_verify(verify_mode);
+
+        let mut connector = MakeTlsConnector::new(builder.build());
+        if !verify_hostname {
+            connector.set_callback(|connect_config, _| {
+                connect_config.set_verify_hostname(false);
+                Ok(())
+            });
+        }
+
+        Ok(connector)
+    }
+
+    async fn init_session(client: &Client, config: &Config) -> Result<(), Error> {
+        if let Some(timeout) = config.statement_timeout {
+            client
+                .batch_execute(&format!("SET statement_timeout = {}", timeout))
+                .await?;
+        }
+        for stmt in config.init_statements() {
+            log::debug!("execute postgres session init statement: {}", stmt);
+            client.batch_execute(stmt).await?;
+        }
+
+        Ok(())
+    }
+
+    pub fn validate_stmts(
+        config: &Config,
+        stmts: Vec<&str>,
+    ) -> Result<Vec<QueryVlidationResult>, SQLError> {
         let rt = Runtime::new().unwrap();
         crossbeam::thread::scope(|s| {
             s.spawn(|_| {
                 rt.block_on(async {
                     // use a new connection to do the query validation
-                    let manager = get_proxy().get_console_manager().await.unwrap();
-                    let client = Self::connect(&manager.config).await?;
-                    client.execute("SET search_path TO anaconda", &[]).await?;
+                    let client = Self::connect(config).await?;
                     let pending_tasks = stmts
                         .iter()
-                        .map(|&s| PostgresProxy::validate_stmt(s, &client));
-                    future::try_join_all(pending_tasks).await
+                        .map(|&s| PostgresProxy::validate_stmt(s, config, &client));
+                    Ok(future::try_join_all(pending_tasks).await?)
                 })
             })
             .join()
@@ -113,9 +354,13 @@ impl PostgresProxy {
         .unwrap()
     }
 
-    pub async fn validate_stmt(stmt: &str, client: &Client) -> Result<QueryVlidationResult, Error> {
+    pub async fn validate_stmt(
+        stmt: &str,
+        config: &Config,
+        client: &Client,
+    ) -> Result<QueryVlidationResult, Error> {
         log::debug!("validating query: {}", stmt);
-        let company_stmt = stmt.replace("COMPANY_", "GREENCO.");
+        let company_stmt = config.default_schema_stmt(stmt);
         let mapped_stmt = generate_param_stmt(&company_stmt, PARAM_SIGN);
 
         match client.prepare(&mapped_stmt).await {
@@ -127,9 +372,13 @@ impl PostgresProxy {
         }
     }
 
-    pub async fn validate_statement(stmt: String, client: &Client) -> Result<SQLResult, Error> {
+    pub async fn validate_statement(
+        stmt: String,
+        config: &Config,
+        client: &Client,
+    ) -> Result<SQLResult, Error> {
         log::debug!("validating query: {}", stmt);
-        let company_stmt = stmt.replace("COMPANY_", "GREENCO.");
+        let company_stmt = config.default_schema_stmt(&stmt);
         let mapped_stmt = generate_param_stmt(&company_stmt, PARAM_SIGN);
 
         match client.prepare(&mapped_stmt).await {
@@ -162,6 +411,7 @@ impl PostgresProxy {
         stmt: &str,
         params: &[Value],
         client: &Client,
+        window: FetchWindow,
     ) -> Result<SQLResultSet, SQLError> {
         let prepared_stmt = client.prepare(&stmt).await?;
         let stmt_params = prepared_stmt.params();
@@ -184,13 +434,45 @@ impl PostgresProxy {
             mapped_params_unbox.push(param_box.as_ref());
         }
 
-        Self::execute_prepared(&prepared_stmt, &mapped_params_unbox, client).await
+        Self::execute_prepared(&prepared_stmt, &mapped_params_unbox, client, window).await
+    }
+
+    /// Classifies the statement by parsing it, a statement which can't be parsed, or a string of
+    /// several statements, is classified as `Other`.
+    pub fn classify_statement(stmt: &str) -> StatementKind {
+        let mut parsed = match Parser::parse_sql(&PostgreSqlDialect {}, stmt) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                log::debug!("failed to parse the postgres statement to classify: {}", e);
+                return StatementKind::Other;
+            }
+        };
+        if parsed.len() != 1 {
+            return StatementKind::Other;
+        }
+
+        match parsed.remove(0) {
+            ast::Statement::Query(_) => StatementKind::Query,
+            ast::Statement::Insert { .. }
+            | ast::Statement::Update { .. }
+            | ast::Statement::Delete { .. }
+            | ast::Statement::Copy { .. } => StatementKind::Dml,
+            ast::Statement::CreateTable { .. }
+            | ast::Statement::CreateView { .. }
+            | ast::Statement::CreateIndex { .. }
+            | ast::Statement::CreateSchema { .. }
+            | ast::Statement::AlterTable { .. }
+            | ast::Statement::Drop { .. }
+            | ast::Statement::Truncate { .. } => StatementKind::Ddl,
+            _ => StatementKind::Other,
+        }
     }
 
     pub async fn execute_prepared(
         stmt: &Statement,
         params: &[&(dyn ToSql + Sync)],
         client: &Client,
+        window: FetchWindow,
     ) -> Result<SQLResultSet, SQLError> {
         let result_set = if stmt.columns().len() == 0 {
             match client.execute(stmt, params).await {
@@ -202,11 +484,21 @@ impl PostgresProxy {
                 }
             }
         } else {
-            let result = client.query(stmt, params).await?;
+            // Stream the rows instead of buffering the whole result, only the rows inside the
+            // window are fetched.
+            let row_stream = client
+                .query_raw(stmt, params.iter().map(|p| *p as &dyn ToSql))
+                .await?;
+            pin_mut!(row_stream);
             let columns = stmt.columns();
-            let mut rows = Vec::with_capacity(result.len());
-            for row in &result {
-                rows.push(get_row_values(row, columns)?)
+            let mut rows = Vec::new();
+            let mut truncated = false;
+            while let Some(row) = row_stream.try_next().await? {
+                if rows.len() == window.limit {
+                    truncated = true;
+                    break;
+                }
+                rows.push(get_row_values(&row, columns)?)
             }
             let column_strs = columns
                 .iter()
@@ -215,12 +507,115 @@ impl PostgresProxy {
 
             log::debug!("Got postgres query result: {:?}", rows);
 
-            SQLResultSet::new(rows.len(), Some(column_strs), Some(rows))
+            SQLResultSet::new(rows.len(), Some(column_strs), Some(rows)).with_truncated(truncated)
         };
 
         Ok(result_set)
     }
 
+    /// Declares the query as a cursor on the console connection, a statement which isn't a query
+    /// is executed right away.
+    async fn open_cursor(
+        stmt: &str,
+        params: &[Value],
+        client: &Client,
+        cursor_id: &str,
+        page_size: usize,
+    ) -> Result<PagedStatement<String>, SQLError> {
+        let kind = Self::classify_statement(stmt);
+        if kind != StatementKind::Query {
+            return Ok(PagedStatement::Executed(
+                kind,
+                Self::execute_string_statement(stmt, params, client, FetchWindow::default())
+                    .await?,
+            ));
+        }
+
+        let columns = client
+            .prepare(stmt)
+            .await?
+            .columns()
+            .iter()
+            .map(|column| String::from(column.name()))
+            .collect();
+        let declare_stmt = format!(
+            "DECLARE \"{}\" NO SCROLL CURSOR WITH HOLD FOR {}",
+            cursor_id, stmt
+        );
+        Self::execute_string_statement(&declare_stmt, params, client, FetchWindow::default())
+            .await?;
+
+        Ok(PagedStatement::Cursor(SQLCursor::new(
+            cursor_id.to_string(),
+            columns,
+            page_size,
+        )))
+    }
+
+    async fn fetch_rows(
+        client: &Client,
+        cursor_name: &str,
+        count: usize,
+    ) -> Result<Vec<Vec<Value>>, SQLError> {
+        let fetch_stmt = format!("FETCH FORWARD {} FROM \"{}\"", count, cursor_name);
+        let rows = client.query(fetch_stmt.as_str(), &[]).await?;
+
+        let mut values = Vec::with_capacity(rows.len());
+        for row in &rows {
+            values.push(get_row_values(row, row.columns())?);
+        }
+        Ok(values)
+    }
+
+    async fn fetch_cursor_page(
+        manager: &mut ConsoleManager<Client>,
+        cursor_id: &str,
+        mut cursor: SQLCursor<String>,
+        run_id: Option<&str>,
+    ) -> Result<SQLResult> {
+        let (client, cancel_handle) = manager.get_console_conn_with_cancel().await?;
+        let _guard = run_registry::attach_statement(run_id, cancel_handle);
+
+        let count = cursor.rows_to_fetch();
+        let result = match Self::fetch_rows(client, cursor.server_cursor(), count).await {
+            Ok(rows) => {
+                let mut rs = cursor.next_page(rows);
+                if !rs.truncated() {
+                    Self::close_server_cursor(client, cursor_id).await;
+                }
+                manager.sync_cursor(cursor_id, cursor, &mut rs);
+                SQLResult::new_result(Some(rs))
+            }
+            Err(e) => SQLResult::new_error(e),
+        };
+
+        Ok(result)
+    }
+
+    /// The cursors are declared WITH HOLD, they stay open on the server until closed.
+    async fn close_server_cursor(client: &Client, cursor_name: &str) {
+        if let Err(e) = client
+            .batch_execute(&format!("CLOSE \"{}\"", cursor_name))
+            .await
+        {
+            log::warn!("failed to close the postgres cursor {}: {}", cursor_name, e);
+        }
+    }
+
+    /// Forgets the cursors after a rollback, which closes the ones declared in the rolled back
+    /// transaction, and closes the ones left.
+    async fn close_cursors(manager: &mut ConsoleManager<Client>) -> Result<(), Error> {
+        if manager.cursors.is_empty() {
+            return Ok(());
+        }
+
+        manager.cursors.clear();
+        match manager.console_client.as_ref() {
+            Some(client) if !client.is_closed() => client.batch_execute("CLOSE ALL").await,
+            _ => Ok(()),
+        }
+    }
+
     async fn start_transaction(client: &Client) -> Result<(), Error> {
         log::debug!("Start Postgres transaction.");
         Ok(client.batch_execute("BEGIN").await?)
@@ -235,19 +630,36 @@ impl PostgresProxy {
         log::debug!("Rollback Postgres transaction.");
         Ok(client.batch_execute("ROLLBACK").await?)
     }
+
+    /// The id of the open transaction, none until the transaction has written something.
+    async fn current_transaction_id(client: &Client) -> Result<Option<String>, Error> {
+        let row = client
+            .query_one("SELECT txid_current_if_assigned()::text", &[])
+            .await?;
+        Ok(row.try_get(0)?)
+    }
 }
 
 impl<'a> SQLClient for PostgresProxy {
-    fn set_config(&'static self, db_config: Config) -> Result<SQLResult> {
+    fn set_config(&self, db_config: Config) -> Result<SQLResult> {
         let handle = async_runtime::handle();
+        let proxy = self.clone();
         thread::spawn(move || {
             handle.block_on(async {
-                let mut console_manager = self.get_console_manager().await?;
+                let mut console_manager = proxy.get_console_manager().await?;
+                // The certificate files are read again, they may have been replaced.
+                TLS_CONNECTORS
+                    .lock()
+                    .unwrap()
+                    .remove(&TlsSettings::new(&db_config));
+                proxy.1.reset(db_config.clone());
                 console_manager.config = db_config;
                 console_manager.console_client = None;
+                console_manager.end_transaction();
+                console_manager.cursors.clear();
                 match console_manager.get_console_conn().await {
                     Ok(_) => Ok(SQLResult::new_result(None)),
-                    Err(e) => Ok(SQLResult::new_error(SQLError::new_postgres_error(e, ""))),
+                    Err(e) => Ok(SQLResult::new_error(e)),
                 }
             })
         })
@@ -255,11 +667,12 @@ impl<'a> SQLClient for PostgresProxy {
         .unwrap()
     }
 
-    fn set_autocommit(&'static self, autocommit: bool) -> Result<SQLResult> {
+    fn set_autocommit(&self, autocommit: bool) -> Result<SQLResult> {
         let handle = async_runtime::handle();
+        let proxy = self.clone();
         thread::spawn(move || {
             handle.block_on(async {
-                let mut manager = self.get_console_manager().await?;
+                let mut manager = proxy.get_console_manager().await?;
                 if manager.autocommit == autocommit {
                     log::warn!(
                         "Try to set Postgres autocommit to the same value: {}",
@@ -276,6 +689,9 @@ impl<'a> SQLClient for PostgresProxy {
                         Self::start_transaction(&client).await?;
                     }
                 }
+                if autocommit {
+                    manager.end_transaction();
+                }
 
                 Ok(SQLResult::new_result(None))
             })
@@ -284,17 +700,19 @@ impl<'a> SQLClient for PostgresProxy {
         .unwrap()
     }
 
-    fn commit_console(&'static self) -> Result<SQLResult> {
+    fn commit_console(&self) -> Result<SQLResult> {
         let handle = async_runtime::handle();
+        let proxy = self.clone();
         thread::spawn(move || {
             handle.block_on(async {
-                let manager = self.get_console_manager().await?;
+                let mut manager = proxy.get_console_manager().await?;
                 if let Some(client) = manager.console_client.as_ref() {
                     Self::commit_transaction(&client).await?;
                     if !manager.autocommit {
                         Self::start_transaction(&client).await?;
                     }
                 }
+                manager.end_transaction();
                 Ok(SQLResult::new_result(None))
             })
         })
@@ -302,17 +720,120 @@ impl<'a> SQLClient for PostgresProxy {
         .unwrap()
     }
 
-    fn rollback_console(&'static self) -> Result<SQLResult> {
+    fn rollback_console(&self) -> Result<SQLResult> {
         let handle = async_runtime::handle();
+        let proxy = self.clone();
         thread::spawn(move || {
             handle.block_on(async {
-                let manager = self.get_console_manager().await?;
+                let mut manager = proxy.get_console_manager().await?;
+                // Rolling back a lost connection acknowledges the lost transaction.
+                let lost = match manager.console_client.as_ref() {
+                    Some(client) if client.is_closed() => true,
+                    Some(client) => {
+                        Self::rollback_transaction(&client).await?;
+                        false
+                    }
+                    None => false,
+                };
+                if lost {
+                    manager.console_client = None;
+                }
+                Self::close_cursors(&mut manager).await?;
                 if let Some(client) = manager.console_client.as_ref() {
-                    Self::rollback_transaction(&client).await?;
                     if !manager.autocommit {
                         Self::start_transaction(&client).await?;
                     }
                 }
+                manager.end_transaction();
+                Ok(SQLResult::new_result(None))
+            })
+        })
+        .join()
+        .unwrap()
+    }
+
+    fn add_savepoint(&self, name: &str) -> Result<SQLResult> {
+        let handle = async_runtime::handle();
+        let proxy = self.clone();
+        let savepoint = name.to_string();
+        thread::spawn(move || {
+            handle.block_on(async {
+                let mut manager = proxy.get_console_manager().await?;
+                if manager.autocommit {
+                    return Ok(SQLResult::new_error(SQLError::new_str(
+                        "Can't add savepoint when autocommit on.",
+                    )));
+                }
+                if let Err(e) = SavePoint::validate_name(&savepoint) {
+                    return Ok(SQLResult::new_error(e));
+                }
+
+                let client = manager.get_console_conn().await?;
+                let stmt = format!("SAVEPOINT {}", savepoint);
+                if let Err(e) = client.batch_execute(&stmt).await {
+                    return Ok(SQLResult::new_error(SQLError::new_postgres_error(e, &stmt)));
+                }
+                manager.push_savepoint(&savepoint);
+
+                Ok(SQLResult::new_result(None))
+            })
+        })
+        .join()
+        .unwrap()
+    }
+
+    fn rollback_to_savepoint(&self, name: &str) -> Result<SQLResult> {
+        let handle = async_runtime::handle();
+        let proxy = self.clone();
+        let savepoint = name.to_string();
+        thread::spawn(move || {
+            handle.block_on(async {
+                let mut manager = proxy.get_console_manager().await?;
+                if !manager.has_savepoint(&savepoint) {
+                    return Ok(SQLResult::new_error(SQLError::new(format!(
+                        "Savepoint {} doesn't exist.",
+                        savepoint
+                    ))));
+                }
+
+                if let Some(client) = manager.console_client.as_ref() {
+                    let stmt = format!("ROLLBACK TO SAVEPOINT {}", savepoint);
+                    if let Err(e) = client.batch_execute(&stmt).await {
+                        return Ok(SQLResult::new_error(SQLError::new_postgres_error(e, &stmt)));
+                    }
+                }
+                manager.forget_cursors_after(&savepoint);
+                manager.rollback_savepoints_to(&savepoint);
+
+                Ok(SQLResult::new_result(None))
+            })
+        })
+        .join()
+        .unwrap()
+    }
+
+    fn release_savepoint(&self, name: &str) -> Result<SQLResult> {
+        let handle = async_runtime::handle();
+        let proxy = self.clone();
+        let savepoint = name.to_string();
+        thread::spawn(move || {
+            handle.block_on(async {
+                let mut manager = proxy.get_console_manager().await?;
+                if !manager.has_savepoint(&savepoint) {
+                    return Ok(SQLResult::new_error(SQLError::new(format!(
+                        "Savepoint {} doesn't exist.",
+                        savepoint
+                    ))));
+                }
+
+                if let Some(client) = manager.console_client.as_ref() {
+                    let stmt = format!("RELEASE SAVEPOINT {}", savepoint);
+                    if let Err(e) = client.batch_execute(&stmt).await {
+                        return Ok(SQLResult::new_error(SQLError::new_postgres_error(e, &stmt)));
+                    }
+                }
+                manager.release_savepoints_from(&savepoint);
+
                 Ok(SQLResult::new_result(None))
             })
         })
@@ -320,40 +841,46 @@ impl<'a> SQLClient for PostgresProxy {
         .unwrap()
     }
 
-    fn add_savepoint(&'static self, _name: &str) -> Result<SQLResult> {
-        todo!()
-        // POSTGRES_RUNTIME
-        //     .lock()
-        //     .or_else(|e| {
-        //         log::error!("failed to get postgres runtime: {}", e);
-        //         Err(anyhow!("failed to get postgres runtime: {}", e))
-        //     })?
-        //     .block_on(async {
-        //         if self.autocommit {
-        //             return Ok(SQLResult::new_error(SQLError::new(
-        //                 "Can't add savepoint when autocommit on.".to_string(),
-        //             )));
-        //         }
-        //         if let Some(client_lock) = &self.client {
-        //             let client = client_lock.lock().unwrap();
-        //             Self::rollback_transaction(&client).await?;
-        //             if !self.autocommit {
-        //                 Self::start_transaction(&client).await?;
-        //             }
-        //         }
-        //         Ok(SQLResult::new_result(None))
-        //     })
-    }
-
-    fn rollback_to_savepoint(&'static self, _name: &str) -> Result<SQLResult> {
-        todo!()
+    fn savepoints(&self) -> Result<Vec<SavePoint>> {
+        let handle = async_runtime::handle();
+        let proxy = self.clone();
+        thread::spawn(move || {
+            handle.block_on(async {
+                let manager = proxy.get_console_manager().await?;
+                Ok(manager.savepoints.clone())
+            })
+        })
+        .join()
+        .unwrap()
+    }
+
+    fn transaction_status(&self) -> Result<TransactionStatus> {
+        let handle = async_runtime::handle();
+        let proxy = self.clone();
+        thread::spawn(move || {
+            handle.block_on(async {
+                let manager = proxy.get_console_manager().await?;
+                let status = manager.transaction_status();
+                let transaction_id = match manager.console_client.as_ref() {
+                    Some(client) if !manager.autocommit && !client.is_closed() => {
+                        Self::current_transaction_id(client).await?
+                    }
+                    _ => None,
+                };
+
+                Ok(status.with_transaction_id(transaction_id))
+            })
+        })
+        .join()
+        .unwrap()
     }
 
     fn execute_stmt(
-        &'static self,
+        &self,
         statement: &str,
         parameters: &[Value],
         _with_statistics: bool,
+        run_id: Option<&str>,
     ) -> Result<SQLResult> {
         let handle = async_runtime::handle();
         let stmt = statement.to_string();
@@ -362,16 +889,22 @@ impl<'a> SQLClient for PostgresProxy {
             s.spawn(|_| {
                 handle.block_on(async {
                     let mut console_manager = self.get_console_manager().await?;
-                    let client = console_manager.get_console_conn().await?;
+                    let (client, cancel_handle) =
+                        console_manager.get_console_conn_with_cancel().await?;
+                    let _guard = run_registry::attach_statement(run_id, cancel_handle);
 
                     let result = match Self::execute_string_statement(
                         &stmt,
                         &parameter_vec,
                         &client,
+                        FetchWindow::default(),
                     )
                     .await
                     {
-                        Ok(rs) => SQLResult::new_result(Some(rs)),
+                        Ok(rs) => {
+                            console_manager.track_result(Self::classify_statement(&stmt), &rs);
+                            SQLResult::new_result(Some(rs))
+                        }
                         Err(e) => SQLResult::new_error(e),
                     };
                     Ok(result)
@@ -383,19 +916,19 @@ impl<'a> SQLClient for PostgresProxy {
         .unwrap()
     }
 
-    fn validate_stmts(&'static self, stmts: &[&str]) -> Result<Vec<SQLResult>> {
+    fn validate_stmts(&self, stmts: &[&str]) -> Result<Vec<SQLResult>> {
         let handle = async_runtime::handle();
         let stmts_vec: Vec<String> = stmts.iter().map(|stmt| stmt.to_string()).collect();
+        let proxy = self.clone();
         thread::spawn(move || {
             handle.block_on(async {
-                let mut console_manager = self.get_console_manager().await?;
-                let client = console_manager.get_console_conn().await?;
+                let config = proxy.get_console_manager().await?.config.clone();
+                // use a new connection to leave the console transaction untouched
+                let client = Self::connect(&config).await?;
 
-                client.execute("SET search_path TO anaconda", &[]).await?;
-                Self::rollback_transaction(client).await;
                 let pending_tasks = stmts_vec
                     .iter()
-                    .map(|s| Self::validate_statement(s.clone(), client));
+                    .map(|s| Self::validate_statement(s.clone(), &config, &client));
                 match future::try_join_all(pending_tasks).await {
                     Ok(res) => Ok(res),
                     Err(e) => Err(e.into()),
@@ -405,25 +938,200 @@ impl<'a> SQLClient for PostgresProxy {
         .join()
         .unwrap()
     }
+
+    fn execute_stmt_paged(
+        &self,
+        statement: &str,
+        parameters: &[Value],
+        page_size: usize,
+        run_id: Option<&str>,
+    ) -> Result<SQLResult> {
+        let handle = async_runtime::handle();
+        let proxy = self.clone();
+        let stmt = statement.to_string();
+        let parameter_vec = parameters.to_vec();
+        let run_id = run_id.map(String::from);
+        thread::spawn(move || {
+            handle.block_on(async {
+                let mut manager = proxy.get_console_manager().await?;
+                let cursor_id = SQLCursor::<String>::new_id();
+                let opened = {
+                    let (client, cancel_handle) = manager.get_console_conn_with_cancel().await?;
+                    let _guard = run_registry::attach_statement(run_id.as_deref(), cancel_handle);
+                    Self::open_cursor(&stmt, &parameter_vec, client, &cursor_id, page_size).await
+                };
+
+                match opened {
+                    Ok(PagedStatement::Cursor(cursor)) => {
+                        Self::fetch_cursor_page(&mut manager, &cursor_id, cursor, run_id.as_deref())
+                            .await
+                    }
+                    Ok(PagedStatement::Executed(kind, rs)) => {
+                        manager.track_result(kind, &rs);
+                        Ok(SQLResult::new_result(Some(rs)))
+                    }
+                    Err(e) => Ok(SQLResult::new_error(e)),
+                }
+            })
+        })
+        .join()
+        .unwrap()
+    }
+
+    fn fetch_next_page(&self, cursor: &str, run_id: Option<&str>) -> Result<SQLResult> {
+        let handle = async_runtime::handle();
+        let proxy = self.clone();
+        let cursor_id = cursor.to_string();
+        let run_id = run_id.map(String::from);
+        thread::spawn(move || {
+            handle.block_on(async {
+                let mut manager = proxy.get_console_manager().await?;
+                match manager.take_cursor(&cursor_id) {
+                    Some(c) => {
+                        Self::fetch_cursor_page(&mut manager, &cursor_id, c, run_id.as_deref())
+                            .await
+                    }
+                    None => Ok(SQLResult::new_error(SQLError::new(format!(
+                        "Cursor {} doesn't exist or has been closed.",
+                        cursor_id
+                    )))),
+                }
+            })
+        })
+        .join()
+        .unwrap()
+    }
+
+    fn close_cursor(&self, cursor: &str) -> Result<bool> {
+        let handle = async_runtime::handle();
+        let proxy = self.clone();
+        let cursor_id = cursor.to_string();
+        thread::spawn(move || {
+            handle.block_on(async {
+                let mut manager = proxy.get_console_manager().await?;
+                let closed = manager.take_cursor(&cursor_id);
+                let found = closed.is_some();
+                if let (Some(mut cursor), Some(client)) = (closed, &manager.console_client) {
+                    Self::close_server_cursor(client, cursor.server_cursor()).await;
+                }
+                Ok(found)
+            })
+        })
+        .join()
+        .unwrap()
+    }
+}
+
+/// The settings a TLS connector is built from.
+#[derive(PartialEq, Eq, Hash)]
+struct TlsSettings {
+    ssl_mode: SslMode,
+    ssl_root_cert: Option<String>,
+    ssl_cert: Option<String>,
+    ssl_key: Option<String>,
+}
+
+impl TlsSettings {
+    fn new(config: &Config) -> Self {
+        Self {
+            ssl_mode: config.ssl_mode(),
+            ssl_root_cert: config.ssl_root_cert.clone(),
+            ssl_cert: config.ssl_cert.clone(),
+            ssl_key: config.ssl_key.clone(),
+        }
+    }
 }
 
 lazy_static! {
-    pub static ref INSTANCE: PostgresProxy = PostgresProxy::new(Config::new(
-        "localhost",
-        "5432",
-        "planning",
-        "postgres",
-        "#postgres#"
-    ));
     static ref POSTGRES_RUNTIME: Arc<Mutex<Runtime>> =
         Arc::new(Mutex::new(Runtime::new().unwrap()));
+    static ref TLS_CONNECTORS: std::sync::Mutex<HashMap<TlsSettings, MakeTlsConnector>> =
+        std::sync::Mutex::new(HashMap::new());
 }
 
-pub fn get_proxy() -> &'static PostgresProxy {
-    &*INSTANCE
+pub fn get_proxy(profile: &str) -> Result<PostgresProxy> {
+    match connection_profile::get_client(profile)? {
+        ProfileClient::Postgres(proxy) => Ok(proxy),
+        _ => Err(anyhow!(
+            "The connection profile {} is not a Postgres profile.",
+            profile
+        )),
+    }
 }
 
 pub fn get_runtime() -> Arc<Mutex<Runtime>> {
     log::debug!("got postgres runtime.");
     Arc::clone(&POSTGRES_RUNTIME)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_statement() {
+        let classify = PostgresProxy::classify_statement;
+
+        assert_eq!(
+            classify("SELECT * FROM company_a.users WHERE id = $1"),
+            StatementKind::Query
+        );
+        assert_eq!(
+            classify("UPDATE users SET name = $1 WHERE id = $2"),
+            StatementKind::Dml
+        );
+        assert_eq!(classify("DROP TABLE users"), StatementKind::Ddl);
+        assert_eq!(
+            classify("SELECT 1; DELETE FROM users"),
+            StatementKind::Other
+        );
+        assert_eq!(classify("SET search_path TO greenco"), StatementKind::Other);
+        assert_eq!(classify("not a statement"), StatementKind::Other);
+    }
+
+    fn config_with_ssl(ssl_mode: Option<SslMode>, ssl_root_cert: Option<&str>) -> Config {
+        let mut config = Config::new("localhost", "5432", "postgres", "postgres", "");
+        config.ssl_mode = ssl_mode;
+        config.ssl_root_cert = ssl_root_cert.map(str::to_string);
+        config
+    }
+
+    #[test]
+    fn test_verify_mode() {
+        let verify_mode = |ssl_mode, ssl_root_cert| {
+            PostgresProxy::verify_mode(&config_with_ssl(ssl_mode, ssl_root_cert))
+        };
+
+        assert_eq!(verify_mode(None, None), (SslVerifyMode::NONE, false));
+        assert_eq!(
+            verify_mode(Some(SslMode::Prefer), Some("ca.pem")),
+            (SslVerifyMode::NONE, false)
+        );
+        assert_eq!(
+            verify_mode(Some(SslMode::Require), None),
+            (SslVerifyMode::NONE, false)
+        );
+        assert_eq!(
+            verify_mode(Some(SslMode::Require), Some("ca.pem")),
+            (SslVerifyMode::PEER, false)
+        );
+        assert_eq!(
+            verify_mode(Some(SslMode::VerifyFull), None),
+            (SslVerifyMode::PEER, true)
+        );
+    }
+
+    #[test]
+    fn test_tls_settings() {
+        let require = config_with_ssl(Some(SslMode::Require), Some("ca.pem"));
+        let mut renamed = require.clone();
+        renamed.host = "db.example.com".to_string();
+
+        assert!(TlsSettings::new(&require) == TlsSettings::new(&renamed));
+        assert!(TlsSettings::new(&require) != TlsSettings::new(&config_with_ssl(None, None)));
+        assert!(
+            TlsSettings::new(&config_with_ssl(None, None))
+                == TlsSettings::new(&config_with_ssl(Some(SslMode::Disable), None))
+        );
+    }
+}
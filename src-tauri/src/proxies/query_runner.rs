@@ -1,6 +1,7 @@
 use std::{
     borrow::BorrowMut,
     cell::RefCell,
+    cmp,
     collections::{HashMap, LinkedList},
     sync::atomic::AtomicBool,
     sync::atomic::Ordering,
@@ -15,6 +16,7 @@ use log::warn;
 use oracle::sql_type::ToSql as OracleToSql;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use uuid::Uuid;
 
 use tokio::runtime::Runtime;
 use tokio_postgres::types::ToSql as PgToSql;
@@ -23,14 +25,23 @@ use crate::{
     core::parameter_iterator::DBParamIter,
     core::parameter_iterator::ParameterIterator,
     core::{
+        canonical_value::EquivalenceRules,
         parameter_iterator::ParamSeeds,
-        query_scanner::QueryScanner,
-        result_diff::{diff, DiffResults},
+        query_scanner::{QueryScanner, ScanSafetyMode, ScanTransaction},
+        result_diff::{diff, diff_by_key, DiffResults, KeyDiff, KeyDiffOptions},
     },
     handlers::query_runner::Query,
 };
 
-use super::sql_common::{SQLError, SQLResult, SQLResultSet};
+use super::{
+    connection_pool::Semaphore,
+    run_registry,
+    scan_progress::ProgressSink,
+    sql_common::{SQLError, SQLResult, SQLResultSet, StatementKind},
+};
+
+/// The mismatching parameter sets kept in the diff summary when the max isn't given.
+pub const DEFAULT_MAX_MISMATCHES: usize = 10;
 
 #[derive(Clone, Serialize, Debug)]
 pub struct ProgressMessage<'a> {
@@ -60,6 +71,30 @@ impl<'a> ProgressMessage<'a> {
             total,
         }
     }
+
+    pub fn schema(&self) -> &str {
+        self.schema
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn parameters(&self) -> Option<&[Value]> {
+        self.parameters
+    }
+
+    pub fn finished(&self) -> usize {
+        self.finished
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -91,6 +126,7 @@ enum Message {
         Duration,
     ),
     StartQuery(usize, Option<Vec<Value>>, usize),
+    Classified(usize, StatementKind),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -115,6 +151,14 @@ impl QueryResultPerSchema {
             results,
         }
     }
+
+    pub fn parameters(&self) -> Option<&[Value]> {
+        self.parameters.as_deref()
+    }
+
+    pub fn results(&self) -> Option<&SQLResult> {
+        self.results.as_ref()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -122,38 +166,258 @@ impl QueryResultPerSchema {
 pub struct ResultPerSchema {
     query_results: Vec<Option<QueryResultPerSchema>>,
     diff_results: Option<DiffResults>,
+    #[serde(default)]
+    cancelled: bool,
+    /// The kind of the statement of each query, none if it failed to be classified.
+    #[serde(default)]
+    statement_kinds: Vec<Option<StatementKind>>,
+    /// The differences of the rows matched by the key columns, when diffed by key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    row_diffs: Option<Vec<KeyDiff>>,
+    /// The diffs of all the parameter sets, when every parameter set is diffed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    diff_summary: Option<DiffSummary>,
+    /// The schema each query ran against, when a query ran against another schema.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    query_schemas: Option<Vec<String>>,
+}
+
+/// The diff of the results of the queries run with a parameter set each.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ParameterSetDiff {
+    /// The index of the parameter sets in the order they were diffed.
+    set_index: usize,
+    /// The parameters of each query, none if the query had no result for the set.
+    parameters: Vec<Option<Vec<Value>>>,
+    diff_results: Option<DiffResults>,
+    row_diffs: Option<Vec<KeyDiff>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffSummary {
+    compared: usize,
+    matching: usize,
+    mismatching: usize,
+    /// The first mismatching parameter sets, up to the max mismatches of the scan.
+    mismatches: Vec<ParameterSetDiff>,
+}
+
+impl DiffSummary {
+    pub fn compared(&self) -> usize {
+        self.compared
+    }
+
+    pub fn mismatching(&self) -> usize {
+        self.mismatching
+    }
+
+    fn add(&mut self, set_diff: ParameterSetDiff, max_mismatches: usize) {
+        self.compared += 1;
+        if set_diff.diff_results.is_none() && set_diff.row_diffs.is_none() {
+            self.matching += 1;
+            return;
+        }
+
+        self.mismatching += 1;
+        if self.mismatches.len() < max_mismatches {
+            self.mismatches.push(set_diff);
+        }
+    }
+}
+
+impl ResultPerSchema {
+    pub fn query_results(&self) -> &[Option<QueryResultPerSchema>] {
+        &self.query_results
+    }
+
+    pub fn diff_results(&self) -> Option<&DiffResults> {
+        self.diff_results.as_ref()
+    }
+
+    pub fn row_diffs(&self) -> Option<&[KeyDiff]> {
+        self.row_diffs.as_deref()
+    }
+
+    pub fn diff_summary(&self) -> Option<&DiffSummary> {
+        self.diff_summary.as_ref()
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancelled
+    }
+}
+
+/// A query of a schema comparison, run against each of the compared schemas.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ComparisonGroup {
+    /// The index of the query in the schema comparison.
+    query_index: usize,
+    schemas: Vec<String>,
+}
+
+impl ComparisonGroup {
+    pub fn new(query_index: usize, schemas: Vec<String>) -> Self {
+        Self {
+            query_index,
+            schemas,
+        }
+    }
+
+    pub fn query_index(&self) -> usize {
+        self.query_index
+    }
+
+    pub fn schemas(&self) -> &[String] {
+        &self.schemas
+    }
+
+    /// The name the progress of the group is reported under.
+    pub fn label(&self) -> String {
+        format!("{} #{}", self.schemas.join(" vs "), self.query_index + 1)
+    }
+}
+
+/// The results of a comparison group, the query results are in the order of the schemas.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ComparisonResult {
+    group: ComparisonGroup,
+    result: ResultPerSchema,
+}
+
+impl ComparisonResult {
+    pub fn group(&self) -> &ComparisonGroup {
+        &self.group
+    }
+
+    pub fn result(&self) -> &ResultPerSchema {
+        &self.result
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RunResults {
     #[serde(flatten)]
     results: HashMap<String, ResultPerSchema>,
+    /// The results of the schema comparison, kept apart from the results of the schemas.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    comparisons: Vec<ComparisonResult>,
+}
+
+impl RunResults {
+    pub fn schema_results(&self) -> &HashMap<String, ResultPerSchema> {
+        &self.results
+    }
+
+    pub fn comparison_results(&self) -> &[ComparisonResult] {
+        &self.comparisons
+    }
+
+    fn all_results(&self) -> impl Iterator<Item = &ResultPerSchema> {
+        self.results
+            .values()
+            .chain(self.comparisons.iter().map(ComparisonResult::result))
+    }
+
+    pub fn has_error(&self) -> bool {
+        self.all_results().any(|schema_result| {
+            schema_result
+                .query_results
+                .iter()
+                .flatten()
+                .any(|qr| matches!(qr.results, Some(SQLResult::Error(_))))
+        })
+    }
+
+    /// Whether the results of any schema were different, or missing for a diff.
+    pub fn has_diff(&self) -> bool {
+        self.all_results().any(|schema_result| {
+            schema_result.diff_results.is_some() || schema_result.row_diffs.is_some()
+        })
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.all_results()
+            .any(|schema_result| schema_result.cancelled)
+    }
 }
 
+/// The settings shared by the scans of all the schemas of a run.
+#[derive(Debug, Clone, Default)]
+pub struct ScanSettings {
+    pub diff_results: bool,
+    /// Diffs the results of every parameter set instead of stopping at the first difference.
+    pub diff_all: bool,
+    /// The max number of mismatching parameter sets kept in the diff summary.
+    pub max_mismatches: Option<usize>,
+    /// Sorts the rows of the results, so the positional diff doesn't depend on the row order.
+    pub sort: bool,
+    pub statement_timeout: Option<Duration>,
+    pub safety_mode: ScanSafetyMode,
+    pub key_diff: Option<KeyDiffOptions>,
+    pub equivalence_rules: EquivalenceRules,
+}
+
+/// Scans the queries of each schema and of each comparison group, the queries of a comparison
+/// group are always diffed.
 pub fn scan_queries(
-    window: tauri::Window,
+    sink: Arc<dyn ProgressSink>,
     schema_queries: HashMap<String, Vec<Query>>,
-    diff_results: bool,
+    comparison_groups: Vec<(ComparisonGroup, Vec<Query>)>,
+    run_id: Option<String>,
+    concurrency: Option<usize>,
+    settings: ScanSettings,
 ) -> RunResults {
-    let mut results = HashMap::with_capacity(schema_queries.len());
+    let run_id = run_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+    run_registry::start_run(&run_id);
+    // Without a concurrency level, the scan is only bounded by the pool size of each profile.
+    let permits = concurrency.map(|c| Arc::new(Semaphore::new(cmp::max(c, 1))));
+    // The rollback mode runs all the schemas in one transaction, rolled back once they finish.
+    let transaction = match settings.safety_mode {
+        ScanSafetyMode::Rollback => Some(Arc::new(ScanTransaction::default())),
+        _ => None,
+    };
+    let comparison_settings = Arc::new(ScanSettings {
+        diff_results: true,
+        ..settings.clone()
+    });
+    let settings = Arc::new(settings);
+    let spawn_scan = |schema: String, queries: Vec<Query>, settings: &Arc<ScanSettings>| {
+        let queries_arc: Arc<[Arc<Query>]> = queries.into_iter().map(Arc::new).collect();
+        let sink_clone = Arc::clone(&sink);
+        let run_id_clone = run_id.clone();
+        let permits_clone = permits.clone();
+        let settings_clone = Arc::clone(settings);
+        let transaction_clone = transaction.clone();
+        thread::spawn(move || {
+            scan_schema_queries(
+                sink_clone,
+                schema,
+                queries_arc,
+                run_id_clone,
+                settings_clone,
+                permits_clone,
+                transaction_clone,
+            )
+        })
+    };
+
     let mut schema_join_handlers = HashMap::with_capacity(schema_queries.len());
     for (schema, queries) in schema_queries {
-        let queries_arc = queries.iter().map(|q| Arc::new(q.clone())).collect();
-        let window_clone = window.clone();
-        schema_join_handlers.insert(
-            schema.clone(),
-            thread::spawn(move || {
-                scan_schema_queries(
-                    window_clone,
-                    schema.clone(),
-                    queries_arc,
-                    diff_results,
-                    true,
-                )
-            }),
-        );
+        schema_join_handlers.insert(schema.clone(), spawn_scan(schema, queries, &settings));
     }
+    let comparison_join_handlers: Vec<_> = comparison_groups
+        .into_iter()
+        .map(|(group, queries)| {
+            let handler = spawn_scan(group.label(), queries, &comparison_settings);
+            (group, handler)
+        })
+        .collect();
 
+    let mut results = HashMap::with_capacity(schema_join_handlers.len());
     for (schema, schema_join_handler) in schema_join_handlers {
         match schema_join_handler.join() {
             Ok(schema_result) => {
@@ -164,19 +428,42 @@ pub fn scan_queries(
             }
         }
     }
+    let mut comparisons = Vec::with_capacity(comparison_join_handlers.len());
+    for (group, comparison_join_handler) in comparison_join_handlers {
+        match comparison_join_handler.join() {
+            Ok(result) => comparisons.push(ComparisonResult { group, result }),
+            Err(e) => {
+                log::error!("comparison scan thread error: {:?}", e);
+            }
+        }
+    }
+    if let Some(transaction) = transaction {
+        transaction.rollback();
+    }
+    run_registry::finish_run(&run_id);
 
-    RunResults { results }
+    RunResults {
+        results,
+        comparisons,
+    }
 }
 
 pub fn scan_schema_queries(
-    window: tauri::Window,
+    sink: Arc<dyn ProgressSink>,
     schema: String,
     queries: Arc<[Arc<Query>]>,
-    diff_results: bool,
-    sort: bool,
+    run_id: String,
+    settings: Arc<ScanSettings>,
+    permits: Option<Arc<Semaphore>>,
+    transaction: Option<Arc<ScanTransaction>>,
 ) -> ResultPerSchema {
+    let diff_results = settings.diff_results;
+    let diff_all = diff_results && settings.diff_all;
+    let max_mismatches = settings.max_mismatches.unwrap_or(DEFAULT_MAX_MISMATCHES);
+    let safety_mode = settings.safety_mode;
     let (sender, receiver) = mpsc::channel::<Message>();
     let stop = Arc::new(AtomicBool::new(false));
+    let cancelled = run_registry::start_run(&run_id);
     log::debug!("start schema queries scan...");
 
     for i in 0..queries.len() {
@@ -184,7 +471,12 @@ pub fn scan_schema_queries(
         let query = &queries_clone.as_ref()[i];
         let tx = sender.clone();
         let stop = Arc::clone(&stop);
-        let schema_clone = schema.clone();
+        let cancelled = Arc::clone(&cancelled);
+        let run_id = run_id.clone();
+        let permits = permits.clone();
+        let transaction = transaction.clone();
+        let statement_timeout = settings.statement_timeout;
+        let schema_clone = query.schema().unwrap_or(&schema).to_string();
         let query_clone = Arc::clone(query);
         let rt = Runtime::new().unwrap();
         thread::spawn(move || {
@@ -193,6 +485,8 @@ pub fn scan_schema_queries(
                 let mode = query_clone.mode();
                 let oracle_seeds: Vec<Vec<Box<dyn OracleToSql>>>;
                 let postgres_seeds: Vec<Vec<Box<dyn PgToSql + Sync>>>;
+                // The query with its parameter sources resolved, to report the scanned values.
+                let resolved_query: Query;
 
                 let param_seeds_ret = match query_clone.db_type() {
                     super::sql_common::DBType::Oracle => QueryScanner::map_oracle_param_seeds(
@@ -208,20 +502,50 @@ pub fn scan_schema_queries(
                     }
                 };
 
+                if let Ok((_, kind)) = &param_seeds_ret {
+                    if tx.send(Message::Classified(i, *kind)).is_err() {
+                        return;
+                    }
+                }
+                let param_seeds_ret = param_seeds_ret.and_then(|(param_seeds, kind)| {
+                    safety_mode.check(kind, query_clone.db_type())?;
+                    Ok((param_seeds, kind))
+                });
+
                 let mut query_scanner = match param_seeds_ret {
-                    Ok(ParamSeeds::Oracle(prepared_statement, seeds)) => {
+                    Ok((ParamSeeds::Oracle(prepared_statement, values, seeds), kind)) => {
                         oracle_seeds = seeds;
+                        resolved_query = query_clone.with_parameters(values);
                         let params_iter =
                             ParameterIterator::new(&oracle_seeds, &mode, prepared_statement);
                         let db_param_iter = DBParamIter::Oracle(RefCell::new(params_iter));
-                        QueryScanner::new(query_clone.as_ref(), db_param_iter)
+                        QueryScanner::new(
+                            &resolved_query,
+                            db_param_iter,
+                            Some(&run_id),
+                            statement_timeout,
+                            permits.as_deref(),
+                            safety_mode,
+                            kind,
+                        )
+                        .with_transaction(transaction.as_deref())
                     }
-                    Ok(ParamSeeds::Postgres(prepared_statement, seeds)) => {
+                    Ok((ParamSeeds::Postgres(prepared_statement, values, seeds), kind)) => {
                         postgres_seeds = seeds;
+                        resolved_query = query_clone.with_parameters(values);
                         let params_iter =
                             ParameterIterator::new(&postgres_seeds, &mode, prepared_statement);
                         let db_param_iter = DBParamIter::Postgres(RefCell::new(params_iter));
-                        QueryScanner::new(query_clone.as_ref(), db_param_iter)
+                        QueryScanner::new(
+                            &resolved_query,
+                            db_param_iter,
+                            Some(&run_id),
+                            statement_timeout,
+                            permits.as_deref(),
+                            safety_mode,
+                            kind,
+                        )
+                        .with_transaction(transaction.as_deref())
                     }
                     Err(err) => {
                         log::error!("map params iterator failed: {}", err);
@@ -243,7 +567,7 @@ pub fn scan_schema_queries(
                     }
                 };
 
-                while !stop.load(Ordering::Acquire) {
+                while !stop.load(Ordering::Acquire) && !cancelled.load(Ordering::Acquire) {
                     log::debug!(
                         "{} scan params, finished: {}, total: {}, drained: {}",
                         i,
@@ -254,7 +578,9 @@ pub fn scan_schema_queries(
                     let cur_params = query_scanner.current_params();
                     let start_msg =
                         Message::StartQuery(i, cur_params.clone(), query_scanner.total());
-                    tx.send(start_msg).unwrap();
+                    if tx.send(start_msg).is_err() {
+                        return;
+                    }
                     let now = Instant::now();
                     match query_scanner.next() {
                         Some(rs) => {
@@ -266,8 +592,8 @@ pub fn scan_schema_queries(
                                 query_scanner.total(),
                                 elapsed,
                             );
-                            if !stop.load(Ordering::Acquire) {
-                                tx.send(messge).unwrap();
+                            if stop.load(Ordering::Acquire) || tx.send(messge).is_err() {
+                                return;
                             }
                         }
                         None => {
@@ -282,14 +608,25 @@ pub fn scan_schema_queries(
         });
     }
 
+    // Only the scan threads hold senders now, so the receiver ends once they all exit.
+    drop(sender);
+
     let mut query_results = vec![LinkedList::new(); queries.len()];
     let mut final_results = vec![None; queries.len()];
+    let mut statement_kinds = vec![None; queries.len()];
     let mut progress_vec: Vec<Option<RefCell<ProgressInfo>>> = vec![None; queries.len()];
     let mut diff_rst = None;
+    let mut row_diffs = None;
+    let mut diff_summary = if diff_all {
+        Some(DiffSummary::default())
+    } else {
+        None
+    };
+    let mut diffed_sets = 0;
     let mut has_error = false;
 
     fn update_and_emit_progress_vec(
-        window: &tauri::Window,
+        sink: &dyn ProgressSink,
         progress_vec: &mut Vec<Option<RefCell<ProgressInfo>>>,
         schema: &str,
         index: usize,
@@ -322,15 +659,14 @@ pub fn scan_schema_queries(
         let progress = progress_vec[index].as_ref().unwrap().borrow();
 
         if notify_progress {
-            emit_progress(
-                window,
+            sink.progress(&ProgressMessage::new(
                 schema,
                 index,
                 cur_params,
                 progress.finished,
                 progress.pending,
                 progress.total,
-            );
+            ));
         }
     }
 
@@ -340,7 +676,7 @@ pub fn scan_schema_queries(
             Message::FinishQuery(i, rs, cur_params, total, elapsed) => {
                 let sql_result = match rs {
                     Ok(mut rs) => {
-                        if sort {
+                        if settings.sort {
                             rs.borrow_mut().sort_rows();
                         }
                         SQLResult::new_result(Some(rs))
@@ -361,7 +697,7 @@ pub fn scan_schema_queries(
                 let (pending_delta, finished_delta) = if diff_results { (0, 0) } else { (0, 1) };
 
                 update_and_emit_progress_vec(
-                    &window,
+                    sink.as_ref(),
                     &mut progress_vec,
                     &schema,
                     i,
@@ -382,7 +718,7 @@ pub fn scan_schema_queries(
                 }
             }
             Message::StartQuery(i, cur_params, total) => update_and_emit_progress_vec(
-                &window,
+                sink.as_ref(),
                 &mut progress_vec,
                 &schema,
                 i,
@@ -393,6 +729,7 @@ pub fn scan_schema_queries(
                 cur_params.as_deref(),
                 true,
             ),
+            Message::Classified(i, kind) => statement_kinds[i] = Some(kind),
         }
 
         if diff_results
@@ -405,21 +742,61 @@ pub fn scan_schema_queries(
         {
             let mut first_results: Vec<Option<(Option<Vec<Value>>, SQLResult)>> =
                 query_results.iter_mut().map(|qr| qr.pop_front()).collect();
+            // The queries with fewer parameter sets have no result for the rest of the sets.
             let first_results_rows: Vec<Option<&[Vec<Value>]>> = first_results
                 .iter()
-                .map(|fr| {
-                    let (_, sql_result) = fr.as_ref().unwrap();
-                    match sql_result {
-                        SQLResult::Result(rs_opt) => match rs_opt {
-                            Some(rs) => rs.get_rows().as_deref(),
-                            None => None,
-                        },
-                        _ => None,
-                    }
+                .map(|fr| match fr.as_ref() {
+                    Some((_, SQLResult::Result(Some(rs)))) => rs.get_rows().as_deref(),
+                    _ => None,
                 })
                 .collect();
 
-            diff_rst = diff(&first_results_rows);
+            // Diffs by key when the key columns are given, the positional diff is the fallback
+            // when a result is missing or has no key columns.
+            let key_diff_rst = settings.key_diff.as_ref().and_then(|options| {
+                let result_sets: Option<Vec<&SQLResultSet>> = first_results
+                    .iter()
+                    .map(|fr| fr.as_ref().and_then(|(_, r)| r.result_set()))
+                    .collect();
+                match diff_by_key(&result_sets?, options, &settings.equivalence_rules) {
+                    Ok(key_diffs) => Some(key_diffs),
+                    Err(e) => {
+                        warn!("failed to diff {} by key, diff by position: {}", schema, e);
+                        None
+                    }
+                }
+            });
+            let (set_diff_rst, set_row_diffs) = match key_diff_rst {
+                Some(key_diffs) if key_diffs.iter().any(|d| !d.is_empty()) => {
+                    (None, Some(key_diffs))
+                }
+                Some(_) => (None, None),
+                None => (diff(&first_results_rows, &settings.equivalence_rules), None),
+            };
+
+            if let Some(summary) = diff_summary.as_mut() {
+                let parameters = first_results
+                    .iter()
+                    .map(|fr| fr.as_ref().and_then(|(params, _)| params.clone()))
+                    .collect();
+                summary.add(
+                    ParameterSetDiff {
+                        set_index: diffed_sets,
+                        parameters,
+                        diff_results: set_diff_rst.clone(),
+                        row_diffs: set_row_diffs.clone(),
+                    },
+                    max_mismatches,
+                );
+            }
+            diffed_sets += 1;
+
+            // When every set is diffed, the results of the first mismatching set are kept.
+            let keep_mismatch = diff_all && (diff_rst.is_some() || row_diffs.is_some());
+            if !keep_mismatch {
+                diff_rst = set_diff_rst;
+                row_diffs = set_row_diffs;
+            }
 
             first_results
                 .drain(..)
@@ -428,7 +805,7 @@ pub fn scan_schema_queries(
                     match &scan_result_opt {
                         Some((cur_params, _)) => {
                             update_and_emit_progress_vec(
-                                &window,
+                                sink.as_ref(),
                                 &mut progress_vec,
                                 &schema,
                                 index,
@@ -443,7 +820,9 @@ pub fn scan_schema_queries(
                         None => {}
                     }
 
-                    final_results[index] = scan_result_opt;
+                    if !keep_mismatch {
+                        final_results[index] = scan_result_opt;
+                    }
                 });
         }
 
@@ -458,11 +837,22 @@ pub fn scan_schema_queries(
         });
         let all_have_result = final_results.iter().all(|fr| fr.as_ref().is_some());
 
-        if all_finished || (has_error && all_have_result) || diff_rst.is_some() {
+        let stop_at_first = !diff_all
+            && ((has_error && all_have_result) || diff_rst.is_some() || row_diffs.is_some());
+        if all_finished || stop_at_first {
             log::debug!("about to finish scan, all finished: {}, has_error: {}, all have result: {}, diff rst: {:?}", all_finished, has_error, all_have_result, diff_rst);
             stop.store(true, Ordering::Release);
         }
 
+        if cancelled.load(Ordering::Acquire) {
+            log::debug!(
+                "scan {} cancelled, stop scanning schema {}.",
+                run_id,
+                schema
+            );
+            stop.store(true, Ordering::Release);
+        }
+
         if stop.load(Ordering::Acquire) {
             break;
         }
@@ -494,36 +884,85 @@ pub fn scan_schema_queries(
         })
         .collect();
 
+    let query_schemas = if queries.iter().any(|q| q.schema().is_some()) {
+        Some(
+            queries
+                .iter()
+                .map(|q| q.schema().unwrap_or(&schema).to_string())
+                .collect(),
+        )
+    } else {
+        None
+    };
+
     let schema_result = ResultPerSchema {
         query_results: rst,
         diff_results: diff_rst,
+        cancelled: cancelled.load(Ordering::Acquire),
+        statement_kinds,
+        row_diffs,
+        diff_summary,
+        query_schemas,
     };
-    emit_schema_result(&window, &schema, &schema_result);
+    sink.schema_result(&schema, &schema_result);
 
     schema_result
 }
 
-fn emit_progress(
-    window: &tauri::Window,
-    schema: &str,
-    index: usize,
-    cur_params: Option<&[Value]>,
-    finished: usize,
-    pending: usize,
-    total: usize,
-) {
-    if let Err(e) = window.emit(
-        "scan_query_progress",
-        Some(ProgressMessage::new(
-            schema, index, cur_params, finished, pending, total,
-        )),
-    ) {
-        warn!("emit progress error: {}", e);
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn set_diff(set_index: usize, mismatched: bool) -> ParameterSetDiff {
+        ParameterSetDiff {
+            set_index,
+            parameters: vec![Some(vec![json!(set_index)]); 2],
+            diff_results: if mismatched {
+                Some(HashMap::from([(1, vec![0])]))
+            } else {
+                None
+            },
+            row_diffs: None,
+        }
     }
-}
 
-fn emit_schema_result(window: &tauri::Window, schema: &str, result: &ResultPerSchema) {
-    if let Err(e) = window.emit("scan_query_schema_result", Some((schema, result))) {
-        warn!("emit schema result error: {}", e);
+    #[test]
+    fn test_diff_summary_counts() {
+        let mut summary = DiffSummary::default();
+        summary.add(set_diff(0, false), DEFAULT_MAX_MISMATCHES);
+        summary.add(set_diff(1, true), DEFAULT_MAX_MISMATCHES);
+        summary.add(set_diff(2, false), DEFAULT_MAX_MISMATCHES);
+
+        assert_eq!(summary.compared(), 3);
+        assert_eq!(summary.matching, 2);
+        assert_eq!(summary.mismatching(), 1);
+        assert_eq!(summary.mismatches.len(), 1);
+        assert_eq!(summary.mismatches[0].set_index, 1);
+    }
+
+    #[test]
+    fn test_diff_summary_keeps_first_mismatches() {
+        let mut summary = DiffSummary::default();
+        for i in 0..5 {
+            summary.add(set_diff(i, true), 2);
+        }
+
+        assert_eq!(summary.compared(), 5);
+        assert_eq!(summary.mismatching(), 5);
+        assert_eq!(
+            summary
+                .mismatches
+                .iter()
+                .map(|mismatch| mismatch.set_index)
+                .collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+
+        let mut summary = DiffSummary::default();
+        summary.add(set_diff(0, true), 0);
+        assert_eq!(summary.mismatching(), 1);
+        assert!(summary.mismatches.is_empty());
     }
 }
@@ -6,7 +6,7 @@ use std::{
 use anyhow::{anyhow, Result};
 
 use lazy_static::lazy_static;
-use rocksdb::{ColumnFamily, Options, WriteBatch, DB};
+use rocksdb::{ColumnFamily, IteratorMode, Options, WriteBatch, DB};
 
 use super::dirs::get_data_dir;
 
@@ -117,6 +117,27 @@ impl RocksDataStore {
             .collect())
     }
 
+    pub fn get_all(cf: Option<&str>, db: &DB) -> Result<Vec<(String, String)>> {
+        let iter = match cf.and_then(|cf_str| db.cf_handle(cf_str)) {
+            Some(cf_handle) => db.iterator_cf(cf_handle, IteratorMode::Start),
+            None => match cf {
+                // The column family hasn't been created yet, so nothing was stored.
+                Some(_) => return Ok(Vec::new()),
+                None => db.iterator(IteratorMode::Start),
+            },
+        };
+
+        let mut key_vals = Vec::new();
+        for (key, val) in iter {
+            key_vals.push((
+                String::from_utf8(key.to_vec())?,
+                String::from_utf8(val.to_vec())?,
+            ));
+        }
+
+        Ok(key_vals)
+    }
+
     pub fn delete(cf: Option<&str>, key: &str, db: &DB) -> Result<(), rocksdb::Error> {
         match cf.and_then(|cf_str| db.cf_handle(cf_str)) {
             Some(cf_handle) => db.delete_cf(cf_handle, key.as_bytes()),
@@ -172,6 +193,27 @@ impl RocksDataStore {
         db.write(write_batch)
     }
 
+    /// Writes the key values of several column families in one batch, so either all of them are
+    /// written or none.
+    pub fn write_batches(
+        cf_key_vals: &[(&str, Vec<(String, String)>)],
+        db: &mut DB,
+    ) -> Result<(), rocksdb::Error> {
+        let mut write_batch = WriteBatch::default();
+        for (cf, key_vals) in cf_key_vals {
+            if key_vals.is_empty() {
+                continue;
+            }
+            Self::create_cf_if_not(cf, db)?;
+            let cf_handle = db.cf_handle(cf).unwrap();
+            for (key, val) in key_vals {
+                write_batch.put_cf(cf_handle, key, val);
+            }
+        }
+
+        db.write(write_batch)
+    }
+
     pub fn set_batch<K, I>(cf: Option<&str>, keys: I, values: I, db: &mut DB) -> Result<()>
     where
         K: AsRef<[u8]>,
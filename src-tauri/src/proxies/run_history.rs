@@ -0,0 +1,441 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use anyhow::Result;
+use async_graphql::SimpleObject;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::handlers::query_runner::ScanInputs;
+
+use super::{
+    query_runner::{ComparisonGroup, QueryResultPerSchema, ResultPerSchema, RunResults},
+    rocksdb::{self, RocksDataStore},
+    sql_common::SQLResult,
+};
+
+static SCAN_RUNS_CF: &str = "SCAN_RUNS";
+/// The summaries of the runs, stored apart so the runs are listed without reading their results.
+static SCAN_RUN_SUMMARIES_CF: &str = "SCAN_RUN_SUMMARIES";
+
+/// A finished scan, stored under its run id with the inputs it was run with.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanRun {
+    run_id: String,
+    started_at: String,
+    elapsed: Duration,
+    inputs: ScanInputs,
+    results: RunResults,
+    /// The id of the run this run replayed.
+    replay_of: Option<String>,
+}
+
+impl ScanRun {
+    pub fn new(
+        run_id: String,
+        started_at: String,
+        elapsed: Duration,
+        inputs: ScanInputs,
+        results: RunResults,
+        replay_of: Option<String>,
+    ) -> Self {
+        Self {
+            run_id,
+            started_at,
+            elapsed,
+            inputs,
+            results,
+            replay_of,
+        }
+    }
+
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    pub fn inputs(&self) -> &ScanInputs {
+        &self.inputs
+    }
+
+    pub fn results(&self) -> &RunResults {
+        &self.results
+    }
+
+    pub fn into_results(self) -> RunResults {
+        self.results
+    }
+
+    fn summary(&self) -> ScanRunSummary {
+        let schema_queries = self.inputs.schema_queries();
+        let mut schemas: Vec<String> = schema_queries.keys().cloned().collect();
+        schemas.sort();
+
+        ScanRunSummary {
+            run_id: self.run_id.clone(),
+            started_at: self.started_at.clone(),
+            elapsed_ms: self.elapsed.as_millis() as u64,
+            query_count: schema_queries.values().map(Vec::len).sum(),
+            schemas,
+            has_error: self.results.has_error(),
+            has_diff: self.results.has_diff(),
+            cancelled: self.results.cancelled(),
+            replay_of: self.replay_of.clone(),
+        }
+    }
+}
+
+#[derive(SimpleObject, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanRunSummary {
+    run_id: String,
+    started_at: String,
+    elapsed_ms: u64,
+    schemas: Vec<String>,
+    query_count: usize,
+    has_error: bool,
+    has_diff: bool,
+    cancelled: bool,
+    replay_of: Option<String>,
+}
+
+/// Saves the run along with its summary, in one batch.
+pub fn save_run(run: &ScanRun) -> Result<()> {
+    let val = serde_json::to_string(run)?;
+    let summary = serde_json::to_string(&run.summary())?;
+    let mut db = rocksdb::get_conn();
+    RocksDataStore::write_batches(
+        &[
+            (SCAN_RUNS_CF, vec![(run.run_id.clone(), val)]),
+            (SCAN_RUN_SUMMARIES_CF, vec![(run.run_id.clone(), summary)]),
+        ],
+        &mut db,
+    )?;
+    Ok(())
+}
+
+pub fn load_run(run_id: &str) -> Result<Option<ScanRun>> {
+    let db = rocksdb::get_conn();
+    match RocksDataStore::get(run_id, &db, Some(SCAN_RUNS_CF))? {
+        Some(val) => Ok(Some(serde_json::from_str(&val)?)),
+        None => Ok(None),
+    }
+}
+
+/// The summaries of the stored runs, the latest first.
+pub fn list_runs() -> Result<Vec<ScanRunSummary>> {
+    let key_vals = {
+        let db = rocksdb::get_conn();
+        RocksDataStore::get_all(Some(SCAN_RUN_SUMMARIES_CF), &db)?
+    };
+
+    let mut summaries = Vec::with_capacity(key_vals.len());
+    for (run_id, val) in key_vals {
+        match serde_json::from_str::<ScanRunSummary>(&val) {
+            Ok(summary) => summaries.push(summary),
+            Err(e) => log::warn!("skip the unreadable scan run summary {}: {}", run_id, e),
+        }
+    }
+    summaries.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+
+    Ok(summaries)
+}
+
+/// Deletes the summary first, so a run is never listed once it's partly deleted.
+pub fn delete_run(run_id: &str) -> Result<()> {
+    let db = rocksdb::get_conn();
+    RocksDataStore::delete(Some(SCAN_RUN_SUMMARIES_CF), run_id, &db)?;
+    RocksDataStore::delete(Some(SCAN_RUNS_CF), run_id, &db)?;
+    Ok(())
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum RunChangeKind {
+    SchemaAdded,
+    SchemaRemoved,
+    /// The query failed in one run only, failed differently or has no result in one run.
+    Outcome,
+    Parameters,
+    Rows,
+    Diff,
+}
+
+/// A difference between the stored run and its replay.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RunChange {
+    /// The schema of the change, none for a change of a schema comparison group.
+    schema: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comparison: Option<ComparisonGroup>,
+    /// The index of the query in the schema, none for a change of the whole schema.
+    query_index: Option<usize>,
+    kind: RunChangeKind,
+    before: Option<String>,
+    after: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RunReplay {
+    run_id: String,
+    replay_of: String,
+    changes: Vec<RunChange>,
+    results: RunResults,
+}
+
+impl RunReplay {
+    pub fn new(stored: &ScanRun, replayed: ScanRun) -> Self {
+        Self {
+            changes: compare_runs(&stored.results, &replayed.results),
+            run_id: replayed.run_id,
+            replay_of: stored.run_id.clone(),
+            results: replayed.results,
+        }
+    }
+}
+
+pub fn compare_runs(before: &RunResults, after: &RunResults) -> Vec<RunChange> {
+    let before_schemas = before.schema_results();
+    let after_schemas = after.schema_results();
+    let schemas: BTreeSet<&String> = before_schemas.keys().chain(after_schemas.keys()).collect();
+
+    let mut changes = Vec::new();
+    for schema in schemas {
+        match (before_schemas.get(schema), after_schemas.get(schema)) {
+            (Some(b), Some(a)) => compare_schema_results(Some(schema), None, b, a, &mut changes),
+            (b, _) => changes.push(RunChange {
+                schema: Some(schema.clone()),
+                comparison: None,
+                query_index: None,
+                kind: if b.is_some() {
+                    RunChangeKind::SchemaRemoved
+                } else {
+                    RunChangeKind::SchemaAdded
+                },
+                before: None,
+                after: None,
+            }),
+        }
+    }
+
+    // The comparison groups are matched by their query and schemas.
+    let after_comparisons = after.comparison_results();
+    for b in before.comparison_results() {
+        match after_comparisons.iter().find(|a| a.group() == b.group()) {
+            Some(a) => {
+                compare_schema_results(None, Some(b.group()), b.result(), a.result(), &mut changes)
+            }
+            None => changes.push(comparison_change(b.group(), RunChangeKind::SchemaRemoved)),
+        }
+    }
+    for a in after_comparisons {
+        if !before
+            .comparison_results()
+            .iter()
+            .any(|b| b.group() == a.group())
+        {
+            changes.push(comparison_change(a.group(), RunChangeKind::SchemaAdded));
+        }
+    }
+
+    changes
+}
+
+fn comparison_change(group: &ComparisonGroup, kind: RunChangeKind) -> RunChange {
+    RunChange {
+        schema: None,
+        comparison: Some(group.clone()),
+        query_index: None,
+        kind,
+        before: None,
+        after: None,
+    }
+}
+
+fn compare_schema_results(
+    schema: Option<&str>,
+    comparison: Option<&ComparisonGroup>,
+    before: &ResultPerSchema,
+    after: &ResultPerSchema,
+    changes: &mut Vec<RunChange>,
+) {
+    let mut push = |query_index, kind, before, after| {
+        changes.push(RunChange {
+            schema: schema.map(str::to_string),
+            comparison: comparison.cloned(),
+            query_index,
+            kind,
+            before,
+            after,
+        })
+    };
+
+    let query_count = before
+        .query_results()
+        .len()
+        .max(after.query_results().len());
+    for i in 0..query_count {
+        let b = before.query_results().get(i).and_then(Option::as_ref);
+        let a = after.query_results().get(i).and_then(Option::as_ref);
+        let (b_outcome, a_outcome) = (describe_outcome(b), describe_outcome(a));
+        if b_outcome != a_outcome {
+            push(Some(i), RunChangeKind::Outcome, b_outcome, a_outcome);
+            continue;
+        }
+
+        let (b, a) = match (b, a) {
+            (Some(b), Some(a)) => (b, a),
+            _ => continue,
+        };
+        if b.parameters() != a.parameters() {
+            push(
+                Some(i),
+                RunChangeKind::Parameters,
+                b.parameters().map(|p| Value::from(p).to_string()),
+                a.parameters().map(|p| Value::from(p).to_string()),
+            );
+        }
+
+        if result_rows(b) != result_rows(a) {
+            push(Some(i), RunChangeKind::Rows, b_outcome, a_outcome);
+        }
+    }
+
+    if before.diff_results() != after.diff_results() {
+        let describe_diff = |r: &ResultPerSchema| {
+            r.diff_results()
+                .map(|d| serde_json::to_string(d).unwrap_or_default())
+        };
+        push(
+            None,
+            RunChangeKind::Diff,
+            describe_diff(before),
+            describe_diff(after),
+        );
+    }
+}
+
+fn result_rows(query_result: &QueryResultPerSchema) -> Option<&Vec<Vec<Value>>> {
+    query_result
+        .results()
+        .and_then(SQLResult::result_set)
+        .and_then(|rs| rs.get_rows().as_ref())
+}
+
+/// The error message of a failed query, or its row count.
+fn describe_outcome(query_result: Option<&QueryResultPerSchema>) -> Option<String> {
+    match query_result?.results()? {
+        SQLResult::Error(e) => Some(format!("error: {}", e.message())),
+        result => Some(format!(
+            "{} rows",
+            result.result_set().map_or(0, |rs| rs.row_count())
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn schema_result(rows: Value, error: Option<&str>) -> Value {
+        let results = match error {
+            Some(message) => json!({"error": {"message": message}}),
+            None => json!({"result": {"rowCount": 1, "columns": ["ID"], "rows": rows}}),
+        };
+        json!({
+            "queryResults": [{
+                "progress": {
+                    "finished": 1,
+                    "total": 1,
+                    "pending": 0,
+                    "elapsed": {"secs": 0, "nanos": 0}
+                },
+                "parameters": [1],
+                "results": results
+            }],
+            "diffResults": null
+        })
+    }
+
+    fn run_results(rows: Value, error: Option<&str>) -> RunResults {
+        serde_json::from_value(json!({ "GREENCO": schema_result(rows, error) })).unwrap()
+    }
+
+    fn comparison_results(rows: Value) -> RunResults {
+        serde_json::from_value(json!({
+            "comparisons": [{
+                "group": {"queryIndex": 0, "schemas": ["GREENCO", "BLUECO"]},
+                "result": schema_result(rows, None)
+            }]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_compare_runs() {
+        let before = run_results(json!([[1]]), None);
+        assert!(compare_runs(&before, &run_results(json!([[1]]), None)).is_empty());
+
+        let changes = compare_runs(&before, &run_results(json!([[2]]), None));
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, RunChangeKind::Rows);
+
+        let changes = compare_runs(&before, &run_results(json!(null), Some("timeout")));
+        assert_eq!(changes[0].kind, RunChangeKind::Outcome);
+        assert_eq!(changes[0].after.as_deref(), Some("error: timeout"));
+    }
+
+    #[test]
+    fn test_compare_comparison_runs() {
+        let before = comparison_results(json!([[1]]));
+        assert!(before.schema_results().is_empty());
+        assert!(compare_runs(&before, &comparison_results(json!([[1]]))).is_empty());
+
+        let changes = compare_runs(&before, &comparison_results(json!([[2]])));
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, RunChangeKind::Rows);
+        assert_eq!(changes[0].schema, None);
+        assert_eq!(changes[0].comparison.as_ref().unwrap().query_index(), 0);
+
+        let changes = compare_runs(&before, &run_results(json!([[1]]), None));
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].kind, RunChangeKind::SchemaAdded);
+        assert_eq!(changes[1].kind, RunChangeKind::SchemaRemoved);
+        assert!(changes[1].comparison.is_some());
+    }
+
+    #[test]
+    fn test_stored_summary() {
+        let inputs: ScanInputs = serde_json::from_value(json!({
+            "schemaQueries": {
+                "GREENCO": [{
+                    "profile": "pg",
+                    "dbType": "postgres",
+                    "statement": "SELECT 1",
+                    "mode": "normal"
+                }]
+            }
+        }))
+        .unwrap();
+        let run = ScanRun::new(
+            "run-1".to_string(),
+            "2022-06-01T00:00:00+08:00".to_string(),
+            Duration::from_millis(1500),
+            inputs,
+            run_results(json!(null), Some("timeout")),
+            None,
+        );
+
+        let stored = serde_json::to_string(&run.summary()).unwrap();
+        let summary: ScanRunSummary = serde_json::from_str(&stored).unwrap();
+        assert_eq!(summary.run_id, "run-1");
+        assert_eq!(summary.elapsed_ms, 1500);
+        assert_eq!(summary.schemas, vec!["GREENCO".to_string()]);
+        assert_eq!(summary.query_count, 1);
+        assert!(summary.has_error);
+        assert!(!summary.cancelled);
+    }
+}
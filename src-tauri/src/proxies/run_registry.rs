@@ -0,0 +1,223 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, Once,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
+use oracle::Connection;
+use postgres_openssl::MakeTlsConnector;
+use tauri::async_runtime;
+use tokio_postgres::CancelToken;
+
+/// A handle which interrupts the statement running on a connection.
+#[derive(Clone)]
+pub enum CancelHandle {
+    Oracle(Arc<Connection>),
+    Postgres(CancelToken, MakeTlsConnector),
+}
+
+impl CancelHandle {
+    fn cancel(self) -> Result<()> {
+        match self {
+            CancelHandle::Oracle(conn) => Ok(conn.break_execution()?),
+            CancelHandle::Postgres(token, tls) => {
+                let handle = async_runtime::handle();
+                thread::spawn(move || handle.block_on(async { token.cancel_query(tls).await }))
+                    .join()
+                    .unwrap()
+                    .map_err(|e| anyhow!("failed to cancel the postgres query: {}", e))
+            }
+        }
+    }
+}
+
+/// The armed statement timers, fired in the order of their deadlines by a single timer thread.
+#[derive(Default)]
+struct TimerQueue {
+    deadlines: BinaryHeap<Reverse<(Instant, usize)>>,
+    armed: HashMap<usize, (CancelHandle, Arc<AtomicBool>)>,
+}
+
+lazy_static! {
+    static ref TIMERS: Mutex<TimerQueue> = Mutex::new(TimerQueue::default());
+    static ref TIMERS_CHANGED: Condvar = Condvar::new();
+    static ref TIMER_SEQ: AtomicUsize = AtomicUsize::new(0);
+}
+
+static TIMER_THREAD: Once = Once::new();
+
+/// Cancels the statement once the timeout elapses, disarmed when dropped.
+pub struct StatementTimer {
+    id: usize,
+    fired: Arc<AtomicBool>,
+}
+
+impl StatementTimer {
+    pub fn start(handle: CancelHandle, timeout: Duration) -> Self {
+        TIMER_THREAD.call_once(|| {
+            thread::spawn(run_timers);
+        });
+        let id = TIMER_SEQ.fetch_add(1, Ordering::AcqRel);
+        let fired = Arc::new(AtomicBool::new(false));
+
+        let mut timers = TIMERS.lock().unwrap();
+        timers
+            .deadlines
+            .push(Reverse((Instant::now() + timeout, id)));
+        timers.armed.insert(id, (handle, Arc::clone(&fired)));
+        TIMERS_CHANGED.notify_one();
+
+        Self { id, fired }
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.fired.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for StatementTimer {
+    fn drop(&mut self) {
+        let mut timers = TIMERS.lock().unwrap();
+        timers.armed.remove(&self.id);
+        // The deadlines of the disarmed timers are skipped when they pass, or dropped at once
+        // when no timer is armed.
+        if timers.armed.is_empty() {
+            timers.deadlines.clear();
+        }
+    }
+}
+
+/// Fires the timers whose deadlines passed and sleeps until the next deadline, the timed out
+/// statements are cancelled outside of the lock.
+fn run_timers() {
+    let mut timers = TIMERS.lock().unwrap();
+    loop {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        while let Some(&Reverse((deadline, id))) = timers.deadlines.peek() {
+            if deadline > now {
+                break;
+            }
+            timers.deadlines.pop();
+            if let Some((handle, fired)) = timers.armed.remove(&id) {
+                fired.store(true, Ordering::Release);
+                expired.push(handle);
+            }
+        }
+
+        if !expired.is_empty() {
+            drop(timers);
+            log::debug!("{} statements timed out, cancel them.", expired.len());
+            for handle in expired {
+                if let Err(e) = handle.cancel() {
+                    log::error!("failed to cancel the timed out statement: {}", e);
+                }
+            }
+            timers = TIMERS.lock().unwrap();
+            continue;
+        }
+
+        timers = match timers.deadlines.peek() {
+            Some(&Reverse((deadline, _))) => {
+                TIMERS_CHANGED
+                    .wait_timeout(timers, deadline - now)
+                    .unwrap()
+                    .0
+            }
+            None => TIMERS_CHANGED.wait(timers).unwrap(),
+        };
+    }
+}
+
+#[derive(Default)]
+struct RunState {
+    cancelled: Arc<AtomicBool>,
+    statements: HashMap<usize, CancelHandle>,
+}
+
+lazy_static! {
+    static ref RUNS: Mutex<HashMap<String, RunState>> = Mutex::new(HashMap::new());
+    static ref STATEMENT_SEQ: AtomicUsize = AtomicUsize::new(0);
+}
+
+/// Detaches the statement from its run when dropped.
+pub struct StatementGuard {
+    run_id: String,
+    statement_id: usize,
+}
+
+impl Drop for StatementGuard {
+    fn drop(&mut self) {
+        if let Some(run) = RUNS.lock().unwrap().get_mut(&self.run_id) {
+            run.statements.remove(&self.statement_id);
+        }
+    }
+}
+
+/// Registers a run, the returned flag is raised once the run is cancelled.
+pub fn start_run(run_id: &str) -> Arc<AtomicBool> {
+    let mut runs = RUNS.lock().unwrap();
+    let run = runs.entry(run_id.to_string()).or_default();
+    Arc::clone(&run.cancelled)
+}
+
+pub fn finish_run(run_id: &str) {
+    RUNS.lock().unwrap().remove(run_id);
+}
+
+pub fn is_cancelled(run_id: Option<&str>) -> bool {
+    match run_id {
+        Some(id) => RUNS
+            .lock()
+            .unwrap()
+            .get(id)
+            .map_or(false, |run| run.cancelled.load(Ordering::Acquire)),
+        None => false,
+    }
+}
+
+/// Attaches a running statement to the run so it can be interrupted by `cancel_run`.
+pub fn attach_statement(run_id: Option<&str>, handle: CancelHandle) -> Option<StatementGuard> {
+    let run_id = run_id?;
+    let mut runs = RUNS.lock().unwrap();
+    let run = runs.get_mut(run_id)?;
+    let statement_id = STATEMENT_SEQ.fetch_add(1, Ordering::AcqRel);
+    run.statements.insert(statement_id, handle);
+
+    Some(StatementGuard {
+        run_id: run_id.to_string(),
+        statement_id,
+    })
+}
+
+/// Stops the run and interrupts all its running statements, returns false if the run is unknown.
+pub fn cancel_run(run_id: &str) -> Result<bool> {
+    let handles: Vec<CancelHandle> = {
+        let runs = RUNS.lock().unwrap();
+        match runs.get(run_id) {
+            Some(run) => {
+                run.cancelled.store(true, Ordering::Release);
+                run.statements.values().cloned().collect()
+            }
+            None => return Ok(false),
+        }
+    };
+
+    log::debug!(
+        "cancel run {}, interrupting {} statements.",
+        run_id,
+        handles.len()
+    );
+    for handle in handles {
+        handle.cancel()?;
+    }
+
+    Ok(true)
+}
@@ -0,0 +1,191 @@
+use std::sync::Mutex;
+
+use async_graphql::{Json, SimpleObject};
+use futures::{stream, Stream};
+use lazy_static::lazy_static;
+use log::warn;
+use serde_json::Value;
+use tokio::sync::broadcast::{self, error::RecvError, Sender};
+
+use super::query_runner::{ProgressMessage, ResultPerSchema};
+
+static EVENTS_CAPACITY: usize = 256;
+
+/// Where a scan reports its progress and the result of each schema, so the runner doesn't
+/// depend on how the progress is shown.
+pub trait ProgressSink: Send + Sync {
+    fn progress(&self, progress: &ProgressMessage);
+
+    fn schema_result(&self, schema: &str, result: &ResultPerSchema);
+}
+
+/// Emits the progress as the events of the window.
+impl ProgressSink for tauri::Window {
+    fn progress(&self, progress: &ProgressMessage) {
+        if let Err(e) = self.emit("scan_query_progress", Some(progress)) {
+            warn!("emit progress error: {}", e);
+        }
+    }
+
+    fn schema_result(&self, schema: &str, result: &ResultPerSchema) {
+        if let Err(e) = self.emit("scan_query_schema_result", Some((schema, result))) {
+            warn!("emit schema result error: {}", e);
+        }
+    }
+}
+
+pub struct NoopSink;
+
+impl ProgressSink for NoopSink {
+    fn progress(&self, _progress: &ProgressMessage) {}
+
+    fn schema_result(&self, _schema: &str, _result: &ResultPerSchema) {}
+}
+
+#[derive(SimpleObject, Clone, Debug)]
+pub struct ScanProgressEvent {
+    pub run_id: String,
+    schema: String,
+    /// The index of the query, none for the result of the schema.
+    index: Option<usize>,
+    parameters: Option<Json<Vec<Value>>>,
+    finished: usize,
+    pending: usize,
+    total: usize,
+    /// The result of the schema, once all its queries finished.
+    schema_result: Option<Json<Value>>,
+}
+
+impl ScanProgressEvent {
+    fn from_progress(run_id: &str, progress: &ProgressMessage) -> Self {
+        Self {
+            run_id: run_id.to_string(),
+            schema: progress.schema().to_string(),
+            index: Some(progress.index()),
+            parameters: progress.parameters().map(|p| Json(p.to_vec())),
+            finished: progress.finished(),
+            pending: progress.pending(),
+            total: progress.total(),
+            schema_result: None,
+        }
+    }
+
+    fn from_schema_result(run_id: &str, schema: &str, result: &ResultPerSchema) -> Self {
+        Self {
+            run_id: run_id.to_string(),
+            schema: schema.to_string(),
+            index: None,
+            parameters: None,
+            finished: 0,
+            pending: 0,
+            total: 0,
+            schema_result: serde_json::to_value(result).ok().map(Json),
+        }
+    }
+}
+
+/// Keeps the events of a scan, for the callers which check the progress afterwards.
+#[derive(Default)]
+pub struct CollectingSink {
+    events: Mutex<Vec<ScanProgressEvent>>,
+}
+
+impl CollectingSink {
+    pub fn events(&self) -> Vec<ScanProgressEvent> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl ProgressSink for CollectingSink {
+    fn progress(&self, progress: &ProgressMessage) {
+        let event = ScanProgressEvent::from_progress("", progress);
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn schema_result(&self, schema: &str, result: &ResultPerSchema) {
+        let event = ScanProgressEvent::from_schema_result("", schema, result);
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+lazy_static! {
+    static ref EVENTS: Sender<ScanProgressEvent> = broadcast::channel(EVENTS_CAPACITY).0;
+}
+
+/// Publishes the progress of a scan to the GraphQL subscribers.
+pub struct SubscriptionSink {
+    run_id: String,
+}
+
+impl SubscriptionSink {
+    pub fn new(run_id: &str) -> Self {
+        Self {
+            run_id: run_id.to_string(),
+        }
+    }
+
+    fn publish(&self, event: ScanProgressEvent) {
+        // Sending fails only when nobody is subscribed.
+        let _ = EVENTS.send(event);
+    }
+}
+
+impl ProgressSink for SubscriptionSink {
+    fn progress(&self, progress: &ProgressMessage) {
+        self.publish(ScanProgressEvent::from_progress(&self.run_id, progress));
+    }
+
+    fn schema_result(&self, schema: &str, result: &ResultPerSchema) {
+        self.publish(ScanProgressEvent::from_schema_result(
+            &self.run_id,
+            schema,
+            result,
+        ));
+    }
+}
+
+pub fn subscribe() -> impl Stream<Item = ScanProgressEvent> {
+    stream::unfold(EVENTS.subscribe(), |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((event, receiver)),
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("scan progress subscriber skipped {} events.", skipped)
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        handlers::query_runner::Query,
+        proxies::query_runner::{scan_schema_queries, ScanSettings},
+    };
+
+    #[test]
+    fn test_collect_schema_result() {
+        let sink = Arc::new(CollectingSink::default());
+        let queries: Arc<[Arc<Query>]> = Arc::from(Vec::new());
+        scan_schema_queries(
+            sink.clone(),
+            "GREENCO".to_string(),
+            queries,
+            "collect-schema-result".to_string(),
+            Arc::new(ScanSettings::default()),
+            None,
+            None,
+        );
+
+        let events = sink.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].schema, "GREENCO");
+        assert_eq!(events[0].index, None);
+        assert!(events[0].schema_result.is_some());
+    }
+}
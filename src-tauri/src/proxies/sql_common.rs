@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::time::Duration;
 use std::{cmp, error::Error, fmt};
 
@@ -6,56 +7,221 @@ use anyhow::Result;
 use async_graphql::{Enum, InputObject, SimpleObject};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tokio_postgres::error::SqlState;
+use uuid::Uuid;
 
 use crate::utilities::find_position_line;
 
-use super::postgres::PostgresProxy;
+use super::{
+    connection_health::{self, ConnectionStatus},
+    postgres::PostgresProxy,
+};
 
-const COMPANY_PLACEHOLDER: &str = "company_";
+/// The prefix of the tables standing for the schema, when a profile configures none.
+pub const DEFAULT_SCHEMA_PLACEHOLDER: &str = "COMPANY_";
+/// The schema the statements are validated against, when a profile configures none.
+pub const DEFAULT_SCHEMA: &str = "GREENCO";
 
-pub struct ConsoleManager<C> {
+/// The hard cap of rows fetched into a single result set.
+pub const MAX_FETCH_ROWS: usize = 100000;
+
+const DEFAULT_POOL_SIZE: usize = 4;
+
+/// A console connection, along with the cursor it keeps open on the server for a paged query.
+pub trait ConsoleConnection {
+    type Cursor;
+}
+
+pub struct ConsoleManager<C: ConsoleConnection> {
+    pub profile: String,
     pub autocommit: bool,
     pub config: Config,
     pub console_client: Option<C>,
+    pub savepoints: Vec<SavePoint>,
+    pub cursors: HashMap<String, SQLCursor<C::Cursor>>,
+    /// The changes made in the console transaction since the last commit or rollback.
+    pub transaction: TransactionStats,
 }
 
-pub trait ConsoleManagerTrait<C> {}
+#[derive(Default, Clone, Debug)]
+pub struct TransactionStats {
+    started_at: Option<String>,
+    dml_count: usize,
+    rows_affected: usize,
+}
 
-pub fn process_statement_schema(statement: &str, schema: &str) -> String {
-    let mut stmt = String::from(statement);
-    let mut stmt_lower = statement.to_lowercase();
-    loop {
-        let str_index = stmt_lower
-            .find(COMPANY_PLACEHOLDER)
-            .unwrap_or(stmt_lower.len());
-        if str_index == stmt_lower.len() {
-            break;
+impl<C: ConsoleConnection> ConsoleManager<C> {
+    pub fn new(profile: String, config: Config) -> Self {
+        Self {
+            profile,
+            autocommit: false,
+            config,
+            console_client: None,
+            savepoints: Vec::new(),
+            cursors: HashMap::new(),
+            transaction: TransactionStats::default(),
         }
+    }
 
-        stmt.replace_range(
-            str_index..str_index + COMPANY_PLACEHOLDER.len(),
-            &format!("{}.", schema),
-        );
-        stmt_lower.replace_range(
-            str_index..str_index + COMPANY_PLACEHOLDER.len(),
-            &format!("{}.", schema),
-        );
+    /// Tracks the changes of a statement executed in the console transaction. Besides the DML, a
+    /// PL/SQL block, or another statement which affected rows, is counted as long as it returned
+    /// no columns, the statements returning rows are reads and `SET` affects none.
+    pub fn track_result(&mut self, kind: StatementKind, result_set: &SQLResultSet) {
+        let returns_rows = result_set.columns().is_some();
+        let changes = match kind {
+            StatementKind::Dml => true,
+            StatementKind::Plsql => !returns_rows,
+            StatementKind::Other => !returns_rows && result_set.row_count() > 0,
+            StatementKind::Query | StatementKind::Ddl => false,
+        };
+        if self.autocommit || !changes {
+            return;
+        }
+
+        let stats = &mut self.transaction;
+        if stats.started_at.is_none() {
+            stats.started_at = Some(chrono::Local::now().to_rfc3339());
+        }
+        stats.dml_count += 1;
+        stats.rows_affected += result_set.row_count();
     }
 
-    stmt
-}
+    /// Whether the open transaction has changes not committed yet.
+    pub fn uncommitted(&self) -> bool {
+        self.transaction.dml_count > 0
+    }
 
-pub fn process_statement_params(statement: &str, param_sign: &str) -> String {
-    let mut new_stmt = String::with_capacity(statement.len());
-    let split = statement.split("?");
-    for (idx, part) in split.enumerate() {
-        if idx != 0 {
-            new_stmt.push_str(&format!("{}{}", param_sign, idx));
+    /// Forgets the state of the transaction once it's committed or rolled back.
+    pub fn end_transaction(&mut self) {
+        self.savepoints.clear();
+        self.transaction = TransactionStats::default();
+    }
+
+    pub fn transaction_status(&self) -> TransactionStatus {
+        let stats = &self.transaction;
+        TransactionStatus {
+            state: if self.uncommitted() {
+                TransactionState::Open
+            } else {
+                TransactionState::Idle
+            },
+            autocommit: self.autocommit,
+            started_at: stats.started_at.clone(),
+            dml_count: stats.dml_count,
+            rows_affected: stats.rows_affected,
+            savepoint_count: self.savepoints.len(),
+            transaction_id: None,
         }
-        new_stmt.push_str(part);
     }
 
-    new_stmt
+    /// Drops the lost console connection so a new one will be opened, unless it would silently
+    /// discard uncommitted changes, then the caller has to roll back the console first.
+    pub fn on_connection_lost(&mut self) -> Result<(), SQLError> {
+        if self.uncommitted() {
+            connection_health::publish(&self.profile, ConnectionStatus::TransactionLost, None);
+            return Err(SQLError::new_str(
+                "The console connection was lost with uncommitted changes, roll back the console to acknowledge the lost transaction and reconnect.",
+            ));
+        }
+
+        connection_health::publish(&self.profile, ConnectionStatus::Reconnecting, None);
+        self.console_client = None;
+        self.end_transaction();
+        self.cursors.clear();
+        Ok(())
+    }
+
+    pub fn has_savepoint(&self, name: &str) -> bool {
+        self.savepoints
+            .iter()
+            .any(|sp| sp.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Tracks a new savepoint on top of the stack, an earlier savepoint with the same name is
+    /// kept, as Postgres does.
+    pub fn push_savepoint(&mut self, name: &str) {
+        let savepoint = self.new_savepoint(name);
+        self.savepoints.push(savepoint);
+    }
+
+    /// Tracks a new savepoint on top of the stack, an earlier savepoint with the same name is
+    /// erased, as Oracle does.
+    pub fn replace_savepoint(&mut self, name: &str) {
+        self.savepoints
+            .retain(|sp| !sp.name.eq_ignore_ascii_case(name));
+        let savepoint = self.new_savepoint(name);
+        self.savepoints.push(savepoint);
+    }
+
+    fn new_savepoint(&self, name: &str) -> SavePoint {
+        let mut savepoint = SavePoint::new(name);
+        savepoint.cursors = self.cursors.keys().cloned().collect();
+        savepoint
+    }
+
+    /// The position of the latest savepoint with the name, which the rollbacks and the releases
+    /// refer to.
+    fn savepoint_position(&self, name: &str) -> Option<usize> {
+        self.savepoints
+            .iter()
+            .rposition(|sp| sp.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Drops the savepoints established after the given one, it stays active.
+    pub fn rollback_savepoints_to(&mut self, name: &str) {
+        if let Some(pos) = self.savepoint_position(name) {
+            self.savepoints.truncate(pos + 1);
+        }
+    }
+
+    /// Forgets the cursors opened after the latest savepoint with the name, since rolling back to
+    /// it closes them on the server, the cursors opened before stay open.
+    pub fn forget_cursors_after(&mut self, name: &str) {
+        if let Some(pos) = self.savepoint_position(name) {
+            let open_before = &self.savepoints[pos].cursors;
+            self.cursors.retain(|id, _| open_before.contains(id));
+        }
+    }
+
+    /// Drops the given savepoint and all the savepoints established after it, an earlier
+    /// savepoint with the same name becomes the latest one.
+    pub fn release_savepoints_from(&mut self, name: &str) {
+        if let Some(pos) = self.savepoint_position(name) {
+            self.savepoints.truncate(pos);
+        }
+    }
+
+    /// Takes the cursor out while its page is fetched, `sync_cursor` puts it back.
+    pub fn take_cursor(&mut self, id: &str) -> Option<SQLCursor<C::Cursor>> {
+        self.cursors.remove(id)
+    }
+
+    /// Keeps the cursor open while the result set has more rows, otherwise closes it.
+    pub fn sync_cursor(
+        &mut self,
+        id: &str,
+        cursor: SQLCursor<C::Cursor>,
+        result_set: &mut SQLResultSet,
+    ) {
+        if result_set.truncated {
+            self.cursors.insert(id.to_string(), cursor);
+            result_set.cursor = Some(id.to_string());
+        }
+    }
+
+    pub fn close_cursor(&mut self, id: &str) -> bool {
+        self.cursors.remove(id).is_some()
+    }
+}
+
+pub trait ConsoleManagerTrait<C> {}
+
+pub fn process_statement_schema(statement: &str, schema: &str, placeholders: &[&str]) -> String {
+    get_schema_stmt(schema, statement, placeholders)
+}
+
+pub fn process_statement_params(statement: &str, param_sign: &str) -> String {
+    generate_param_stmt(statement, param_sign)
 }
 
 #[derive(Enum, Serialize, Deserialize, Debug, Copy, Eq, PartialEq, Clone)]
@@ -65,12 +231,116 @@ pub enum DBType {
     Postgres,
 }
 
+/// What a statement does, classified before it's scanned.
+#[derive(Enum, Serialize, Deserialize, Debug, Copy, Eq, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum StatementKind {
+    Query,
+    Dml,
+    Ddl,
+    /// Oracle PL/SQL blocks and calls.
+    Plsql,
+    /// Anything else, including the statements which fail to parse.
+    Other,
+}
+
+impl StatementKind {
+    /// Whether the changes of the statement are undone by a rollback, Oracle commits DDL
+    /// implicitly and the other statements may commit by themselves.
+    pub fn is_transactional(&self, db_type: DBType) -> bool {
+        match self {
+            StatementKind::Query | StatementKind::Dml => true,
+            StatementKind::Ddl => db_type == DBType::Postgres,
+            StatementKind::Plsql | StatementKind::Other => false,
+        }
+    }
+}
+
+/// The max number of rows to fetch from a query result.
+#[derive(Clone, Copy, Debug)]
+pub struct FetchWindow {
+    pub limit: usize,
+}
+
+impl FetchWindow {
+    pub fn new(limit: usize) -> Self {
+        FetchWindow {
+            limit: cmp::min(limit, MAX_FETCH_ROWS),
+        }
+    }
+}
+
+impl Default for FetchWindow {
+    fn default() -> Self {
+        FetchWindow::new(MAX_FETCH_ROWS)
+    }
+}
+
+/// A query fetched page by page from the cursor `S` kept open on the server, so the statement
+/// only runs once.
+pub struct SQLCursor<S> {
+    server_cursor: S,
+    columns: Vec<String>,
+    page_size: usize,
+    /// The first row of the next page, fetched ahead to tell if there is a next page.
+    lookahead: Option<Vec<Value>>,
+}
+
+impl<S> SQLCursor<S> {
+    pub fn new(server_cursor: S, columns: Vec<String>, page_size: usize) -> Self {
+        SQLCursor {
+            server_cursor,
+            columns,
+            page_size: page_size.clamp(1, MAX_FETCH_ROWS),
+            lookahead: None,
+        }
+    }
+
+    pub fn new_id() -> String {
+        Uuid::new_v4().to_string()
+    }
+
+    pub fn server_cursor(&mut self) -> &mut S {
+        &mut self.server_cursor
+    }
+
+    /// The number of rows to fetch from the server cursor for the next page, along with the row
+    /// looked ahead.
+    pub fn rows_to_fetch(&self) -> usize {
+        match self.lookahead {
+            Some(_) => self.page_size,
+            None => self.page_size + 1,
+        }
+    }
+
+    /// Builds the next page of the fetched rows, the row past the page is kept for the next one.
+    pub fn next_page(&mut self, fetched: Vec<Vec<Value>>) -> SQLResultSet {
+        let mut rows: Vec<_> = self.lookahead.take().into_iter().chain(fetched).collect();
+        if rows.len() > self.page_size {
+            self.lookahead = rows.drain(self.page_size..).next();
+        }
+
+        SQLResultSet::new(rows.len(), Some(self.columns.clone()), Some(rows))
+            .with_truncated(self.lookahead.is_some())
+    }
+}
+
+/// A statement run to be fetched page by page, only a query keeps a cursor open.
+pub enum PagedStatement<S> {
+    Cursor(SQLCursor<S>),
+    Executed(StatementKind, SQLResultSet),
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct SQLResultSet {
     row_count: usize,
     columns: Option<Vec<String>>,
     rows: Option<Vec<Vec<Value>>>,
+    #[serde(default)]
+    truncated: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>,
 }
 
 impl SQLResultSet {
@@ -83,9 +353,25 @@ impl SQLResultSet {
             row_count,
             columns,
             rows,
+            truncated: false,
+            cursor: None,
         }
     }
 
+    /// Marks the result set as holding only part of the rows of the query.
+    pub fn with_truncated(mut self, truncated: bool) -> Self {
+        self.truncated = truncated;
+        self
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.row_count
+    }
+
     pub fn sort_rows(&mut self) -> () {
         match self.rows.as_mut() {
             Some(row_vec) => {
@@ -123,6 +409,10 @@ impl SQLResultSet {
     pub fn get_rows(&self) -> &Option<Vec<Vec<Value>>> {
         &self.rows
     }
+
+    pub fn columns(&self) -> Option<&[String]> {
+        self.columns.as_deref()
+    }
 }
 
 #[derive(Clone, Serialize, Debug, Deserialize)]
@@ -159,9 +449,17 @@ impl From<&tokio_postgres::error::ErrorPosition> for QueryErrorPoisition {
     }
 }
 
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SQLErrorKind {
+    Timeout,
+}
+
 #[derive(Clone, Serialize, Deserialize, Default, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct SQLError {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kind: Option<SQLErrorKind>,
     action: Option<String>,
     code: Option<String>,
     column: Option<String>,
@@ -239,6 +537,21 @@ impl SQLError {
         instance
     }
 
+    pub fn new_timeout(timeout: Duration) -> Self {
+        SQLError {
+            kind: Some(SQLErrorKind::Timeout),
+            message: format!(
+                "The statement didn't finish in {} ms and was cancelled.",
+                timeout.as_millis()
+            ),
+            ..Default::default()
+        }
+    }
+
+    pub fn kind(&self) -> Option<SQLErrorKind> {
+        self.kind
+    }
+
     pub fn get_code(&self) -> &Option<String> {
         &self.code
     }
@@ -283,7 +596,15 @@ impl From<oracle::Error> for SQLError {
                     Some(e.offset())
                 };
 
+                // DPI-1067: the call timeout of the connection is exceeded.
+                let kind = if message.starts_with("DPI-1067") {
+                    Some(SQLErrorKind::Timeout)
+                } else {
+                    None
+                };
+
                 SQLError {
+                    kind,
                     message,
                     action,
                     code,
@@ -320,8 +641,17 @@ impl From<tokio_postgres::Error> for SQLError {
                 let severity = Some(db_err.severity().to_string());
                 let table = opt_str_to_opt_string(db_err.table());
                 let where_ = opt_str_to_opt_string(db_err.where_());
+                // query_canceled is also raised by a cancel request, only the message tells them apart.
+                let kind = if db_err.code() == &SqlState::QUERY_CANCELED
+                    && message.contains("statement timeout")
+                {
+                    Some(SQLErrorKind::Timeout)
+                } else {
+                    None
+                };
 
                 SQLError {
+                    kind,
                     code,
                     column,
                     constraint,
@@ -368,6 +698,15 @@ impl SQLResult {
         Self::ResultWithStatistics { result, statistics }
     }
 
+    pub fn result_set(&self) -> Option<&SQLResultSet> {
+        match self {
+            SQLResult::Result(result) | SQLResult::ResultWithStatistics { result, .. } => {
+                result.as_ref()
+            }
+            SQLResult::Error(_) => None,
+        }
+    }
+
     pub fn new_error(error: SQLError) -> SQLResult {
         SQLResult::Error(error)
     }
@@ -390,13 +729,80 @@ impl SQLReponse {
     }
 }
 
-#[derive(SimpleObject, InputObject)]
+/// Input only, so the password is never sent back over GraphQL.
+#[derive(InputObject, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Config {
     pub host: String,
     pub port: String,
     pub db: String,
     pub username: String,
     pub password: String,
+    /// Timeout of a single statement in milliseconds, no timeout when absent.
+    #[serde(default)]
+    pub statement_timeout: Option<u64>,
+    /// Statements executed on every new session, e.g. `SET search_path TO anaconda`.
+    #[serde(default)]
+    pub init_statements: Option<Vec<String>>,
+    /// Postgres only, TLS is disabled when absent.
+    #[serde(default)]
+    pub ssl_mode: Option<SslMode>,
+    /// Path of the PEM CA bundle used to verify the server certificate.
+    #[serde(default)]
+    pub ssl_root_cert: Option<String>,
+    /// Paths of the PEM client certificate and its private key.
+    #[serde(default)]
+    pub ssl_cert: Option<String>,
+    #[serde(default)]
+    pub ssl_key: Option<String>,
+    /// Oracle only, how the connect string is built, defaults to the SID of `db`.
+    #[serde(default)]
+    pub connect_mode: Option<OracleConnectMode>,
+    /// The raw descriptor, TNS alias or EZConnect URL of the `connect_mode`.
+    #[serde(default)]
+    pub connect_string: Option<String>,
+    /// The max number of pooled connections used by the scans of the profile.
+    #[serde(default)]
+    pub pool_size: Option<u32>,
+    /// The prefixes of the tables standing for the schema, `COMPANY_` when absent.
+    #[serde(default)]
+    pub schema_placeholders: Option<Vec<String>>,
+    /// The schema the statements are validated against, `GREENCO` when absent.
+    #[serde(default)]
+    pub default_schema: Option<String>,
+}
+
+#[derive(Enum, Serialize, Deserialize, Debug, Copy, Eq, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum OracleConnectMode {
+    Sid,
+    ServiceName,
+    Descriptor,
+    TnsAlias,
+    EzConnect,
+}
+
+impl Default for OracleConnectMode {
+    fn default() -> Self {
+        OracleConnectMode::Sid
+    }
+}
+
+/// Mirrors the libpq sslmode, `Require` also verifies the certificate chain when a CA bundle
+/// is configured.
+#[derive(Enum, Serialize, Deserialize, Debug, Copy, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum SslMode {
+    Disable,
+    Prefer,
+    Require,
+    VerifyFull,
+}
+
+impl Default for SslMode {
+    fn default() -> Self {
+        SslMode::Disable
+    }
 }
 
 impl Config {
@@ -407,51 +813,714 @@ impl Config {
             db: db.to_string(),
             username: username.to_string(),
             password: password.to_string(),
+            statement_timeout: None,
+            init_statements: None,
+            ssl_mode: None,
+            ssl_root_cert: None,
+            ssl_cert: None,
+            ssl_key: None,
+            connect_mode: None,
+            connect_string: None,
+            pool_size: None,
+            schema_placeholders: None,
+            default_schema: None,
         }
     }
+
+    pub fn connect_mode(&self) -> OracleConnectMode {
+        self.connect_mode.unwrap_or_default()
+    }
+
+    pub fn ssl_mode(&self) -> SslMode {
+        self.ssl_mode.unwrap_or_default()
+    }
+
+    pub fn statement_timeout(&self) -> Option<Duration> {
+        self.statement_timeout.map(Duration::from_millis)
+    }
+
+    pub fn init_statements(&self) -> &[String] {
+        self.init_statements.as_deref().unwrap_or(&[])
+    }
+
+    pub fn schema_placeholders(&self) -> Vec<&str> {
+        match &self.schema_placeholders {
+            Some(placeholders) if !placeholders.is_empty() => {
+                placeholders.iter().map(String::as_str).collect()
+            }
+            _ => vec![DEFAULT_SCHEMA_PLACEHOLDER],
+        }
+    }
+
+    pub fn default_schema(&self) -> &str {
+        match self.default_schema.as_deref() {
+            Some(schema) if !schema.trim().is_empty() => schema,
+            _ => DEFAULT_SCHEMA,
+        }
+    }
+
+    /// The statement with the placeholders replaced by the default schema.
+    pub fn default_schema_stmt(&self, stmt: &str) -> String {
+        get_schema_stmt(self.default_schema(), stmt, &self.schema_placeholders())
+    }
+
+    pub fn pool_size(&self) -> usize {
+        self.pool_size
+            .map_or(DEFAULT_POOL_SIZE, |size| cmp::max(size as usize, 1))
+    }
+
     pub fn to_key_value_string(&self) -> String {
+        // The certificate checks of verify-full are done by the TLS connector itself.
+        let ssl_mode = match self.ssl_mode() {
+            SslMode::Disable => "disable",
+            SslMode::Prefer => "prefer",
+            SslMode::Require | SslMode::VerifyFull => "require",
+        };
         format!(
-            "host={} port={} user={} password={} dbname={}",
-            self.host, self.port, self.username, self.password, self.db
+            "host={} port={} user={} password={} dbname={} sslmode={}",
+            self.host, self.port, self.username, self.password, self.db, ssl_mode
         )
     }
 }
 
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TransactionState {
+    /// Nothing to commit since the last commit or rollback.
+    Idle,
+    /// Changes are pending, they are lost if the console is closed or rolled back.
+    Open,
+}
+
+#[derive(SimpleObject, Clone, Debug)]
+pub struct TransactionStatus {
+    state: TransactionState,
+    autocommit: bool,
+    /// When the first change of the transaction was made.
+    started_at: Option<String>,
+    /// The number of DML statements executed since the last commit or rollback.
+    dml_count: usize,
+    rows_affected: usize,
+    savepoint_count: usize,
+    /// The id assigned to the transaction by the server, only available with Postgres.
+    transaction_id: Option<String>,
+}
+
+impl TransactionStatus {
+    pub fn with_transaction_id(mut self, transaction_id: Option<String>) -> Self {
+        self.transaction_id = transaction_id;
+        self
+    }
+}
+
+#[derive(SimpleObject, Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct SavePoint {
     name: String,
+    created_at: String,
+    /// The ids of the console cursors open when the savepoint was established.
+    #[graphql(skip)]
+    #[serde(skip)]
+    cursors: Vec<String>,
+}
+
+impl SavePoint {
+    pub fn new(name: &str) -> Self {
+        SavePoint {
+            name: name.to_string(),
+            created_at: chrono::Local::now().to_rfc3339(),
+            cursors: Vec::new(),
+        }
+    }
+
+    pub fn validate_name(name: &str) -> Result<(), SQLError> {
+        let mut chars = name.chars();
+        let valid = match chars.next() {
+            Some(c) => {
+                c.is_ascii_alphabetic()
+                    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+            }
+            None => false,
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(SQLError::new(format!("Invalid savepoint name: {}", name)))
+        }
+    }
 }
 
 pub trait SQLClient {
     fn execute_stmt(
-        &'static self,
+        &self,
         statement: &str,
         parameters: &[Value],
         with_statistics: bool,
+        run_id: Option<&str>,
     ) -> Result<SQLResult>;
-    fn set_config(&'static self, config: Config) -> Result<SQLResult>;
-    fn set_autocommit(&'static self, autocommit: bool) -> Result<SQLResult>;
-    fn commit_console(&'static self) -> Result<SQLResult>;
-    fn rollback_console(&'static self) -> Result<SQLResult>;
-    fn add_savepoint(&'static self, name: &str) -> Result<SQLResult>;
-    fn rollback_to_savepoint(&'static self, name: &str) -> Result<SQLResult>;
-    fn validate_stmts(&'static self, stmts: &[&str]) -> Result<Vec<SQLResult>>;
+    fn set_config(&self, config: Config) -> Result<SQLResult>;
+    fn set_autocommit(&self, autocommit: bool) -> Result<SQLResult>;
+    fn commit_console(&self) -> Result<SQLResult>;
+    fn rollback_console(&self) -> Result<SQLResult>;
+    fn add_savepoint(&self, name: &str) -> Result<SQLResult>;
+    fn rollback_to_savepoint(&self, name: &str) -> Result<SQLResult>;
+    fn release_savepoint(&self, name: &str) -> Result<SQLResult>;
+    fn savepoints(&self) -> Result<Vec<SavePoint>>;
+    fn transaction_status(&self) -> Result<TransactionStatus>;
+    fn validate_stmts(&self, stmts: &[&str]) -> Result<Vec<SQLResult>>;
+    fn execute_stmt_paged(
+        &self,
+        statement: &str,
+        parameters: &[Value],
+        page_size: usize,
+        run_id: Option<&str>,
+    ) -> Result<SQLResult>;
+    fn fetch_next_page(&self, cursor: &str, run_id: Option<&str>) -> Result<SQLResult>;
+    fn close_cursor(&self, cursor: &str) -> Result<bool>;
 }
 
-pub fn get_schema_stmt(schema: &str, stmt: &str) -> String {
-    stmt.to_lowercase()
-        .replace(COMPANY_PLACEHOLDER, &format!("{}.", schema))
+/// Replaces the placeholder prefixes of the identifiers with the schema, e.g. `COMPANY_ITEM`
+/// becomes `GREENCO.ITEM`. The prefixes match ignoring the case, the literals, the comments and
+/// the quoted identifiers are kept as they are, and so are the identifiers qualified by another
+/// name, e.g. the column `c.COMPANY_ID`.
+pub fn get_schema_stmt(schema: &str, stmt: &str, placeholders: &[&str]) -> String {
+    let mut res = String::with_capacity(stmt.len());
+    let mut qualified = false;
+    for token in tokenize(stmt) {
+        let prefix_len = match token.kind {
+            SqlTokenKind::Identifier if !qualified => placeholders
+                .iter()
+                .find(|placeholder| {
+                    !placeholder.is_empty()
+                        && matches!(
+                            token.text.get(..placeholder.len()),
+                            Some(prefix) if prefix.eq_ignore_ascii_case(placeholder)
+                        )
+                })
+                .map(|placeholder| placeholder.len()),
+            _ => None,
+        };
+
+        match prefix_len {
+            Some(len) => {
+                res.push_str(schema);
+                res.push('.');
+                res.push_str(&token.text[len..]);
+            }
+            None => res.push_str(token.text),
+        }
+        qualified = token.text == ".";
+    }
+
+    res
 }
 
+/// Numbers the `?` placeholders with the parameter sign, the question marks in the literals and
+/// the comments are kept.
 pub fn generate_param_stmt(stmt: &str, param_sign: &str) -> String {
     let mut res = String::with_capacity(stmt.len());
-    let (mut stmt_parts, mut i) = (stmt.split("?"), 1);
-
-    res.push_str(stmt_parts.next().unwrap());
-    for part in stmt_parts {
-        res.push_str(&format!("{}{}", param_sign, i));
-        res.push_str(part);
-        i += 1;
+    let mut i = 1;
+    for token in tokenize(stmt) {
+        match token.kind {
+            SqlTokenKind::Placeholder(Placeholder::Positional) => {
+                res.push_str(&format!("{}{}", param_sign, i));
+                i += 1;
+            }
+            _ => res.push_str(token.text),
+        }
     }
 
     res
 }
+
+/// A bind variable of a statement.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Placeholder {
+    /// `?`, bound to the next parameter.
+    Positional,
+    /// `:1` or `$1`, bound to the parameter by its number.
+    Numbered(usize),
+    /// `:name`, `$name` or `@name`.
+    Named(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SqlTokenKind {
+    Identifier,
+    /// An identifier in double quotes.
+    QuotedIdentifier,
+    /// A string literal, including the Postgres dollar quoted strings.
+    Literal,
+    Comment,
+    Placeholder(Placeholder),
+    /// Whitespace, numbers, operators and punctuation.
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqlToken<'a> {
+    pub kind: SqlTokenKind,
+    pub text: &'a str,
+}
+
+fn is_identifier_start(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'_' || !b.is_ascii()
+}
+
+fn is_identifier_char(b: u8) -> bool {
+    is_identifier_start(b) || b.is_ascii_digit() || b == b'$' || b == b'#'
+}
+
+/// The end of the run of bytes from the start which match the predicate.
+fn scan_while(bytes: &[u8], start: usize, pred: impl Fn(u8) -> bool) -> usize {
+    bytes[start..]
+        .iter()
+        .position(|&b| !pred(b))
+        .map_or(bytes.len(), |len| start + len)
+}
+
+/// The end of the text which ends with the terminator, the end of the statement if it's never
+/// terminated.
+fn scan_until(stmt: &str, start: usize, terminator: &str) -> usize {
+    stmt[start..]
+        .find(terminator)
+        .map_or(stmt.len(), |i| start + i + terminator.len())
+}
+
+/// The end of a literal in single quotes starting at the quote, `''` is an escaped quote and so
+/// is `\'` when the backslash escapes.
+fn scan_quoted(bytes: &[u8], start: usize, quote: u8, backslash_escapes: bool) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if backslash_escapes => i += 2,
+            b if b == quote && bytes.get(i + 1) == Some(&quote) => i += 2,
+            b if b == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+
+    bytes.len()
+}
+
+/// The end of an Oracle q-quoted literal starting at the quote, e.g. `q'[it's]'`.
+fn scan_q_quoted(stmt: &str, start: usize) -> usize {
+    let bytes = stmt.as_bytes();
+    let close = match bytes.get(start + 1) {
+        Some(b'[') => b']',
+        Some(b'(') => b')',
+        Some(b'{') => b'}',
+        Some(b'<') => b'>',
+        Some(&delimiter) if delimiter.is_ascii() => delimiter,
+        _ => return scan_quoted(bytes, start, b'\'', false),
+    };
+    let terminator = format!("{}'", close as char);
+    scan_until(stmt, (start + 2).min(stmt.len()), &terminator)
+}
+
+/// Splits the statement into tokens, so the placeholders and the identifiers are found outside
+/// the literals and the comments. Concatenating the text of the tokens gives the statement back.
+pub fn tokenize(stmt: &str) -> Vec<SqlToken<'_>> {
+    let bytes = stmt.as_bytes();
+    let mut tokens: Vec<SqlToken> = Vec::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let next = bytes.get(start + 1).copied();
+        let (kind, end) = match bytes[start] {
+            b'\'' => (
+                SqlTokenKind::Literal,
+                scan_quoted(bytes, start, b'\'', false),
+            ),
+            b'"' => (
+                SqlTokenKind::QuotedIdentifier,
+                scan_quoted(bytes, start, b'"', false),
+            ),
+            b'-' if next == Some(b'-') => (
+                SqlTokenKind::Comment,
+                scan_while(bytes, start, |b| b != b'\n'),
+            ),
+            b'/' if next == Some(b'*') => {
+                (SqlTokenKind::Comment, scan_until(stmt, start + 2, "*/"))
+            }
+            b'?' => (
+                SqlTokenKind::Placeholder(Placeholder::Positional),
+                start + 1,
+            ),
+            b':' if next == Some(b':') => (SqlTokenKind::Other, start + 2),
+            b':' | b'$' if matches!(next, Some(b) if b.is_ascii_digit()) => {
+                let end = scan_while(bytes, start + 1, |b| b.is_ascii_digit());
+                match stmt[start + 1..end].parse() {
+                    Ok(n) => (SqlTokenKind::Placeholder(Placeholder::Numbered(n)), end),
+                    Err(_) => (SqlTokenKind::Other, end),
+                }
+            }
+            b'$' if next == Some(b'$') => {
+                (SqlTokenKind::Literal, scan_until(stmt, start + 2, "$$"))
+            }
+            b'$' if matches!(next, Some(b) if is_identifier_start(b)) => {
+                let end = scan_while(bytes, start + 1, |b| {
+                    is_identifier_char(b) && b != b'$' && b != b'#'
+                });
+                if bytes.get(end) == Some(&b'$') {
+                    let tag = &stmt[start..=end];
+                    (SqlTokenKind::Literal, scan_until(stmt, end + 1, tag))
+                } else {
+                    let name = stmt[start + 1..end].to_string();
+                    (SqlTokenKind::Placeholder(Placeholder::Named(name)), end)
+                }
+            }
+            b':' if matches!(next, Some(b) if is_identifier_start(b)) => {
+                let end = scan_while(bytes, start + 1, is_identifier_char);
+                let name = stmt[start + 1..end].to_string();
+                (SqlTokenKind::Placeholder(Placeholder::Named(name)), end)
+            }
+            // An @ right after an identifier is an Oracle database link.
+            b'@' if matches!(next, Some(b) if is_identifier_start(b))
+                && !matches!(
+                    tokens.last().map(|t| &t.kind),
+                    Some(SqlTokenKind::Identifier | SqlTokenKind::QuotedIdentifier)
+                ) =>
+            {
+                let end = scan_while(bytes, start + 1, is_identifier_char);
+                let name = stmt[start + 1..end].to_string();
+                (SqlTokenKind::Placeholder(Placeholder::Named(name)), end)
+            }
+            b if is_identifier_start(b) => {
+                let end = scan_while(bytes, start, is_identifier_char);
+                let prefix = &stmt[start..end];
+                match bytes.get(end) {
+                    Some(b'\'') if prefix.eq_ignore_ascii_case("e") => {
+                        (SqlTokenKind::Literal, scan_quoted(bytes, end, b'\'', true))
+                    }
+                    Some(b'\'')
+                        if prefix.eq_ignore_ascii_case("q")
+                            || prefix.eq_ignore_ascii_case("nq") =>
+                    {
+                        (SqlTokenKind::Literal, scan_q_quoted(stmt, end))
+                    }
+                    Some(b'\'') if prefix.eq_ignore_ascii_case("n") => {
+                        (SqlTokenKind::Literal, scan_quoted(bytes, end, b'\'', false))
+                    }
+                    _ => (SqlTokenKind::Identifier, end),
+                }
+            }
+            b if b.is_ascii_digit() => (
+                SqlTokenKind::Other,
+                scan_while(bytes, start, |b| is_identifier_char(b) || b == b'.'),
+            ),
+            b if b.is_ascii_whitespace() => (
+                SqlTokenKind::Other,
+                scan_while(bytes, start, |b| b.is_ascii_whitespace()),
+            ),
+            _ => (SqlTokenKind::Other, start + 1),
+        };
+
+        tokens.push(SqlToken {
+            kind,
+            text: &stmt[start..end],
+        });
+        start = end;
+    }
+
+    tokens
+}
+
+/// Rewrites the placeholders of the statement into the native placeholders of the database and
+/// orders the parameters to match them. `?` takes the next positional parameter, `:1` and `$1`
+/// the positional parameter by number, and the named placeholders the named parameter, matched
+/// ignoring the case when there is no exact match. Oracle binds the placeholders by position so
+/// a repeated placeholder is bound again, Postgres refers to the same parameter.
+pub fn bind_parameters(
+    statement: &str,
+    db_type: DBType,
+    parameters: &[Value],
+    named: &serde_json::Map<String, Value>,
+) -> Result<(String, Vec<Value>), SQLError> {
+    let param_sign = match db_type {
+        DBType::Oracle => ":",
+        DBType::Postgres => "$",
+    };
+    let mut stmt = String::with_capacity(statement.len());
+    let mut bound: Vec<Value> = Vec::new();
+    let mut bound_keys: HashMap<Placeholder, usize> = HashMap::new();
+    let mut positional = 0;
+
+    for token in tokenize(statement) {
+        let placeholder = match token.kind {
+            SqlTokenKind::Placeholder(Placeholder::Positional) => {
+                positional += 1;
+                Placeholder::Numbered(positional)
+            }
+            SqlTokenKind::Placeholder(placeholder) => placeholder,
+            _ => {
+                stmt.push_str(token.text);
+                continue;
+            }
+        };
+
+        let existing = match db_type {
+            DBType::Postgres => bound_keys.get(&placeholder).copied(),
+            DBType::Oracle => None,
+        };
+        let index = match existing {
+            Some(index) => index,
+            None => {
+                bound.push(parameter_value(&placeholder, parameters, named)?);
+                bound_keys.insert(placeholder, bound.len());
+                bound.len()
+            }
+        };
+        stmt.push_str(&format!("{}{}", param_sign, index));
+    }
+
+    Ok((stmt, bound))
+}
+
+fn parameter_value(
+    placeholder: &Placeholder,
+    parameters: &[Value],
+    named: &serde_json::Map<String, Value>,
+) -> Result<Value, SQLError> {
+    let value = match placeholder {
+        Placeholder::Numbered(n) => n.checked_sub(1).and_then(|i| parameters.get(i)),
+        Placeholder::Named(name) => named.get(name).or_else(|| {
+            named
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value)
+        }),
+        Placeholder::Positional => None,
+    };
+
+    value.cloned().ok_or_else(|| match placeholder {
+        Placeholder::Named(name) => SQLError::new(format!("Missing the parameter :{}.", name)),
+        _ => SQLError::new(format!(
+            "Missing the parameter of the placeholder {:?}, {} parameters are given.",
+            placeholder,
+            parameters.len()
+        )),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    impl ConsoleConnection for () {
+        type Cursor = ();
+    }
+
+    fn console_manager() -> ConsoleManager<()> {
+        let config = Config::new("localhost", "5432", "postgres", "postgres", "");
+        ConsoleManager::new("test".to_string(), config)
+    }
+
+    #[test]
+    fn test_tokenize_placeholders() {
+        let stmt = "select 'a?' || :name, x::int, $1 -- why?\n from t@link where c = ? /* ? */ and d = $$?$$";
+        let tokens = tokenize(stmt);
+        let placeholders: Vec<_> = tokens
+            .iter()
+            .filter_map(|t| match &t.kind {
+                SqlTokenKind::Placeholder(p) => Some(p.clone()),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(
+            placeholders,
+            vec![
+                Placeholder::Named("name".to_string()),
+                Placeholder::Numbered(1),
+                Placeholder::Positional,
+            ]
+        );
+        assert_eq!(tokens.iter().map(|t| t.text).collect::<String>(), stmt);
+    }
+
+    #[test]
+    fn test_generate_param_stmt_skips_literals() {
+        assert_eq!(
+            generate_param_stmt("select '?', q'[?]' from t where a = ? and b = ?", ":"),
+            "select '?', q'[?]' from t where a = :1 and b = :2"
+        );
+    }
+
+    #[test]
+    fn test_bind_parameters() {
+        let named = json!({"ID": 7}).as_object().unwrap().clone();
+        let stmt = "select * from t where id = :id or parent = :id or x = ?";
+
+        let (pg_stmt, pg_params) =
+            bind_parameters(stmt, DBType::Postgres, &[json!("x")], &named).unwrap();
+        assert_eq!(
+            pg_stmt,
+            "select * from t where id = $1 or parent = $1 or x = $2"
+        );
+        assert_eq!(pg_params, vec![json!(7), json!("x")]);
+
+        let (ora_stmt, ora_params) =
+            bind_parameters(stmt, DBType::Oracle, &[json!("x")], &named).unwrap();
+        assert_eq!(
+            ora_stmt,
+            "select * from t where id = :1 or parent = :2 or x = :3"
+        );
+        assert_eq!(ora_params, vec![json!(7), json!(7), json!("x")]);
+
+        assert!(bind_parameters(":missing", DBType::Oracle, &[], &named).is_err());
+    }
+
+    #[test]
+    fn test_get_schema_stmt() {
+        let stmt = "SELECT c.Company_Id, 'company_x' FROM company_Item c /* COMPANY_ */ JOIN \"COMPANY_A\" a ON 1 = 1";
+        assert_eq!(
+            get_schema_stmt("GREENCO", stmt, &[DEFAULT_SCHEMA_PLACEHOLDER]),
+            "SELECT c.Company_Id, 'company_x' FROM GREENCO.Item c /* COMPANY_ */ JOIN \"COMPANY_A\" a ON 1 = 1"
+        );
+        assert_eq!(
+            get_schema_stmt(
+                "app",
+                "select * from tenant_users",
+                &["TENANT_", "COMPANY_"]
+            ),
+            "select * from app.users"
+        );
+    }
+
+    #[test]
+    fn test_process_statement_schema() {
+        let mut config = Config::new("localhost", "5432", "postgres", "postgres", "");
+        config.schema_placeholders = Some(vec!["TENANT_".to_string()]);
+        assert_eq!(
+            process_statement_schema(
+                "select * from tenant_users join company_roles using (id)",
+                "app",
+                &config.schema_placeholders()
+            ),
+            "select * from app.users join company_roles using (id)"
+        );
+    }
+
+    #[test]
+    fn test_cursor_pages() {
+        let mut cursor = SQLCursor::new((), vec!["ID".to_string()], 2);
+        let rows = |ids: &[i64]| ids.iter().map(|id| vec![json!(id)]).collect::<Vec<_>>();
+
+        assert_eq!(cursor.rows_to_fetch(), 3);
+        let page = cursor.next_page(rows(&[1, 2, 3]));
+        assert_eq!(page.get_rows(), &Some(rows(&[1, 2])));
+        assert!(page.truncated());
+
+        assert_eq!(cursor.rows_to_fetch(), 2);
+        let page = cursor.next_page(rows(&[4, 5]));
+        assert_eq!(page.get_rows(), &Some(rows(&[3, 4])));
+        assert!(page.truncated());
+
+        let page = cursor.next_page(Vec::new());
+        assert_eq!(page.get_rows(), &Some(rows(&[5])));
+        assert!(!page.truncated());
+    }
+
+    fn savepoint_names<C: ConsoleConnection>(manager: &ConsoleManager<C>) -> Vec<&str> {
+        manager.savepoints.iter().map(|sp| sp.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_savepoint_stack() {
+        let mut manager = console_manager();
+        for name in ["a", "b", "a", "c"] {
+            manager.push_savepoint(name);
+        }
+        assert_eq!(savepoint_names(&manager), vec!["a", "b", "a", "c"]);
+
+        manager.rollback_savepoints_to("A");
+        assert_eq!(savepoint_names(&manager), vec!["a", "b", "a"]);
+
+        manager.release_savepoints_from("a");
+        assert_eq!(savepoint_names(&manager), vec!["a", "b"]);
+        assert!(manager.has_savepoint("a"));
+
+        manager.release_savepoints_from("a");
+        assert!(savepoint_names(&manager).is_empty());
+        assert!(!manager.has_savepoint("a"));
+
+        manager.rollback_savepoints_to("missing");
+        manager.release_savepoints_from("missing");
+        assert!(savepoint_names(&manager).is_empty());
+    }
+
+    #[test]
+    fn test_replace_savepoint() {
+        let mut manager = console_manager();
+        for name in ["a", "b", "A"] {
+            manager.replace_savepoint(name);
+        }
+        assert_eq!(savepoint_names(&manager), vec!["b", "A"]);
+
+        manager.rollback_savepoints_to("b");
+        assert_eq!(savepoint_names(&manager), vec!["b"]);
+    }
+
+    #[test]
+    fn test_forget_cursors_after() {
+        fn open_cursor(manager: &mut ConsoleManager<()>, id: &str) {
+            manager
+                .cursors
+                .insert(id.to_string(), SQLCursor::new((), Vec::new(), 10));
+        }
+
+        let mut manager = console_manager();
+        open_cursor(&mut manager, "a");
+        manager.push_savepoint("s");
+        open_cursor(&mut manager, "b");
+        manager.push_savepoint("t");
+        open_cursor(&mut manager, "c");
+
+        manager.forget_cursors_after("t");
+        let mut ids: Vec<_> = manager.cursors.keys().cloned().collect();
+        ids.sort();
+        assert_eq!(ids, vec!["a", "b"]);
+
+        manager.forget_cursors_after("s");
+        assert_eq!(manager.cursors.keys().collect::<Vec<_>>(), vec!["a"]);
+    }
+
+    #[test]
+    fn test_track_result() {
+        let mut manager = console_manager();
+        manager.track_result(StatementKind::Ddl, &SQLResultSet::new(0, None, None));
+        manager.track_result(
+            StatementKind::Query,
+            &SQLResultSet::new(0, Some(Vec::new()), Some(Vec::new())),
+        );
+        assert!(!manager.uncommitted());
+
+        manager.track_result(StatementKind::Dml, &SQLResultSet::new(3, None, None));
+        let status = manager.transaction_status();
+        assert!(manager.uncommitted());
+        assert_eq!(status.dml_count, 1);
+        assert_eq!(status.rows_affected, 3);
+    }
+
+    #[test]
+    fn test_track_result_other() {
+        let mut manager = console_manager();
+        let rows = vec![vec![Value::from(1)], vec![Value::from(2)]];
+        manager.track_result(
+            StatementKind::Other,
+            &SQLResultSet::new(2, Some(vec!["id".to_string()]), Some(rows)),
+        );
+        // A SET classified as other affects no rows.
+        manager.track_result(StatementKind::Other, &SQLResultSet::new(0, None, None));
+        assert!(!manager.uncommitted());
+        assert_eq!(manager.transaction_status().rows_affected, 0);
+
+        manager.track_result(StatementKind::Other, &SQLResultSet::new(2, None, None));
+        manager.track_result(StatementKind::Plsql, &SQLResultSet::new(1, None, None));
+        let status = manager.transaction_status();
+        assert_eq!(status.dml_count, 2);
+        assert_eq!(status.rows_affected, 3);
+    }
+}
@@ -10,10 +10,10 @@ use serde_json::{json, Value};
 
 use crate::proxies::sql_common::{process_statement_params, process_statement_schema};
 
-pub fn process_statement(statement: &str, schema: &str) -> Result<String> {
+pub fn process_statement(statement: &str, schema: &str, placeholders: &[&str]) -> Result<String> {
     let result = process_statement_params(statement, ":");
 
-    Ok(process_statement_schema(&result, schema))
+    Ok(process_statement_schema(&result, schema, placeholders))
 }
 
 pub fn get_row_values(row: &Row, columns: &[ColumnInfo]) -> Result<Vec<Value>> {
@@ -31,8 +31,15 @@ where
 {
     let json_val = match sql_type {
         OracleType::Number(_, _) => {
-            let val: Option<i64> = row.get(idx)?;
-            json!(val)
+            // Read as text so the decimals aren't lost, the integers stay JSON numbers.
+            let val: Option<String> = row.get(idx)?;
+            match val {
+                Some(text) => match text.parse::<i64>() {
+                    Ok(int) => json!(int),
+                    Err(_) => json!(text),
+                },
+                None => Value::Null,
+            }
         }
         OracleType::Float(_) => {
             let val: Option<f64> = row.get(idx)?;
@@ -1,3 +1,4 @@
+use std::error::Error;
 use std::fmt::Display;
 
 use anyhow::{anyhow, Result};
@@ -7,12 +8,15 @@ use chrono::NaiveDate;
 use chrono::NaiveDateTime;
 use chrono::NaiveTime;
 use regex::Regex;
-use serde_json::{json, Value};
-use tokio_postgres::types::Type;
+use serde_json::{json, Map, Value};
+use tokio_postgres::types::{FromSql, Kind, Type};
 use tokio_postgres::Row;
 use tokio_postgres::{row::RowIndex, Column};
 use uuid::Uuid;
 
+use crate::core::postgres_types::{
+    composite_fields, PgEnum, PgInet, PgInterval, PgMoney, PgNumeric,
+};
 use crate::proxies::sql_common::{process_statement_params, process_statement_schema};
 
 static ARRAY_PATTERN: &str = r"(?i)(array\t*\[\t*\?\t*\](?:\t*::\t*anaconda\.[a-z]+)?)";
@@ -22,11 +26,11 @@ pub fn process_statement_array(statement: &str) -> Result<String> {
     Ok(re.replace_all(statement, "?").to_string())
 }
 
-pub fn process_statement(statement: &str, schema: &str) -> Result<String> {
+pub fn process_statement(statement: &str, schema: &str, placeholders: &[&str]) -> Result<String> {
     let mut result = process_statement_array(statement)?;
     result = process_statement_params(&result, "$");
 
-    Ok(process_statement_schema(&result, schema))
+    Ok(process_statement_schema(&result, schema, placeholders))
 }
 
 pub fn get_row_values(row: &Row, columns: &[Column]) -> Result<Vec<Value>> {
@@ -101,8 +105,8 @@ where
                     None => cell_val = json!(Option::<String>::None),
                 };
             } else {
-                let val: Option<String> = row.try_get(idx)?;
-                cell_val = json!(val)
+                let val: Option<PgCell> = row.try_get(idx)?;
+                cell_val = val.map_or(Value::Null, |cell| cell.0);
             }
         }
         tokio_postgres::types::Kind::Array(element_type) => {
@@ -180,10 +184,14 @@ where
                 });
                 cell_val = json!(mapped_val);
             } else {
-                let val: Option<Vec<Option<String>>> = row.try_get(idx)?;
-                cell_val = json!(val)
+                let val: Option<PgCell> = row.try_get(idx)?;
+                cell_val = val.map_or(Value::Null, |cell| cell.0);
             }
         }
+        tokio_postgres::types::Kind::Enum(_) | tokio_postgres::types::Kind::Composite(_) => {
+            let val: Option<PgCell> = row.try_get(idx)?;
+            cell_val = val.map_or(Value::Null, |cell| cell.0);
+        }
         _ => {
             return Err(anyhow!(
                 "convert from sql error: not support sql type: {}",
@@ -194,3 +202,207 @@ where
 
     Ok(cell_val)
 }
+
+/// A cell decoded from its raw value by its type, for the types which have no Rust type of their
+/// own, e.g. NUMERIC, the enums and the composites.
+struct PgCell(Value);
+
+impl<'a> FromSql<'a> for PgCell {
+    fn from_sql(sql_type: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        decode_cell(sql_type, raw).map(PgCell)
+    }
+
+    fn accepts(_: &Type) -> bool {
+        true
+    }
+}
+
+fn decode_cell(sql_type: &Type, raw: &[u8]) -> Result<Value, Box<dyn Error + Sync + Send>> {
+    let cell_val = match sql_type.kind() {
+        Kind::Enum(_) => json!(PgEnum::from_sql(sql_type, raw)?.0),
+        Kind::Composite(fields) => {
+            let mut obj = Map::with_capacity(fields.len());
+            for (field, field_raw) in fields.iter().zip(composite_fields(raw)?) {
+                let field_val = match field_raw {
+                    Some(field_raw) => decode_cell(field.type_(), field_raw)?,
+                    None => Value::Null,
+                };
+                obj.insert(field.name().to_string(), field_val);
+            }
+            Value::Object(obj)
+        }
+        Kind::Array(_) => {
+            let val: Vec<Option<PgCell>> = FromSql::from_sql(sql_type, raw)?;
+            Value::Array(
+                val.into_iter()
+                    .map(|cell| cell.map_or(Value::Null, |cell| cell.0))
+                    .collect(),
+            )
+        }
+        _ => decode_simple_cell(sql_type, raw)?,
+    };
+
+    Ok(cell_val)
+}
+
+fn decode_simple_cell(sql_type: &Type, raw: &[u8]) -> Result<Value, Box<dyn Error + Sync + Send>> {
+    let cell_val = if *sql_type == Type::BOOL {
+        json!(bool::from_sql(sql_type, raw)?)
+    } else if *sql_type == Type::CHAR {
+        json!(i8::from_sql(sql_type, raw)?)
+    } else if *sql_type == Type::INT2 {
+        json!(i16::from_sql(sql_type, raw)?)
+    } else if *sql_type == Type::INT4 {
+        json!(i32::from_sql(sql_type, raw)?)
+    } else if *sql_type == Type::INT8 {
+        json!(i64::from_sql(sql_type, raw)?)
+    } else if *sql_type == Type::FLOAT4 {
+        json!(f32::from_sql(sql_type, raw)?)
+    } else if *sql_type == Type::FLOAT8 {
+        json!(f64::from_sql(sql_type, raw)?)
+    } else if *sql_type == Type::NUMERIC {
+        // Always the decimal text, the diff matches it with the Oracle NUMBER by its value.
+        json!(PgNumeric::from_sql(sql_type, raw)?.0)
+    } else if *sql_type == Type::MONEY {
+        json!(PgMoney::from_sql(sql_type, raw)?.to_string())
+    } else if *sql_type == Type::JSON || *sql_type == Type::JSONB {
+        Value::from_sql(sql_type, raw)?
+    } else if *sql_type == Type::INTERVAL {
+        json!(PgInterval::from_sql(sql_type, raw)?.to_string())
+    } else if *sql_type == Type::INET || *sql_type == Type::CIDR {
+        json!(PgInet::from_sql(sql_type, raw)?.to_text(*sql_type == Type::CIDR))
+    } else if *sql_type == Type::BYTEA {
+        json!(Vec::<u8>::from_sql(sql_type, raw)?)
+    } else if *sql_type == Type::TIMESTAMP {
+        json!(NaiveDateTime::from_sql(sql_type, raw)?.to_string())
+    } else if *sql_type == Type::TIMESTAMPTZ {
+        json!(DateTime::<Local>::from_sql(sql_type, raw)?.to_string())
+    } else if *sql_type == Type::DATE {
+        json!(NaiveDate::from_sql(sql_type, raw)?.to_string())
+    } else if *sql_type == Type::TIME {
+        json!(NaiveTime::from_sql(sql_type, raw)?.to_string())
+    } else if *sql_type == Type::UUID {
+        json!(Uuid::from_sql(sql_type, raw)?.to_string())
+    } else if <&str as FromSql>::accepts(sql_type) {
+        json!(<&str as FromSql>::from_sql(sql_type, raw)?)
+    } else {
+        return Err(format!("convert from sql error: not support sql type: {}", sql_type).into());
+    };
+
+    Ok(cell_val)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use tokio_postgres::types::{Field, IsNull};
+
+    use super::*;
+    use crate::core::postgres_param_mapper::map_to_sql;
+
+    fn round_trip(param: Value, sql_type: &Type) -> Value {
+        let mapped = map_to_sql(&param, sql_type).unwrap();
+        let mut buf = BytesMut::new();
+        match mapped.to_sql_checked(sql_type, &mut buf).unwrap() {
+            IsNull::Yes => Value::Null,
+            IsNull::No => decode_cell(sql_type, &buf).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_numeric_round_trip() {
+        for text in [
+            "1.50",
+            "-0.000123",
+            "12345678901234567890.0001",
+            "0.0",
+            "NaN",
+        ] {
+            assert_eq!(round_trip(json!(text), &Type::NUMERIC), json!(text));
+        }
+        assert_eq!(round_trip(json!(42), &Type::NUMERIC), json!("42"));
+        assert_eq!(round_trip(json!("1e3"), &Type::NUMERIC), json!("1000"));
+        assert_eq!(round_trip(Value::Null, &Type::NUMERIC), Value::Null);
+        assert_eq!(
+            round_trip(json!(["1.5", null]), &Type::NUMERIC_ARRAY),
+            json!(["1.5", null])
+        );
+        assert!(map_to_sql(&json!("1.2.3"), &Type::NUMERIC).is_err());
+        assert!(map_to_sql(&json!("1e-9999999999"), &Type::NUMERIC).is_err());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let obj = json!({"a": [1, "b"], "c": null});
+        assert_eq!(round_trip(obj.clone(), &Type::JSONB), obj);
+        assert_eq!(
+            round_trip(json!(r#"{"a": 1}"#), &Type::JSON),
+            json!({"a": 1})
+        );
+        assert_eq!(round_trip(json!("text"), &Type::JSONB), json!("text"));
+    }
+
+    #[test]
+    fn test_interval_money_and_inet_round_trip() {
+        let interval = "1 year 2 mons 3 days 04:05:06.5";
+        assert_eq!(
+            round_trip(json!(interval), &Type::INTERVAL),
+            json!(interval)
+        );
+        assert_eq!(
+            round_trip(json!("90 minutes"), &Type::INTERVAL),
+            json!("01:30:00")
+        );
+        assert_eq!(
+            round_trip(json!(-1.5), &Type::INTERVAL),
+            json!("-00:00:01.5")
+        );
+
+        assert_eq!(
+            round_trip(json!("$1,234.5"), &Type::MONEY),
+            json!("1234.50")
+        );
+        assert_eq!(round_trip(json!(-0.07), &Type::MONEY), json!("-0.07"));
+        assert!(map_to_sql(&json!("1.234"), &Type::MONEY).is_err());
+
+        assert_eq!(
+            round_trip(json!("192.168.0.1"), &Type::INET),
+            json!("192.168.0.1")
+        );
+        assert_eq!(round_trip(json!("::1/64"), &Type::INET), json!("::1/64"));
+        assert_eq!(
+            round_trip(json!("10.0.0.0/8"), &Type::CIDR),
+            json!("10.0.0.0/8")
+        );
+    }
+
+    #[test]
+    fn test_enum_and_composite_round_trip() {
+        let mood = Type::new(
+            "mood".to_string(),
+            0,
+            Kind::Enum(vec!["happy".to_string(), "sad".to_string()]),
+            "public".to_string(),
+        );
+        assert_eq!(round_trip(json!("happy"), &mood), json!("happy"));
+
+        let pair = Type::new(
+            "pair".to_string(),
+            0,
+            Kind::Composite(vec![
+                Field::new("id".to_string(), Type::INT4),
+                Field::new("amount".to_string(), Type::NUMERIC),
+                Field::new("mood".to_string(), mood),
+            ]),
+            "public".to_string(),
+        );
+        assert_eq!(
+            round_trip(json!({"id": 1, "amount": "2.50"}), &pair),
+            json!({"id": 1, "amount": "2.50", "mood": null})
+        );
+        assert_eq!(
+            round_trip(json!([2, 3, "sad"]), &pair),
+            json!({"id": 2, "amount": "3", "mood": "sad"})
+        );
+    }
+}
@@ -18,13 +18,22 @@ const getApolloClient = async () => {
   return getClient(port);
 };
 
+/**
+ * The connection profile of the console of the db type.
+ */
+export const consoleProfile = (dbType: Dbtype) =>
+  dbType === Dbtype.Oracle ? "oracle" : "postgres";
+
 export const setAutocommit = async (dbType: Dbtype, dbAutocommit: boolean) => {
   const client = await getApolloClient();
 
   const res = await client.mutate<
     SetAutocommitMutation,
     SetAutocommitMutationVariables
-  >({ variables: { dbType, dbAutocommit }, mutation: SetAutocommitDocument });
+  >({
+    variables: { profile: consoleProfile(dbType), dbAutocommit },
+    mutation: SetAutocommitDocument,
+  });
 
   return res.data;
 };
@@ -36,7 +45,11 @@ export const setConfig = async (dbType: Dbtype, dbConfig: Config) => {
     SetConfigMutation,
     SetConfigMutationVariables
   >({
-    variables: { dbType: dbType, dbConfig: dbConfig },
+    variables: {
+      profile: consoleProfile(dbType),
+      dbType: dbType,
+      dbConfig: dbConfig,
+    },
     mutation: SetConfigDocument,
   });
 
@@ -53,7 +66,13 @@ export const executeStmt = async (
   const client = await getApolloClient();
 
   const res = await client.query<ExecuteStmtQuery, ExecuteStmtQueryVariables>({
-    variables: { dbType, params, schema, stmt, withStat },
+    variables: {
+      profile: consoleProfile(dbType),
+      params,
+      schema,
+      stmt,
+      withStat,
+    },
     query: ExecuteStmtDocument,
   });
 
@@ -1,7 +1,9 @@
 import React, { useCallback, useContext } from "react";
+import { consoleProfile } from "../../apis/graphql/sql";
 import SearchBar from "../../components/SearchBar";
 import {
   AppStateKey,
+  Dbtype,
   useSearchJavaPropsMutation,
 } from "../../generated/graphql";
 import { useAppState } from "../../hooks/useAppState";
@@ -27,6 +29,8 @@ const PropsSearchView = React.memo(() => {
           filepath: filePath,
           classPattern: fileName,
           validateQueries: true,
+          oracleProfile: consoleProfile(Dbtype.Oracle),
+          postgresProfile: consoleProfile(Dbtype.Postgres),
         },
       });
       if (!data) {
@@ -9,6 +9,7 @@ import {
   Dbtype,
   useSetConfigMutation,
 } from "../../generated/graphql";
+import { consoleProfile } from "../../apis/graphql/sql";
 import { useAppState } from "../../hooks/useAppState";
 import {} from "./settingsSlice";
 
@@ -27,7 +28,7 @@ const SettingsPanel = React.memo(({ active }: any) => {
       port: "1521",
       db: "anaconda",
       username: "anaconda",
-      password: "anaconda",
+      password: "",
     }
   );
   const [pgConfig, setPgConfig] = useAppState<Config>(
@@ -37,7 +38,7 @@ const SettingsPanel = React.memo(({ active }: any) => {
       port: "5432",
       db: "planning",
       username: "postgres",
-      password: "#postgres#",
+      password: "",
     }
   );
   const [setConfigMutation, { data, loading }] = useSetConfigMutation();
@@ -47,7 +48,11 @@ const SettingsPanel = React.memo(({ active }: any) => {
       return;
     }
     setConfigMutation({
-      variables: { dbType: Dbtype.Oracle, dbConfig: oracleConfig },
+      variables: {
+        profile: consoleProfile(Dbtype.Oracle),
+        dbType: Dbtype.Oracle,
+        dbConfig: oracleConfig,
+      },
     })
       .then(() =>
         snackBar.enqueueSnackbar(`Successfully connected to the Oracle.`, {
@@ -66,7 +71,11 @@ const SettingsPanel = React.memo(({ active }: any) => {
       return;
     }
     setConfigMutation({
-      variables: { dbType: Dbtype.Postgres, dbConfig: pgConfig },
+      variables: {
+        profile: consoleProfile(Dbtype.Postgres),
+        dbType: Dbtype.Postgres,
+        dbConfig: pgConfig,
+      },
     })
       .then(() =>
         snackBar.enqueueSnackbar(`Successfully connected to the Postgres.`, {
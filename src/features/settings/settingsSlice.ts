@@ -19,7 +19,7 @@ const DEFAULT_ORACLE_SETTINGS = {
   port: "1521",
   sid: "anaconda",
   user: "anaconda",
-  password: "anaconda",
+  password: "",
 };
 
 export interface PostgreSettings {
@@ -35,7 +35,7 @@ const DEFAULT_POSTGRE_SETTINGS = {
   port: "5432",
   dbname: "planning",
   user: "postgres",
-  password: "#postgres#",
+  password: "",
 };
 
 const initialState: settingsState = {
@@ -6,6 +6,7 @@ import SettingsBackupRestoreIcon from "@material-ui/icons/SettingsBackupRestore"
 import clsx from "clsx";
 import React, { useCallback, useContext, useEffect } from "react";
 import { useDispatch, useSelector } from "react-redux";
+import { consoleProfile } from "../../apis/graphql/sql";
 import { GlobalContext } from "../../App";
 import ProcessIconButton from "../../components/ProgressIconButton";
 import SVGIcon from "../../components/SVGIcon";
@@ -72,7 +73,7 @@ const TransactionControlToolBar = React.memo(
         try {
           await setDbAutocommit({
             variables: {
-              dbType: Dbtype.Oracle,
+              profile: consoleProfile(Dbtype.Oracle),
               dbAutocommit: autocommit,
             },
           });
@@ -80,7 +81,7 @@ const TransactionControlToolBar = React.memo(
         try {
           await setDbAutocommit({
             variables: {
-              dbType: Dbtype.Postgres,
+              profile: consoleProfile(Dbtype.Postgres),
               dbAutocommit: autocommit,
             },
           });
@@ -105,10 +106,14 @@ const TransactionControlToolBar = React.memo(
 
     const handleClickCommit = React.useCallback(async () => {
       try {
-        await commitConsole({ variables: { dbType: Dbtype.Oracle } });
+        await commitConsole({
+          variables: { profile: consoleProfile(Dbtype.Oracle) },
+        });
       } catch (e) {}
       try {
-        await commitConsole({ variables: { dbType: Dbtype.Postgres } });
+        await commitConsole({
+          variables: { profile: consoleProfile(Dbtype.Postgres) },
+        });
       } catch (e) {}
 
       dispatch(changeUncommitCount(0));
@@ -116,10 +121,14 @@ const TransactionControlToolBar = React.memo(
 
     const handleClickRollback = React.useCallback(async () => {
       try {
-        await rollbackConsole({ variables: { dbType: Dbtype.Oracle } });
+        await rollbackConsole({
+          variables: { profile: consoleProfile(Dbtype.Oracle) },
+        });
       } catch (e) {}
       try {
-        await rollbackConsole({ variables: { dbType: Dbtype.Postgres } });
+        await rollbackConsole({
+          variables: { profile: consoleProfile(Dbtype.Postgres) },
+        });
       } catch (e) {}
 
       dispatch(changeUncommitCount(0));